@@ -3,5 +3,49 @@ document.addEventListener('DOMContentLoaded', () => {
   window.jQuery = $;
   window.$ = $;
   require('jquery-ujs');
+
+  // file inputs with a data-upload-url are uploaded as soon as a file is
+  // picked, rather than with the rest of their form
+  $(document).on('change', 'input[type=file][data-upload-url]', (e) => {
+    var input = e.currentTarget;
+    var body = new FormData();
+    body.append('file', input.files[0]);
+    $.ajax({
+      url: $(input).data('upload-url'),
+      method: 'POST',
+      data: body,
+      processData: false,
+      contentType: false,
+    }).done((attachment) => {
+      $($(input).data('upload-preview'))
+        .attr('src', attachment.url)
+        .attr('alt', attachment.name)
+        .removeClass('d-none');
+    }).fail((xhr) => {
+      alert('Upload failed: ' + xhr.statusText);
+    });
+  });
+
+  // countdowns to a jam's next milestone keep counting down after the page
+  // loads, in the same "1d 2h 3m" shape the server renders them in
+  var updateCountdowns = () => {
+    $('[data-countdown-until]').each((_, element) => {
+      var until = Date.parse($(element).data('countdown-until'));
+      var minutes = Math.max(0, Math.floor((until - Date.now()) / 60000));
+      var days = Math.floor(minutes / (24 * 60));
+      var hours = Math.floor(minutes / 60) % 24;
+      var parts = [minutes % 60 + 'm'];
+      if (days > 0 || hours > 0) {
+        parts.unshift(hours + 'h');
+      }
+      if (days > 0) {
+        parts.unshift(days + 'd');
+      }
+      $(element).text(parts.join(' '));
+    });
+  };
+  updateCountdowns();
+  setInterval(updateCountdowns, 30000);
+
   console.log('Initialized app');
 });
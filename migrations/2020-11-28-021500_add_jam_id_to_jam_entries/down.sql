@@ -0,0 +1,13 @@
+CREATE TABLE jam_entries_old(
+    id INTEGER PRIMARY KEY NOT NULL,
+    submitter_user_id BIGINT NOT NULL,
+    approval_state INTEGER CHECK(approval_state IN (0, 1, 2, 5, 8)) NOT NULL DEFAULT 0,
+    title TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    summary_attachment_id INTEGER NOT NULL,
+    rich_text_id INTEGER NOT NULL
+);
+
+DROP TABLE jam_entries;
+ALTER TABLE jam_entries_old RENAME TO jam_entries;
@@ -0,0 +1,32 @@
+-- jam entries were created without saying which jam they belong to, and with a
+-- mandatory summary image that an entry can't have until someone uploads one.
+-- sqlite can't add constraints or loosen columns in place, so the table is
+-- rebuilt. while we're here the approval state check gets the values the
+-- application actually writes, "approved" (4) having been left out before.
+CREATE TABLE jam_entries_new(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- the jam this entry was made for
+    jam_id INTEGER NOT NULL,
+    -- who submitted this entry
+    submitter_user_id BIGINT NOT NULL,
+    -- the approval state, one of "draft" (0), "submitted" (2), "approved" (4),
+    -- or "rejected" (8). the approval system is there to prevent malicious
+    -- actors from spamming the site.
+    approval_state INTEGER CHECK(approval_state IN (0, 2, 4, 8)) NOT NULL DEFAULT 0,
+    -- the title of this jam entry
+    title TEXT NOT NULL,
+    -- the slug of this jam entry, which is part of the url and makes it easy
+    -- for users to see what they're going to read when passing around the url.
+    slug TEXT NOT NULL,
+    -- a summary text which can appear in a list of jame entries.
+    summary TEXT NOT NULL,
+    -- summary image, which is an attachment.
+    summary_attachment_id INTEGER,
+    -- the text of this entry
+    rich_text_id INTEGER NOT NULL
+);
+
+-- there's nothing that could have written entries yet, so there's no jam to
+-- sensibly assign existing rows to. they're dropped rather than orphaned.
+DROP TABLE jam_entries;
+ALTER TABLE jam_entries_new RENAME TO jam_entries;
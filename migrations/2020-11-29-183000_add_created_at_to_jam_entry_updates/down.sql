@@ -0,0 +1,13 @@
+CREATE TABLE jam_entry_updates_old(
+    id INTEGER PRIMARY KEY NOT NULL,
+    jam_entry_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    rich_text_id INTEGER,
+    external_content_url TEXT,
+    approval_state INTEGER CHECK(approval_state IN (0, 1, 2, 5, 8)) NOT NULL DEFAULT 0
+);
+
+DROP TABLE jam_entry_updates;
+ALTER TABLE jam_entry_updates_old RENAME TO jam_entry_updates;
@@ -0,0 +1,33 @@
+-- updates form a devlog, so they need to know when they were posted in order
+-- to be shown in order. sqlite can't add a column with a non-constant default,
+-- so the table is rebuilt. the approval state check also gets the values the
+-- application actually writes, "approved" (4) having been left out before.
+CREATE TABLE jam_entry_updates_new(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- the jam entry this is attached to
+    jam_entry_id INTEGER NOT NULL,
+    -- the title of this update
+    title TEXT NOT NULL,
+    -- the slug of this update, which is part of the url and makes it easy for
+    -- users to see what they're going to read when passing around the url.
+    slug TEXT NOT NULL,
+    -- a summary text which can appear in a list of updates.
+    summary TEXT NOT NULL,
+    -- the rich text content this applies to.
+    -- should not be present if external_content_url is present.
+    rich_text_id INTEGER,
+    -- the external content link this applies to.
+    -- should not be present if rich_text_id is present.
+    external_content_url TEXT,
+    -- the approval state, one of "draft" (0), "submitted" (2), "approved" (4),
+    -- or "rejected" (8). the approval system is there to prevent malicious
+    -- actors from spamming the site.
+    approval_state INTEGER CHECK(approval_state IN (0, 2, 4, 8)) NOT NULL DEFAULT 0,
+    -- when this update was first posted, stored as text in ISO-8601.
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK((rich_text_id IS NULL) != (external_content_url IS NULL))
+);
+
+-- nothing could have written updates yet, so there's nothing to carry over.
+DROP TABLE jam_entry_updates;
+ALTER TABLE jam_entry_updates_new RENAME TO jam_entry_updates;
@@ -0,0 +1,13 @@
+CREATE TABLE attachments_old(
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    published BOOLEAN NOT NULL DEFAULT TRUE,
+    mime_type TEXT NOT NULL,
+    md5 BLOB NOT NULL
+);
+
+INSERT INTO attachments_old (id, name, published, mime_type, md5)
+    SELECT id, name, published, mime_type, md5 FROM attachments;
+
+DROP TABLE attachments;
+ALTER TABLE attachments_old RENAME TO attachments;
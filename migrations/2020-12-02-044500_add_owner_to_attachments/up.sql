@@ -0,0 +1,3 @@
+-- who uploaded an attachment, so that people can only reference and manage
+-- their own files. attachments uploaded before this was tracked have no owner.
+ALTER TABLE attachments ADD COLUMN owner_user_id BIGINT;
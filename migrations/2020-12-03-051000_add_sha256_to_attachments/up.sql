@@ -0,0 +1,4 @@
+-- the sha256 of the file, which is what we'd rather people check downloads
+-- against, since md5 is no longer any good against an attacker. attachments
+-- stored before this was added don't have one until they are rehashed.
+ALTER TABLE attachments ADD COLUMN sha256 BLOB;
@@ -0,0 +1,2 @@
+DROP INDEX rich_text_revisions_rich_text_id;
+DROP TABLE rich_text_revisions;
@@ -0,0 +1,22 @@
+-- every version of a rich text that has ever been saved, so that changes to
+-- something like a jam's rules after people have started working to them are
+-- on the record, and can be undone.
+CREATE TABLE rich_text_revisions(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- the rich text this is a version of
+    rich_text_id INTEGER NOT NULL REFERENCES rich_texts(id),
+    -- the content of the rich text as of this revision
+    content TEXT NOT NULL,
+    -- who saved this revision. the revisions recorded for content which
+    -- predates revisions have no author.
+    author_user_id BIGINT REFERENCES gh_user_records(id),
+    -- when this revision was saved
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX rich_text_revisions_rich_text_id
+    ON rich_text_revisions(rich_text_id);
+
+-- start each existing rich text's history with what it says now
+INSERT INTO rich_text_revisions (rich_text_id, content, author_user_id, created_at)
+    SELECT id, content, NULL, CURRENT_TIMESTAMP FROM rich_texts;
@@ -0,0 +1,2 @@
+DROP INDEX sessions_gh_user_id;
+DROP TABLE sessions;
@@ -0,0 +1,17 @@
+-- a login from one browser. the browser holds a random token in a private
+-- cookie, and only the sha-256 of that token is kept here, so that this table
+-- can't be used to log in as anyone. deleting a session logs that browser out.
+CREATE TABLE sessions(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- who is logged in
+    gh_user_id BIGINT NOT NULL REFERENCES gh_user_records(id),
+    -- sha-256 of the token in the browser's cookie
+    token_sha256 BLOB NOT NULL UNIQUE,
+    -- when the user logged in
+    created_at TIMESTAMP NOT NULL,
+    -- when the session was last used, give or take a few minutes. sessions
+    -- which go unused for long enough expire.
+    last_seen_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX sessions_gh_user_id ON sessions(gh_user_id);
@@ -0,0 +1,11 @@
+CREATE TABLE permissions_old(
+    id INTEGER PRIMARY KEY NOT NULL,
+    gh_user_id BIGINT NOT NULL,
+    name TEXT NOT NULL
+);
+
+INSERT INTO permissions_old (id, gh_user_id, name)
+    SELECT id, gh_user_id, name FROM permissions;
+
+DROP TABLE permissions;
+ALTER TABLE permissions_old RENAME TO permissions;
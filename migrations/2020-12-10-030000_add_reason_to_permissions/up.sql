@@ -0,0 +1,19 @@
+-- permissions now remember why and when they were granted, which matters most
+-- for bans, where the banned user is shown both. sqlite can't add a column
+-- with a non-constant default, so the table is rebuilt. permissions granted
+-- before this are taken to have been granted now, having no better idea.
+CREATE TABLE permissions_new(
+    id INTEGER PRIMARY KEY NOT NULL,
+    gh_user_id BIGINT NOT NULL,
+    name TEXT NOT NULL,
+    -- why the permission was granted, if anyone said.
+    reason TEXT,
+    -- when the permission was granted, stored as text in ISO-8601.
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO permissions_new (id, gh_user_id, name)
+    SELECT id, gh_user_id, name FROM permissions;
+
+DROP TABLE permissions;
+ALTER TABLE permissions_new RENAME TO permissions;
@@ -0,0 +1,5 @@
+-- when a moderator rejects something they say why, so that whoever wrote it
+-- can fix it and submit it again. only meaningful while rejected.
+ALTER TABLE jams ADD COLUMN rejection_reason TEXT;
+ALTER TABLE jam_entries ADD COLUMN rejection_reason TEXT;
+ALTER TABLE jam_entry_updates ADD COLUMN rejection_reason TEXT;
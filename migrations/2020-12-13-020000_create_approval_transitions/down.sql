@@ -0,0 +1,23 @@
+DROP TABLE approval_transitions;
+
+CREATE TABLE jams_old(
+    id INTEGER PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    summary_attachment_id INTEGER,
+    rich_text_id INTEGER NOT NULL,
+    start_date TIMESTAMP NOT NULL,
+    end_date TIMESTAMP NOT NULL,
+    approval_state INTEGER CHECK(approval_state IN (0, 1, 2, 4, 8)) NOT NULL DEFAULT 0,
+    rejection_reason TEXT
+);
+
+INSERT INTO jams_old (id, title, slug, summary, summary_attachment_id,
+        rich_text_id, start_date, end_date, approval_state, rejection_reason)
+    SELECT id, title, slug, summary, summary_attachment_id, rich_text_id,
+        start_date, end_date, approval_state, rejection_reason
+    FROM jams;
+
+DROP TABLE jams;
+ALTER TABLE jams_old RENAME TO jams;
@@ -0,0 +1,58 @@
+-- every time a jam, entry or update moves between approval states, who moved
+-- it and why, so that what happened to something is on the record even after
+-- it has moved on. rows are written by the application and never changed.
+CREATE TABLE approval_transitions(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- what kind of thing moved, one of "jam", "jam_entry" or
+    -- "jam_entry_update". there's no foreign key, as the history outlives a
+    -- deleted update.
+    subject_kind TEXT CHECK(subject_kind IN ('jam', 'jam_entry', 'jam_entry_update')) NOT NULL,
+    -- the id of the thing which moved, in the table subject_kind names
+    subject_id INTEGER NOT NULL,
+    -- the approval state it moved from, with the same values as the content
+    from_state INTEGER CHECK(from_state IN (0, 2, 4, 8)) NOT NULL,
+    -- the approval state it moved to
+    to_state INTEGER CHECK(to_state IN (0, 2, 4, 8)) NOT NULL,
+    -- who moved it. forgotten when that user is deleted.
+    actor_user_id BIGINT REFERENCES gh_user_records(id),
+    -- why, which is given when a moderator rejects something
+    reason TEXT,
+    -- when it moved
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX approval_transitions_subject
+    ON approval_transitions(subject_kind, subject_id);
+
+-- the approval state checks have to agree with the values above. the entries
+-- and updates tables already do: their checks were corrected to (0, 2, 4, 8)
+-- when 2020-11-28-021500_add_jam_id_to_jam_entries and
+-- 2020-11-29-183000_add_created_at_to_jam_entry_updates rebuilt them, so they
+-- aren't touched here. the jams check still lets through 1, which nothing can
+-- read back, so only the jams table is rebuilt. anything stuck in an
+-- unreadable state goes back to being a draft.
+CREATE TABLE jams_new(
+    id INTEGER PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    summary_attachment_id INTEGER,
+    rich_text_id INTEGER NOT NULL,
+    start_date TIMESTAMP NOT NULL,
+    end_date TIMESTAMP NOT NULL,
+    -- the approval state, one of "draft" (0), "submitted" (2), "approved" (4),
+    -- or "rejected" (8).
+    approval_state INTEGER CHECK(approval_state IN (0, 2, 4, 8)) NOT NULL DEFAULT 0,
+    rejection_reason TEXT
+);
+
+INSERT INTO jams_new (id, title, slug, summary, summary_attachment_id,
+        rich_text_id, start_date, end_date, approval_state, rejection_reason)
+    SELECT id, title, slug, summary, summary_attachment_id, rich_text_id,
+        start_date, end_date,
+        CASE WHEN approval_state IN (0, 2, 4, 8) THEN approval_state ELSE 0 END,
+        rejection_reason
+    FROM jams;
+
+DROP TABLE jams;
+ALTER TABLE jams_new RENAME TO jams;
@@ -0,0 +1,3 @@
+-- when voting on a jam's entries closes and the results are in. voting opens
+-- as soon as the jam ends. jams without one have no voting at all.
+ALTER TABLE jams ADD COLUMN voting_end_date TIMESTAMP;
@@ -0,0 +1,5 @@
+-- the timezone the jam's organizer thinks in, as an IANA name such as
+-- "America/Los_Angeles". the jam's dates are instants in UTC, and this is only
+-- used to read and write them the way the organizer would say them. jams made
+-- before this were all midnight UTC anyway.
+ALTER TABLE jams ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC';
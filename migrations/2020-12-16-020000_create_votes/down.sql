@@ -0,0 +1,3 @@
+DROP INDEX jam_entry_votes_voter_user_id;
+DROP TABLE jam_entry_votes;
+DROP TABLE vote_categories;
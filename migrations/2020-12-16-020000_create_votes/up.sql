@@ -0,0 +1,38 @@
+-- what entries are rated on once a jam's voting opens. every jam uses the same
+-- categories, which are listed in the order they're shown.
+CREATE TABLE vote_categories(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- the short name shown beside the ratings, such as "Gameplay"
+    name TEXT NOT NULL UNIQUE,
+    -- a sentence on what voters should consider
+    description TEXT NOT NULL,
+    -- where the category is shown, lowest first
+    position INTEGER NOT NULL
+);
+
+INSERT INTO vote_categories (name, description, position) VALUES
+    ('Gameplay', 'How much fun is it to play?', 0),
+    ('Graphics', 'How good does it look?', 1),
+    ('Audio', 'How good are the music and sound effects?', 2),
+    ('Theme', 'How well does it fit the theme of the jam?', 3);
+
+-- one user's rating of one entry in one category. a voter may change their
+-- rating for as long as voting is open, which updates the row in place.
+CREATE TABLE jam_entry_votes(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- the entry being rated
+    jam_entry_id INTEGER NOT NULL REFERENCES jam_entries(id),
+    -- who is rating it
+    voter_user_id BIGINT NOT NULL REFERENCES gh_user_records(id),
+    -- what it's being rated on
+    vote_category_id INTEGER NOT NULL REFERENCES vote_categories(id),
+    -- from one star to five
+    rating INTEGER CHECK(rating BETWEEN 1 AND 5) NOT NULL,
+    -- when the vote was first cast
+    created_at TIMESTAMP NOT NULL,
+    -- when the vote was last changed
+    updated_at TIMESTAMP NOT NULL,
+    UNIQUE(jam_entry_id, voter_user_id, vote_category_id)
+);
+
+CREATE INDEX jam_entry_votes_voter_user_id ON jam_entry_votes(voter_user_id);
@@ -0,0 +1,265 @@
+use crate::attachments::{
+    AttachmentStorageError, Inventory, StorageBackend, StoredBody,
+};
+use rocket::tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use std::{
+    io::{ErrorKind, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+/// How attachments are arranged within a [`DirectoryBackend`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DirectoryLayout {
+    /// Every attachment sits directly in the directory, named by its id. This
+    /// is how attachments have always been stored.
+    Flat,
+
+    /// Attachments are spread over 256 subdirectories, named by the last two
+    /// hex digits of the attachment's id, so that no one directory holds so
+    /// many files that it gets slow to work with.
+    Sharded,
+}
+
+/// Stores attachments as files in a directory on the local filesystem.
+pub struct DirectoryBackend {
+    layout: DirectoryLayout,
+    root: PathBuf,
+}
+
+impl DirectoryBackend {
+    pub fn new(layout: DirectoryLayout, root: PathBuf) -> Self {
+        DirectoryBackend { layout, root }
+    }
+
+    /// Where the attachment with the given id lives, whether or not it is
+    /// actually there.
+    fn path_for(&self, attachment_id: i32) -> PathBuf {
+        let mut path = self.root.clone();
+
+        if self.layout == DirectoryLayout::Sharded {
+            path.push(shard_for(attachment_id));
+        }
+
+        path.push(attachment_id.to_string());
+        path
+    }
+
+    /// The path at which an attachment is stored, if it is there at all.
+    async fn existing_path_for(
+        &self,
+        attachment_id: i32,
+    ) -> Result<PathBuf, AttachmentStorageError> {
+        let path = self.path_for(attachment_id);
+
+        match fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => Ok(path),
+            Ok(_) => Err(not_found(&path)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(not_found(&path)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Sorts the files in one directory into attachments and strays,
+    /// returning any shards found along the way. In a sharded layout, only an
+    /// attachment in the right shard counts as one.
+    async fn take_inventory_of(
+        &self,
+        directory: &Path,
+        shard: Option<&str>,
+        inventory: &mut Inventory,
+    ) -> Result<Vec<(PathBuf, String)>, AttachmentStorageError> {
+        let mut entries = fs::read_dir(directory).await?;
+        let mut shards = Vec::new();
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let file_name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default()
+                .to_string();
+            let is_file = fs::metadata(&path).await?.is_file();
+
+            if file_name.starts_with(".upload-") {
+                continue;
+            }
+
+            if self.layout == DirectoryLayout::Sharded
+                && shard.is_none()
+                && !is_file
+                && is_shard_name(&file_name)
+            {
+                shards.push((path, file_name));
+                continue;
+            }
+
+            let in_right_place = |id| match shard {
+                Some(shard) => shard == shard_for(id),
+                None => self.layout == DirectoryLayout::Flat,
+            };
+
+            match file_name.parse::<i32>() {
+                Ok(id)
+                    if is_file
+                        && id.to_string() == file_name
+                        && in_right_place(id) =>
+                {
+                    inventory.attachment_ids.push(id)
+                }
+                _ => inventory.unrecognized.push(path.display().to_string()),
+            }
+        }
+
+        Ok(shards)
+    }
+}
+
+#[rocket::async_trait]
+impl StorageBackend for DirectoryBackend {
+    async fn store(
+        &self,
+        file: &Path,
+        attachment_id: i32,
+    ) -> Result<(), AttachmentStorageError> {
+        let destination_path = self.path_for(attachment_id);
+
+        if let Some(parent) = destination_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        fs::rename(file, &destination_path).await?;
+
+        Ok(())
+    }
+
+    async fn size(
+        &self,
+        attachment_id: i32,
+    ) -> Result<u64, AttachmentStorageError> {
+        let path = self.existing_path_for(attachment_id).await?;
+
+        Ok(fs::metadata(path).await?.len())
+    }
+
+    async fn load(
+        &self,
+        attachment_id: i32,
+        start: u64,
+        length: u64,
+    ) -> Result<StoredBody, AttachmentStorageError> {
+        let path = self.existing_path_for(attachment_id).await?;
+        let mut file = File::open(path).await?;
+        file.seek(SeekFrom::Start(start)).await?;
+
+        Ok(Box::pin(file.take(length)))
+    }
+
+    async fn delete(
+        &self,
+        attachment_id: i32,
+    ) -> Result<(), AttachmentStorageError> {
+        let path = self.existing_path_for(attachment_id).await?;
+
+        Ok(fs::remove_file(path).await?)
+    }
+
+    async fn inventory(&self) -> Result<Inventory, AttachmentStorageError> {
+        let mut inventory = Inventory::default();
+
+        let shards = self
+            .take_inventory_of(&self.root, None, &mut inventory)
+            .await?;
+
+        for (path, shard) in shards {
+            self.take_inventory_of(&path, Some(&shard), &mut inventory)
+                .await?;
+        }
+
+        inventory.attachment_ids.sort_unstable();
+        inventory.unrecognized.sort();
+
+        Ok(inventory)
+    }
+}
+
+/// The subdirectory an attachment goes in, in a sharded layout.
+fn shard_for(attachment_id: i32) -> String {
+    format!("{:02x}", attachment_id & 0xff)
+}
+
+fn is_shard_name(name: &str) -> bool {
+    name.len() == 2
+        && name
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+fn not_found(path: &Path) -> AttachmentStorageError {
+    AttachmentStorageError::NotFound(path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::tokio::io::AsyncReadExt;
+
+    #[rocket::async_test]
+    async fn test_flat_inventory() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend =
+            DirectoryBackend::new(DirectoryLayout::Flat, dir.path().into());
+        for name in &["1", "12", "007", "notes.txt", ".upload-abc"] {
+            std::fs::write(dir.path().join(name), "x").unwrap();
+        }
+
+        let inventory = backend.inventory().await.unwrap();
+        assert_eq!(vec![1, 12], inventory.attachment_ids);
+        assert_eq!(
+            vec![
+                dir.path().join("007").display().to_string(),
+                dir.path().join("notes.txt").display().to_string()
+            ],
+            inventory.unrecognized
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_sharded_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend =
+            DirectoryBackend::new(DirectoryLayout::Sharded, dir.path().into());
+        let upload = dir.path().join(".upload-abc");
+        std::fs::write(&upload, "some content").unwrap();
+
+        backend.store(&upload, 258).await.unwrap();
+        assert!(dir.path().join("02").join("258").is_file());
+        assert_eq!(12, backend.size(258).await.unwrap());
+
+        let mut content = String::new();
+        backend
+            .load(258, 5, 3)
+            .await
+            .unwrap()
+            .read_to_string(&mut content)
+            .await
+            .unwrap();
+        assert_eq!("con", content);
+
+        // in the wrong shard, so not an attachment
+        std::fs::create_dir(dir.path().join("03")).unwrap();
+        std::fs::write(dir.path().join("03").join("4"), "x").unwrap();
+
+        let inventory = backend.inventory().await.unwrap();
+        assert_eq!(vec![258], inventory.attachment_ids);
+        assert_eq!(
+            vec![dir.path().join("03").join("4").display().to_string()],
+            inventory.unrecognized
+        );
+
+        backend.delete(258).await.unwrap();
+        assert!(backend.size(258).await.is_err());
+    }
+}
@@ -0,0 +1,417 @@
+mod directory;
+mod s3;
+
+pub use crate::attachments::{
+    directory::{DirectoryBackend, DirectoryLayout},
+    s3::{S3Backend, S3Config},
+};
+use rocket::tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    io::Error as IOError,
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+};
+use tempfile::NamedTempFile;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AttachmentStorageError {
+    #[error("IOError {0}")]
+    IoError(#[from] IOError),
+
+    #[error("File does not exist at {0}")]
+    NotFound(String),
+
+    #[error("HTTP Error {0}")]
+    HttpError(#[from] reqwest::Error),
+
+    #[error("The object store responded with {0} to {1}")]
+    ObjectStoreError(reqwest::StatusCode, String),
+}
+
+/// The content of a stored attachment, or some range of it, as it is read out
+/// of storage.
+pub type StoredBody = Pin<Box<dyn AsyncRead + Send>>;
+
+/// A place that attachments can be kept, keyed by their id. Backends only need
+/// to shuffle bytes around; working out what those bytes ought to be is the job
+/// of [`AttachmentStorage`].
+#[rocket::async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Stores the file with the given attachment id, replacing anything already
+    /// stored with that id. The original file is consumed.
+    async fn store(
+        &self,
+        file: &Path,
+        attachment_id: i32,
+    ) -> Result<(), AttachmentStorageError>;
+
+    /// The size in bytes of a stored attachment.
+    async fn size(
+        &self,
+        attachment_id: i32,
+    ) -> Result<u64, AttachmentStorageError>;
+
+    /// Reads `length` bytes of a stored attachment, beginning at `start`.
+    async fn load(
+        &self,
+        attachment_id: i32,
+        start: u64,
+        length: u64,
+    ) -> Result<StoredBody, AttachmentStorageError>;
+
+    /// Removes an attachment from storage.
+    async fn delete(
+        &self,
+        attachment_id: i32,
+    ) -> Result<(), AttachmentStorageError>;
+
+    /// Lists everything in storage, sorting out the attachments from whatever
+    /// else has found its way in there.
+    async fn inventory(&self) -> Result<Inventory, AttachmentStorageError>;
+}
+
+/// Where attachments are stored, as configured with
+/// `UDEVGAMES_ATTACHMENT_STORAGE`.
+///
+/// * `/some/path` or `flat:/some/path` stores each attachment in that
+///   directory, named by its id.
+/// * `sharded:/some/path` is the same, but spreads the attachments over 256
+///   subdirectories so no one directory gets enormous.
+/// * `s3:bucket` stores attachments as objects in an S3-compatible object
+///   store, which is configured separately.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StorageSpec {
+    Directory(DirectoryLayout, PathBuf),
+    S3(String),
+}
+
+#[derive(Debug, Error)]
+#[error("Could not understand attachment storage {0}")]
+pub struct StorageSpecParseError(String);
+
+impl FromStr for StorageSpec {
+    type Err = StorageSpecParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let spec = if let Some(path) = s.strip_prefix("flat:") {
+            StorageSpec::Directory(DirectoryLayout::Flat, PathBuf::from(path))
+        } else if let Some(path) = s.strip_prefix("sharded:") {
+            StorageSpec::Directory(
+                DirectoryLayout::Sharded,
+                PathBuf::from(path),
+            )
+        } else if let Some(bucket) = s.strip_prefix("s3:") {
+            StorageSpec::S3(bucket.to_string())
+        } else {
+            StorageSpec::Directory(DirectoryLayout::Flat, PathBuf::from(s))
+        };
+
+        match &spec {
+            StorageSpec::Directory(_, path) if path.as_os_str().is_empty() => {
+                Err(StorageSpecParseError(s.to_string()))
+            }
+            StorageSpec::S3(bucket) if bucket.is_empty() => {
+                Err(StorageSpecParseError(s.to_string()))
+            }
+            _ => Ok(spec),
+        }
+    }
+}
+
+impl StorageSpec {
+    /// Whether two specs keep attachments in the same place, so that copying
+    /// from one to the other would copy each attachment onto itself. A
+    /// directory is the same directory whatever its layout, and however its
+    /// path is written.
+    pub fn same_location(&self, other: &StorageSpec) -> bool {
+        match (self, other) {
+            (StorageSpec::Directory(_, a), StorageSpec::Directory(_, b)) => {
+                let canonical = |path: &PathBuf| {
+                    path.canonicalize().unwrap_or_else(|_| path.clone())
+                };
+                canonical(a) == canonical(b)
+            }
+            (StorageSpec::S3(a), StorageSpec::S3(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Hides the logic of placing files into storage and retrieving them again.
+pub struct AttachmentStorage {
+    /// Where the files are stored.
+    backend: Box<dyn StorageBackend>,
+    /// Where uploads are received before they are stored.
+    upload_path: PathBuf,
+}
+
+/// Everything that is sitting in attachment storage.
+#[derive(Debug, Default)]
+pub struct Inventory {
+    /// The ids of the attachments which have a file in storage.
+    pub attachment_ids: Vec<i32>,
+    /// Anything else in storage which isn't an attachment, excluding uploads
+    /// which are still being received.
+    pub unrecognized: Vec<String>,
+}
+
+/// The checksums of a stored file. Both are computed in a single pass over the
+/// file, since reading it is the expensive part.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Digests {
+    /// The md5sum, kept for compatibility with what we've always recorded.
+    pub md5: [u8; 16],
+    /// The sha256sum, which is what people should be verifying downloads with.
+    pub sha256: [u8; 32],
+}
+
+impl AttachmentStorage {
+    /// Creates a new attachment storage keeping files in the given backend,
+    /// and receiving uploads into the given path.
+    pub fn new(
+        backend: Box<dyn StorageBackend>,
+        upload_path: PathBuf,
+    ) -> AttachmentStorage {
+        AttachmentStorage {
+            backend,
+            upload_path,
+        }
+    }
+
+    /// Creates a new attachment storage keeping files in a local directory,
+    /// which uploads are also received into.
+    pub fn directory(layout: DirectoryLayout, path: PathBuf) -> Self {
+        AttachmentStorage::new(
+            Box::new(DirectoryBackend::new(layout, path.clone())),
+            path,
+        )
+    }
+
+    /// Stores the file with the given attachment id. The original file is
+    /// consumed.
+    pub async fn store(
+        &self,
+        file: &Path,
+        attachment_id: i32,
+    ) -> Result<(), AttachmentStorageError> {
+        self.backend.store(file, attachment_id).await
+    }
+
+    /// Creates a temporary file in the upload path, for receiving uploads
+    /// into. For a directory backend this is the storage directory itself, so
+    /// that [`AttachmentStorage::store`] can move it into place without
+    /// copying. The file is deleted when dropped, unless it has been stored.
+    pub fn temp_file(&self) -> Result<NamedTempFile, AttachmentStorageError> {
+        Ok(tempfile::Builder::new()
+            .prefix(".upload-")
+            .tempfile_in(&self.upload_path)?)
+    }
+
+    /// The size in bytes of a stored attachment.
+    pub async fn size(
+        &self,
+        attachment_id: i32,
+    ) -> Result<u64, AttachmentStorageError> {
+        self.backend.size(attachment_id).await
+    }
+
+    /// Loads `length` bytes of an attachment out of storage, beginning at
+    /// `start`.
+    pub async fn load(
+        &self,
+        attachment_id: i32,
+        start: u64,
+        length: u64,
+    ) -> Result<StoredBody, AttachmentStorageError> {
+        self.backend.load(attachment_id, start, length).await
+    }
+
+    /// Removes an attachment from storage.
+    pub async fn delete(
+        &self,
+        attachment_id: i32,
+    ) -> Result<(), AttachmentStorageError> {
+        self.backend.delete(attachment_id).await
+    }
+
+    /// Computes the digests of an attachment as it currently is in storage,
+    /// for checking against what was recorded when it was stored.
+    pub async fn digest(
+        &self,
+        attachment_id: i32,
+    ) -> Result<Digests, AttachmentStorageError> {
+        let size = self.size(attachment_id).await?;
+        let body = self.load(attachment_id, 0, size).await?;
+
+        Ok(digest_reader(body).await?)
+    }
+
+    /// Lists everything in storage, sorting out the attachments from whatever
+    /// else has found its way in there.
+    pub async fn inventory(&self) -> Result<Inventory, AttachmentStorageError> {
+        self.backend.inventory().await
+    }
+}
+
+/// The MIME type of anything [`sniff_mime_type`] doesn't recognise, which
+/// browsers download rather than render.
+pub const OPAQUE_MIME_TYPE: &str = "application/octet-stream";
+
+/// Works out what kind of file this is from the first few bytes of it, for the
+/// handful of types that people are likely to upload. Browsers are quite happy
+/// to trust whatever MIME type we tell them, so we go by what's in the file
+/// rather than by what it claims to be, and nothing that can run scripts, such
+/// as html or svg, is ever recognised.
+pub fn sniff_mime_type(head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+    ];
+
+    // webp is a riff container with the format a few bytes in
+    if head.len() >= 12 && &head[0..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| head.starts_with(signature))
+        .map(|(_, mime_type)| *mime_type)
+}
+
+/// Digests a file a chunk at a time, so that hashing a game binary that's
+/// hundreds of megabytes doesn't need hundreds of megabytes of memory.
+pub async fn digest_file(file: &Path) -> Result<Digests, IOError> {
+    digest_reader(File::open(file).await?).await
+}
+
+async fn digest_reader(
+    mut reader: impl AsyncRead + Unpin,
+) -> Result<Digests, IOError> {
+    let mut md5_context = md5::Context::new();
+    let mut sha256_hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let n = match reader.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        md5_context.consume(&buffer[..n]);
+        sha256_hasher.update(&buffer[..n]);
+    }
+
+    let mut sha256: [u8; 32] = [0; 32];
+    sha256.copy_from_slice(&sha256_hasher.finalize());
+
+    Ok(Digests {
+        md5: md5_context.compute().0,
+        sha256,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::attachments::*;
+    use hex::decode as hex_decode;
+    use std::io::Write;
+
+    /// This may look dumb to test... and it kind of is. But I wanted to make
+    /// sure the digester was working in a way consistent with expectation. So I
+    /// wrote a test for it. It didn't make sense to delete it.
+    #[rocket::async_test]
+    async fn test_file_hashing() {
+        let example_content = "this is an example";
+        let expected_md5 =
+            hex_decode("9202816dabaaf34bb106a10421b9a0d0").unwrap();
+        let expected_sha256 = hex_decode(
+            "d44c035835f1c5e0668b7d186a2ff5b0dc2e3137ec3c50b12a34c47b7af51e44",
+        )
+        .unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        write!(&file, "{}", example_content).unwrap();
+        let actual = digest_file(file.path()).await.unwrap();
+        assert_eq!(expected_md5, actual.md5);
+        assert_eq!(expected_sha256, actual.sha256);
+    }
+
+    /// Files bigger than the read buffer have to come out the same as if they
+    /// had been hashed in one go.
+    #[rocket::async_test]
+    async fn test_chunked_hashing() {
+        let content = vec![b'x'; 200 * 1024 + 7];
+        let actual = digest_reader(&content[..]).await.unwrap();
+        assert_eq!(md5::compute(&content).0, actual.md5);
+        assert_eq!(&Sha256::digest(&content)[..], &actual.sha256[..]);
+    }
+
+    #[test]
+    fn test_storage_spec_parsing() {
+        assert_eq!(
+            StorageSpec::Directory(DirectoryLayout::Flat, "/srv/a".into()),
+            "/srv/a".parse().unwrap()
+        );
+        assert_eq!(
+            StorageSpec::Directory(DirectoryLayout::Flat, "/srv/a".into()),
+            "flat:/srv/a".parse().unwrap()
+        );
+        assert_eq!(
+            StorageSpec::Directory(DirectoryLayout::Sharded, "/srv/a".into()),
+            "sharded:/srv/a".parse().unwrap()
+        );
+        assert_eq!(
+            StorageSpec::S3("udevgames".to_string()),
+            "s3:udevgames".parse().unwrap()
+        );
+        assert!("s3:".parse::<StorageSpec>().is_err());
+        assert!("sharded:".parse::<StorageSpec>().is_err());
+    }
+
+    #[test]
+    fn test_storage_spec_same_location() {
+        let spec = |s: &str| s.parse::<StorageSpec>().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("a")).unwrap();
+        std::fs::create_dir(dir.path().join("b")).unwrap();
+        let a = dir.path().join("a").display().to_string();
+        let b_to_a = dir.path().join("b/../a").display().to_string();
+
+        assert!(spec("/srv/a").same_location(&spec("sharded:/srv/a")));
+        assert!(spec(&a).same_location(&spec(&b_to_a)));
+        assert!(spec("s3:udevgames").same_location(&spec("s3:udevgames")));
+        assert!(!spec("/srv/a").same_location(&spec("/srv/b")));
+        assert!(!spec("s3:udevgames").same_location(&spec("s3:backup")));
+        assert!(!spec("s3:udevgames").same_location(&spec("udevgames")));
+    }
+
+    #[test]
+    fn test_mime_type_sniffing() {
+        assert_eq!(
+            Some("image/png"),
+            sniff_mime_type(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")
+        );
+        assert_eq!(Some("image/jpeg"), sniff_mime_type(b"\xff\xd8\xff\xe0"));
+        assert_eq!(
+            Some("image/webp"),
+            sniff_mime_type(b"RIFF\0\0\0\0WEBPVP8 ")
+        );
+        assert_eq!(None, sniff_mime_type(b"RIFF\0\0\0\0WAVEfmt "));
+        assert_eq!(None, sniff_mime_type(b"just some text"));
+        assert_eq!(None, sniff_mime_type(b"<svg xmlns=\"http://www.w3.org/"));
+        assert_eq!(None, sniff_mime_type(b"<!DOCTYPE html><script>"));
+        assert_eq!(None, sniff_mime_type(b""));
+    }
+}
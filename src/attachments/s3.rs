@@ -0,0 +1,563 @@
+use crate::attachments::{
+    AttachmentStorageError, Inventory, StorageBackend, StoredBody,
+};
+use bytes::{Buf, Bytes};
+use chrono::{DateTime, Utc};
+use futures::stream::{Stream, StreamExt};
+use hex::encode as hex_encode;
+use hmac::{Hmac, Mac, NewMac};
+use reqwest::{Body, Client, Method, RequestBuilder, StatusCode, Url};
+use rocket::tokio::{
+    fs::{self, File},
+    io::{AsyncRead, AsyncReadExt},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// We don't hash request bodies before sending them, which would mean reading
+/// every upload twice. S3 and its lookalikes accept this in place of a hash.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// How to reach an S3-compatible object store. This works with anything that
+/// speaks the S3 API with path-style bucket addressing, such as MinIO.
+pub struct S3Config {
+    /// The base url of the object store, such as `http://localhost:9000` or
+    /// `https://s3.us-west-2.amazonaws.com`.
+    pub endpoint: Url,
+    /// The region the bucket is in, which is part of every signature even
+    /// when the store doesn't have regions.
+    pub region: String,
+    /// The bucket attachments are kept in.
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+/// Stores attachments as objects in an S3-compatible object store, keyed by
+/// their id.
+pub struct S3Backend {
+    config: S3Config,
+    client: Client,
+}
+
+impl S3Backend {
+    pub fn new(config: S3Config) -> Self {
+        S3Backend {
+            config,
+            client: Client::new(),
+        }
+    }
+
+    fn object_url(&self, attachment_id: i32) -> Url {
+        let mut url = self.config.endpoint.clone();
+        url.set_path(&format!("/{}/{}", self.config.bucket, attachment_id));
+        url
+    }
+
+    fn describe(&self, attachment_id: i32) -> String {
+        format!("s3:{}/{}", self.config.bucket, attachment_id)
+    }
+
+    /// Starts a request to the object store, signed with the configured
+    /// credentials.
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let signed = sign(&self.config, method.as_str(), &url, Utc::now());
+
+        self.client
+            .request(method, url)
+            .header("x-amz-date", signed.amz_date)
+            .header("x-amz-content-sha256", UNSIGNED_PAYLOAD)
+            .header("Authorization", signed.authorization)
+    }
+
+    /// Sends a request, turning anything unsuccessful into an error.
+    async fn send(
+        &self,
+        request: RequestBuilder,
+        attachment_id: Option<i32>,
+    ) -> Result<reqwest::Response, AttachmentStorageError> {
+        let response = request.send().await?;
+
+        match (response.status(), attachment_id) {
+            (status, _) if status.is_success() => Ok(response),
+            (StatusCode::NOT_FOUND, Some(id)) => {
+                Err(AttachmentStorageError::NotFound(self.describe(id)))
+            }
+            (status, _) => Err(AttachmentStorageError::ObjectStoreError(
+                status,
+                response.url().to_string(),
+            )),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl StorageBackend for S3Backend {
+    async fn store(
+        &self,
+        file: &Path,
+        attachment_id: i32,
+    ) -> Result<(), AttachmentStorageError> {
+        let length = fs::metadata(file).await?.len();
+        let reader = File::open(file).await?;
+        let body = futures::stream::unfold(reader, |mut reader| async move {
+            let mut buffer = vec![0u8; 64 * 1024];
+            match reader.read(&mut buffer).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    buffer.truncate(n);
+                    Some((Ok::<_, std::io::Error>(Bytes::from(buffer)), reader))
+                }
+                Err(e) => Some((Err(e), reader)),
+            }
+        });
+
+        let request = self
+            .request(Method::PUT, self.object_url(attachment_id))
+            .header("Content-Length", length)
+            .body(Body::wrap_stream(body));
+        self.send(request, Some(attachment_id)).await?;
+
+        // the file is consumed, just as a local backend would move it away
+        fs::remove_file(file).await?;
+
+        Ok(())
+    }
+
+    async fn size(
+        &self,
+        attachment_id: i32,
+    ) -> Result<u64, AttachmentStorageError> {
+        let request =
+            self.request(Method::HEAD, self.object_url(attachment_id));
+        let response = self.send(request, Some(attachment_id)).await?;
+
+        response
+            .headers()
+            .get("Content-Length")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| {
+                AttachmentStorageError::ObjectStoreError(
+                    response.status(),
+                    response.url().to_string(),
+                )
+            })
+    }
+
+    async fn load(
+        &self,
+        attachment_id: i32,
+        start: u64,
+        length: u64,
+    ) -> Result<StoredBody, AttachmentStorageError> {
+        // there's no way to ask for zero bytes with a range
+        if length == 0 {
+            return Ok(Box::pin(&b""[..]));
+        }
+
+        let request = self
+            .request(Method::GET, self.object_url(attachment_id))
+            .header("Range", format!("bytes={}-{}", start, start + length - 1));
+        let response = self.send(request, Some(attachment_id)).await?;
+
+        Ok(Box::pin(BodyReader {
+            stream: Box::pin(response.bytes_stream()),
+            chunk: Bytes::new(),
+        }))
+    }
+
+    async fn delete(
+        &self,
+        attachment_id: i32,
+    ) -> Result<(), AttachmentStorageError> {
+        let request =
+            self.request(Method::DELETE, self.object_url(attachment_id));
+        self.send(request, Some(attachment_id)).await?;
+
+        Ok(())
+    }
+
+    async fn inventory(&self) -> Result<Inventory, AttachmentStorageError> {
+        let mut inventory = Inventory::default();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let mut url = self.config.endpoint.clone();
+            url.set_path(&format!("/{}", self.config.bucket));
+            // the query is signed as-is, so it has to already be in the
+            // canonical order and encoding
+            url.set_query(Some(&match &continuation_token {
+                Some(token) => format!(
+                    "continuation-token={}&list-type=2",
+                    aws_uri_encode(token)
+                ),
+                None => "list-type=2".to_string(),
+            }));
+
+            let response =
+                self.send(self.request(Method::GET, url), None).await?;
+            let listing = response.text().await?;
+
+            for key in xml_elements(&listing, "Key") {
+                let key = xml_unescape(key);
+                match key.parse::<i32>() {
+                    Ok(id) if id.to_string() == key => {
+                        inventory.attachment_ids.push(id)
+                    }
+                    _ => inventory
+                        .unrecognized
+                        .push(format!("s3:{}/{}", self.config.bucket, key)),
+                }
+            }
+
+            let is_truncated =
+                xml_elements(&listing, "IsTruncated").first() == Some(&"true");
+            continuation_token =
+                xml_elements(&listing, "NextContinuationToken")
+                    .first()
+                    .map(|token| xml_unescape(token));
+
+            if !is_truncated || continuation_token.is_none() {
+                break;
+            }
+        }
+
+        inventory.attachment_ids.sort_unstable();
+        inventory.unrecognized.sort();
+
+        Ok(inventory)
+    }
+}
+
+/// Adapts the body of a response, which arrives as a stream of chunks, into
+/// something that can be read from like a file.
+struct BodyReader {
+    stream: Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>,
+    /// Whatever is left of the last chunk received.
+    chunk: Bytes,
+}
+
+impl AsyncRead for BodyReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        while self.chunk.is_empty() {
+            match self.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(chunk))) => self.chunk = chunk,
+                Poll::Ready(Some(Err(e))) => {
+                    return Poll::Ready(Err(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        e,
+                    )))
+                }
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk[..n]);
+        self.chunk.advance(n);
+
+        Poll::Ready(Ok(n))
+    }
+}
+
+/// The headers which authenticate a request to the object store.
+struct Signed {
+    amz_date: String,
+    authorization: String,
+}
+
+/// Signs a request with AWS Signature Version 4. Only the host and the
+/// `x-amz-` headers are signed, and the payload never is.
+fn sign(
+    config: &S3Config,
+    method: &str,
+    url: &Url,
+    now: DateTime<Utc>,
+) -> Signed {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or(""), port),
+        None => url.host_str().unwrap_or("").to_string(),
+    };
+    let headers = [
+        ("host", host.as_str()),
+        ("x-amz-content-sha256", UNSIGNED_PAYLOAD),
+        ("x-amz-date", amz_date.as_str()),
+    ];
+    let canonical_request =
+        canonical_request(method, url, &headers, UNSIGNED_PAYLOAD);
+    let (scope, signature) = signature(
+        &config.secret_key,
+        &config.region,
+        "s3",
+        now,
+        &canonical_request,
+    );
+
+    Signed {
+        authorization: format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            config.access_key,
+            scope,
+            signed_headers(&headers),
+            signature
+        ),
+        amz_date,
+    }
+}
+
+/// Puts a request into the canonical form that Signature Version 4 signs.
+/// The url's query must already be in canonical order and encoding, and the
+/// headers must have lowercase names and be sorted by them.
+fn canonical_request(
+    method: &str,
+    url: &Url,
+    headers: &[(&str, &str)],
+    payload_hash: &str,
+) -> String {
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+
+    format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        url.path(),
+        url.query().unwrap_or(""),
+        canonical_headers,
+        signed_headers(headers),
+        payload_hash
+    )
+}
+
+fn signed_headers(headers: &[(&str, &str)]) -> String {
+    headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Works out the credential scope of a canonical request and its signature,
+/// which together with the access key make up its `Authorization` header.
+fn signature(
+    secret_key: &str,
+    region: &str,
+    service: &str,
+    now: DateTime<Utc>,
+    canonical_request: &str,
+) -> (String, String) {
+    type HmacSha256 = Hmac<Sha256>;
+
+    fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+        let mut mac = HmacSha256::new_varkey(key)
+            .expect("HMAC can take a key of any size");
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    let date = now.format("%Y%m%d").to_string();
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        now.format("%Y%m%dT%H%M%SZ"),
+        scope,
+        hex_encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let signing_key = [region, service, "aws4_request"].iter().fold(
+        hmac(format!("AWS4{}", secret_key).as_bytes(), &date),
+        |key, part| hmac(&key, part),
+    );
+    let signature = hex_encode(hmac(&signing_key, &string_to_sign));
+
+    (scope, signature)
+}
+
+/// Percent-encodes everything but the characters AWS leaves alone when it
+/// works out what a signature should be.
+fn aws_uri_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'_'
+            | b'.'
+            | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Finds the text of every element with the given name. The listings we get
+/// back are simple enough that this is all the XML parsing we need.
+fn xml_elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let mut elements = Vec::new();
+    let mut rest = xml;
+
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        match rest.find(&close) {
+            Some(end) => {
+                elements.push(&rest[..end]);
+                rest = &rest[end + close.len()..];
+            }
+            None => break,
+        }
+    }
+
+    elements
+}
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_signing() {
+        let config = S3Config {
+            endpoint: Url::parse("http://localhost:9000").unwrap(),
+            region: "us-east-1".to_string(),
+            bucket: "udevgames".to_string(),
+            access_key: "minioadmin".to_string(),
+            secret_key: "minioadmin".to_string(),
+        };
+        let url = Url::parse("http://localhost:9000/udevgames/42").unwrap();
+        let signed =
+            sign(&config, "GET", &url, Utc.ymd(2020, 12, 5).and_hms(12, 0, 0));
+
+        assert_eq!("20201205T120000Z", signed.amz_date);
+        assert_eq!(
+            "AWS4-HMAC-SHA256 \
+             Credential=minioadmin/20201205/us-east-1/s3/aws4_request, \
+             SignedHeaders=host;x-amz-content-sha256;x-amz-date, \
+             Signature=2eb408d99119f6897642ff3556c1d0a5c61d2b7b0f2bbdf9fe27310a53a512bb",
+            signed.authorization
+        );
+    }
+
+    /// The `get-vanilla` and `post-vanilla` cases of AWS's Signature Version 4
+    /// test suite, which sign a bare request to a made up service.
+    #[test]
+    fn test_signature_test_suite() {
+        let url = Url::parse("https://example.amazonaws.com/").unwrap();
+        let headers = [
+            ("host", "example.amazonaws.com"),
+            ("x-amz-date", "20150830T123600Z"),
+        ];
+        let empty_payload_hash =
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let now = Utc.ymd(2015, 8, 30).and_hms(12, 36, 0);
+        let sign = |method| {
+            signature(
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                "us-east-1",
+                "service",
+                now,
+                &canonical_request(method, &url, &headers, empty_payload_hash),
+            )
+        };
+
+        assert_eq!(
+            "GET\n/\n\n\
+             host:example.amazonaws.com\n\
+             x-amz-date:20150830T123600Z\n\n\
+             host;x-amz-date\n\
+             e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            canonical_request("GET", &url, &headers, empty_payload_hash)
+        );
+        assert_eq!(
+            (
+                "20150830/us-east-1/service/aws4_request".to_string(),
+                "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+                    .to_string()
+            ),
+            sign("GET")
+        );
+        assert_eq!(
+            "5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b",
+            sign("POST").1
+        );
+    }
+
+    #[test]
+    fn test_listing_parsing() {
+        let listing = "<ListBucketResult><IsTruncated>true</IsTruncated>\
+            <Contents><Key>1</Key></Contents>\
+            <Contents><Key>a&amp;b</Key></Contents>\
+            <NextContinuationToken>abc/def=</NextContinuationToken>\
+            </ListBucketResult>";
+
+        assert_eq!(vec!["1", "a&amp;b"], xml_elements(listing, "Key"));
+        assert_eq!("a&b", xml_unescape("a&amp;b"));
+        assert_eq!(vec!["true"], xml_elements(listing, "IsTruncated"));
+        assert_eq!("abc%2Fdef%3D", aws_uri_encode("abc/def="));
+    }
+    /// Runs through everything the backend does against a real object store,
+    /// such as a local MinIO, configured with the same `UDEVGAMES_S3_`
+    /// variables as the site plus `UDEVGAMES_S3_TEST_BUCKET`, which must
+    /// already exist. Run it with `cargo test -- --ignored`.
+    #[rocket::async_test]
+    #[ignore]
+    async fn test_object_store() {
+        dotenv::dotenv().ok();
+        let var = |name: &str| {
+            std::env::var(name)
+                .unwrap_or_else(|_| panic!("{} must be set", name))
+        };
+        let backend = S3Backend::new(S3Config {
+            endpoint: Url::parse(&var("UDEVGAMES_S3_ENDPOINT")).unwrap(),
+            region: var("UDEVGAMES_S3_REGION"),
+            bucket: var("UDEVGAMES_S3_TEST_BUCKET"),
+            access_key: var("UDEVGAMES_S3_ACCESS_KEY"),
+            secret_key: var("UDEVGAMES_S3_SECRET_KEY"),
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let upload = dir.path().join(".upload-abc");
+        std::fs::write(&upload, "some content").unwrap();
+
+        backend.store(&upload, 258).await.unwrap();
+        assert!(!upload.exists());
+        assert_eq!(12, backend.size(258).await.unwrap());
+
+        let mut content = String::new();
+        backend
+            .load(258, 5, 3)
+            .await
+            .unwrap()
+            .read_to_string(&mut content)
+            .await
+            .unwrap();
+        assert_eq!("con", content);
+
+        let inventory = backend.inventory().await.unwrap();
+        assert!(inventory.attachment_ids.contains(&258));
+
+        backend.delete(258).await.unwrap();
+        assert!(backend.size(258).await.is_err());
+    }
+}
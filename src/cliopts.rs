@@ -1,3 +1,4 @@
+use crate::models::PermissionKind;
 use clap::{crate_authors, crate_version, Clap};
 
 #[derive(Clap)]
@@ -12,6 +13,9 @@ pub enum SubCommand {
     Migrate(Migrate),
     Serve(Serve),
     Permission(Permission),
+    User(User),
+    Session(Session),
+    Attachments(Attachments),
 }
 
 /// Migrates the uDevGames database to the current schema
@@ -20,7 +24,12 @@ pub struct Migrate {}
 
 /// Starts the uDevGames website
 #[derive(Clap)]
-pub struct Serve {}
+pub struct Serve {
+    /// Log in with a fake Github built into the site, which lets anyone log
+    /// in as anyone without a network connection. For development only!
+    #[clap(long)]
+    pub fake_github: bool,
+}
 
 /// Grant, revoke, and show permissions given to users
 #[derive(Clap)]
@@ -43,9 +52,15 @@ pub struct PermissionGrant {
     #[clap(short, long)]
     pub user: String,
 
-    /// The permission to grant to the user
+    /// The permission to grant to the user: admin, banned, moderator or
+    /// jam_host
     #[clap(short, long)]
-    pub permission: String,
+    pub permission: PermissionKind,
+
+    /// Why the permission is being granted. Required for a ban, since the
+    /// banned user is shown it
+    #[clap(short, long)]
+    pub reason: Option<String>,
 }
 
 /// Revokes a permission from a user
@@ -71,3 +86,121 @@ pub struct PermissionShow {
     #[clap(short, long)]
     pub permission: Option<String>,
 }
+
+/// List, show, sync with Github, and delete users
+#[derive(Clap)]
+pub struct User {
+    #[clap(subcommand)]
+    pub subcmd: UserSubCommand,
+}
+
+#[derive(Clap)]
+pub enum UserSubCommand {
+    List(UserList),
+    Show(UserShow),
+    Sync(UserSync),
+    Delete(UserDelete),
+}
+
+/// Lists every user who has ever logged in
+#[derive(Clap)]
+pub struct UserList {}
+
+/// Shows everything about a user
+#[derive(Clap)]
+pub struct UserShow {
+    /// The user to show, either by @login or numeric id
+    #[clap(short, long)]
+    pub user: String,
+}
+
+/// Refreshes a user's login, avatar and profile link from Github
+#[derive(Clap)]
+pub struct UserSync {
+    /// The user to sync, either by @login or numeric id
+    #[clap(short, long)]
+    pub user: String,
+}
+
+/// Deletes a user along with their permissions and sessions
+#[derive(Clap)]
+pub struct UserDelete {
+    /// The user to delete, either by @login or numeric id
+    #[clap(short, long)]
+    pub user: String,
+}
+
+/// List and revoke the sessions users are logged in with
+#[derive(Clap)]
+pub struct Session {
+    #[clap(subcommand)]
+    pub subcmd: SessionSubCommand,
+}
+
+#[derive(Clap)]
+pub enum SessionSubCommand {
+    List(SessionList),
+    Revoke(SessionRevoke),
+}
+
+/// Lists sessions, most recently used first
+#[derive(Clap)]
+pub struct SessionList {
+    /// Only list sessions of this user, either by @login or numeric id
+    #[clap(short, long)]
+    pub user: Option<String>,
+}
+
+/// Revokes sessions, logging their browsers out
+#[derive(Clap)]
+pub struct SessionRevoke {
+    /// The id of the session to revoke, as shown by `session list`
+    #[clap(long)]
+    pub id: Option<i32>,
+
+    /// Revoke every session of this user, either by @login or numeric id
+    #[clap(short, long)]
+    pub user: Option<String>,
+}
+
+/// Audit and maintain stored attachments
+#[derive(Clap)]
+pub struct Attachments {
+    #[clap(subcommand)]
+    pub subcmd: AttachmentsSubCommand,
+}
+
+#[derive(Clap)]
+pub enum AttachmentsSubCommand {
+    Verify(AttachmentsVerify),
+    MigrateStorage(AttachmentsMigrateStorage),
+}
+
+/// Re-hashes every stored attachment, reporting missing files, hash
+/// mismatches, and stray files in storage which no attachment refers to
+#[derive(Clap)]
+pub struct AttachmentsVerify {
+    /// Unpublish attachments which are missing or fail verification, so that
+    /// they are no longer served
+    #[clap(long)]
+    pub unpublish_corrupted: bool,
+
+    /// Record the SHA-256 of attachments which pass verification but were
+    /// stored before SHA-256 digests were tracked
+    #[clap(long)]
+    pub record_missing_sha256: bool,
+}
+
+/// Copies every attachment from the current storage into another, such as from
+/// a local directory into an object store
+#[derive(Clap)]
+pub struct AttachmentsMigrateStorage {
+    /// The storage to copy attachments into, written the same way as
+    /// UDEVGAMES_ATTACHMENT_STORAGE: a path, sharded:<path>, or s3:<bucket>
+    #[clap(long)]
+    pub to: String,
+
+    /// Delete each attachment from the current storage once it has been copied
+    #[clap(long)]
+    pub delete_source: bool,
+}
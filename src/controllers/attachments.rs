@@ -1,19 +1,585 @@
-use crate::{attachments::AttachmentStorage, db::DbPool, models::Attachment};
-use rocket::{get, response::Stream, tokio::fs::File, State};
+use crate::{
+    attachments::{
+        digest_file, sniff_mime_type, AttachmentStorage, StoredBody,
+        OPAQUE_MIME_TYPE,
+    },
+    db::DbPool,
+    models::{Attachment, Jam},
+    template_helpers::{
+        Admin, AttachmentContext, CsrfVerified, RequirePermission, UserRequired,
+    },
+};
+use bytes::Bytes;
+use hex::encode as hex_encode;
+use rocket::{
+    data::{Data, ToByteUnit},
+    get,
+    http::{ContentType, Status},
+    post,
+    request::{FromRequest, Outcome},
+    response::{Responder, Response, Result as RocketResult},
+    tokio::{
+        fs::File,
+        io::{AsyncReadExt, AsyncWriteExt},
+    },
+    Request, State,
+};
+use rocket_contrib::json::Json;
+use tempfile::TempPath;
+
+// CREATE   /attachments                -> Attachment   USERS ONLY
+// CREATE   /jams/:jam_id/attachments   -> Attachment   ADMIN ONLY, sets the
+//                                                      jam's summary image
+// GET      /attachments/:id/:name      -> File         published only
+
+/// The largest upload we'll accept, in bytes. This is measured against the
+/// whole request body, so the file itself has to be a little smaller than this
+/// to fit alongside the multipart framing.
+const MAX_UPLOAD_BYTES: u64 = 512 * 1024 * 1024;
+
+/// How much of the start of an upload is kept around for working out what kind
+/// of file it is.
+const SNIFF_BYTES: usize = 512;
 
 // match stuff like udevgames.com/attachments/1/my_file.jpeg
 #[get("/attachments/<id>/<_name>")]
 pub async fn get_attachment(
     pool: State<'_, DbPool>,
     attachment_storage: State<'_, AttachmentStorage>,
+    conditions: DownloadConditions,
     id: i32,
     _name: String,
-) -> Result<Stream<File>, super::HandlerError> {
-    let conn = pool.get()?;
-    let attachment = match Attachment::find_published_by_id(&conn, id)? {
+) -> Result<AttachmentDownload, super::HandlerError> {
+    let attachment = match Attachment::find_published_by_id(&pool.get()?, id)? {
         Some(attachment) => attachment,
         None => return Err(super::HandlerError::NotFound),
     };
-    let f = attachment_storage.load(attachment.id)?;
-    Ok(Stream::from(File::from_std(f)))
+    let etag = etag_for(&attachment);
+
+    if let Some(if_none_match) = &conditions.if_none_match {
+        if etag_matches(if_none_match, &etag) {
+            return Ok(AttachmentDownload::NotModified { etag });
+        }
+    }
+
+    let size = attachment_storage.size(attachment.id).await?;
+
+    // a range only applies to the version of the file the client already has
+    // part of, so if it's changed since then the whole thing is sent
+    let range = match (&conditions.range, &conditions.if_range) {
+        (Some(range), None) => parse_range(range, size),
+        (Some(range), Some(if_range)) if if_range.trim() == etag => {
+            parse_range(range, size)
+        }
+        _ => ByteRange::Full,
+    };
+
+    let (start, length) = match range {
+        ByteRange::Full => (0, size),
+        ByteRange::Partial { start, end } => (start, end - start + 1),
+        ByteRange::Unsatisfiable => {
+            return Ok(AttachmentDownload::Unsatisfiable { etag, size })
+        }
+    };
+
+    let body = attachment_storage
+        .load(attachment.id, start, length)
+        .await?;
+
+    Ok(AttachmentDownload::Content {
+        attachment,
+        etag,
+        size,
+        range,
+        body,
+    })
+}
+
+/// The headers a browser sends to ask for only part of an attachment, or for
+/// it only if it has changed.
+pub struct DownloadConditions {
+    if_none_match: Option<String>,
+    range: Option<String>,
+    if_range: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for DownloadConditions {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let header = |name| req.headers().get_one(name).map(str::to_string);
+
+        Outcome::Success(DownloadConditions {
+            if_none_match: header("If-None-Match"),
+            range: header("Range"),
+            if_range: header("If-Range"),
+        })
+    }
+}
+
+/// A published attachment on its way to a browser. This sets the headers that
+/// let browsers cache attachments and resume interrupted downloads.
+pub enum AttachmentDownload {
+    /// The browser already has this version of the attachment.
+    NotModified { etag: String },
+
+    /// The browser asked for a range that isn't in the attachment.
+    Unsatisfiable { etag: String, size: u64 },
+
+    /// The attachment, or the part of it that was asked for.
+    Content {
+        attachment: Attachment,
+        etag: String,
+        size: u64,
+        range: ByteRange,
+        body: StoredBody,
+    },
+}
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for AttachmentDownload {
+    fn respond_to(self, _request: &'r Request<'_>) -> RocketResult<'o> {
+        let mut response = Response::build();
+
+        // attachments come from users but are served from this site, so
+        // browsers are told not to second-guess their type, and anything that
+        // does get rendered can't run scripts as the site
+        response
+            .raw_header("Cache-Control", "public, max-age=3600")
+            .raw_header("Accept-Ranges", "bytes")
+            .raw_header("X-Content-Type-Options", "nosniff")
+            .raw_header("Content-Security-Policy", "sandbox");
+
+        match self {
+            AttachmentDownload::NotModified { etag } => response
+                .status(Status::NotModified)
+                .raw_header("ETag", etag)
+                .ok(),
+            AttachmentDownload::Unsatisfiable { etag, size } => response
+                .status(Status::RangeNotSatisfiable)
+                .raw_header("ETag", etag)
+                .raw_header("Content-Range", format!("bytes */{}", size))
+                .ok(),
+            AttachmentDownload::Content {
+                attachment,
+                etag,
+                size,
+                range,
+                body,
+            } => {
+                let length = match range {
+                    ByteRange::Partial { start, end } => {
+                        response.status(Status::PartialContent).raw_header(
+                            "Content-Range",
+                            format!("bytes {}-{}/{}", start, end, size),
+                        );
+                        end - start + 1
+                    }
+                    _ => size,
+                };
+
+                let content_type =
+                    ContentType::parse_flexible(&attachment.mime_type)
+                        .unwrap_or(ContentType::Binary);
+                let inline = is_inline_mime_type(&attachment.mime_type);
+                let disposition = if inline { "inline" } else { "attachment" };
+
+                response
+                    .header(content_type)
+                    .raw_header("ETag", etag)
+                    .raw_header(
+                        "Content-Disposition",
+                        format!(
+                            "{}; filename=\"{}\"",
+                            disposition,
+                            disposition_file_name(&attachment.name)
+                        ),
+                    )
+                    .raw_header("Content-Length", length.to_string())
+                    .streamed_body(body)
+                    .ok()
+            }
+        }
+    }
+}
+
+/// Whether an attachment of this type is shown in the browser rather than
+/// downloaded. Only the raster images [`sniff_mime_type`] recognises are, as
+/// svg can carry scripts and older attachments may have been stored as one.
+fn is_inline_mime_type(mime_type: &str) -> bool {
+    matches!(
+        mime_type,
+        "image/png" | "image/jpeg" | "image/gif" | "image/webp"
+    )
+}
+
+/// What part of an attachment a request is asking for.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// The whole attachment.
+    Full,
+    /// The bytes from `start` to `end`, inclusive.
+    Partial { start: u64, end: u64 },
+    /// A range which lies entirely past the end of the attachment.
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header against an attachment of the given size. Only a
+/// single range of bytes is supported, which is what download managers use to
+/// resume; anything else is answered with the whole attachment, which the
+/// spec allows.
+fn parse_range(header: &str, size: u64) -> ByteRange {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return ByteRange::Full,
+    };
+    let (first, last) = match spec.find('-') {
+        Some(i) => (&spec[..i], &spec[i + 1..]),
+        None => return ByteRange::Full,
+    };
+
+    match (first.parse::<u64>(), last.parse::<u64>()) {
+        // bytes=500-999
+        (Ok(start), Ok(end)) if start <= end => {
+            if start >= size {
+                ByteRange::Unsatisfiable
+            } else {
+                ByteRange::Partial {
+                    start,
+                    end: end.min(size - 1),
+                }
+            }
+        }
+        // bytes=500-
+        (Ok(start), Err(_)) if last.is_empty() => {
+            if start >= size {
+                ByteRange::Unsatisfiable
+            } else {
+                ByteRange::Partial {
+                    start,
+                    end: size - 1,
+                }
+            }
+        }
+        // bytes=-500, the last 500 bytes
+        (Err(_), Ok(suffix)) if first.is_empty() => {
+            if suffix == 0 || size == 0 {
+                ByteRange::Unsatisfiable
+            } else {
+                ByteRange::Partial {
+                    start: size.saturating_sub(suffix),
+                    end: size - 1,
+                }
+            }
+        }
+        _ => ByteRange::Full,
+    }
+}
+
+/// The entity tag of an attachment is its hash, since the hash changes exactly
+/// when the content does. Attachments that haven't had a SHA-256 recorded yet
+/// fall back to their md5.
+fn etag_for(attachment: &Attachment) -> String {
+    match &attachment.sha256 {
+        Some(sha256) => format!("\"{}\"", hex_encode(sha256)),
+        None => format!("\"{}\"", hex_encode(&attachment.md5)),
+    }
+}
+
+/// Whether an `If-None-Match` header matches the given entity tag. This uses
+/// the weak comparison the spec calls for, so `W/` prefixes are ignored.
+fn etag_matches(header: &str, etag: &str) -> bool {
+    header.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.trim_start_matches("W/") == etag
+    })
+}
+
+/// The file name goes inside a quoted string in a header, so anything which
+/// could break out of that, or which isn't plain ascii, is replaced.
+fn disposition_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Accepts a `multipart/form-data` upload with the file in a field called
+/// `file`, storing it as an attachment owned by the user who uploaded it. The
+/// response describes the new attachment as JSON, so that an editor can
+/// insert a link to it.
+#[post("/attachments", data = "<data>")]
+pub async fn create_attachment(
+    pool: State<'_, DbPool>,
+    attachment_storage: State<'_, AttachmentStorage>,
+    user: UserRequired,
+    _csrf: CsrfVerified,
+    content_type: &ContentType,
+    data: Data,
+) -> Result<Json<AttachmentContext>, super::HandlerError> {
+    let upload =
+        receive_upload(&attachment_storage, content_type, data).await?;
+    let attachment =
+        store_upload(&pool, &attachment_storage, upload, Some(user.id()))
+            .await?;
+
+    Ok(Json(AttachmentContext::from_model(&attachment)))
+}
+
+/// Accepts an image upload in the same way as [`create_attachment`] and makes
+/// it the summary image of a jam, which is shown as its banner. The image it
+/// replaces is unpublished.
+#[post("/jams/<jam_id>/attachments", data = "<data>")]
+pub async fn create_jam_attachment(
+    pool: State<'_, DbPool>,
+    attachment_storage: State<'_, AttachmentStorage>,
+    admin_only: RequirePermission<Admin>,
+    _csrf: CsrfVerified,
+    jam_id: i32,
+    content_type: &ContentType,
+    data: Data,
+) -> Result<Json<AttachmentContext>, super::HandlerError> {
+    if Jam::find_by_id(&pool.get()?, jam_id)?.is_none() {
+        return Err(super::HandlerError::NotFound);
+    }
+
+    let upload =
+        receive_upload(&attachment_storage, content_type, data).await?;
+
+    if !upload.mime_type.starts_with("image/") {
+        return Err(super::HandlerError::BadRequest(
+            "A jam's summary attachment must be an image".to_string(),
+        ));
+    }
+
+    let attachment =
+        store_upload(&pool, &attachment_storage, upload, Some(admin_only.id()))
+            .await?;
+
+    Jam::replace_summary_attachment(&pool.get()?, jam_id, attachment.id)?;
+
+    Ok(Json(AttachmentContext::from_model(&attachment)))
+}
+
+/// A file which has been received from the client but not yet stored.
+struct Upload {
+    /// Where the file is sitting, in the attachment storage's upload path.
+    /// This is deleted on drop, which is a harmless no-op once the file has
+    /// been stored and thereby moved elsewhere.
+    file: TempPath,
+
+    /// The name the client gave for the file, made safe for use in a url.
+    name: String,
+
+    /// The MIME type of the file, going only by what's in it.
+    mime_type: String,
+}
+
+/// Streams the `file` field of a multipart request body into a temporary file,
+/// without holding the whole thing in memory.
+async fn receive_upload(
+    attachment_storage: &AttachmentStorage,
+    content_type: &ContentType,
+    data: Data,
+) -> Result<Upload, super::HandlerError> {
+    let boundary = multer::parse_boundary(content_type.to_string())?;
+
+    // read one byte past the limit so that an oversized body is noticed,
+    // rather than silently truncated
+    let reader = data.open((MAX_UPLOAD_BYTES + 1).bytes());
+    let body = futures::stream::unfold(reader, |mut reader| async move {
+        let mut buffer = vec![0u8; 64 * 1024];
+        match reader.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buffer.truncate(n);
+                Some((Ok::<_, std::io::Error>(Bytes::from(buffer)), reader))
+            }
+            Err(e) => Some((Err(e), reader)),
+        }
+    });
+    let mut multipart = multer::Multipart::new(body, boundary);
+
+    while let Some(mut field) = multipart.next_field().await? {
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let name = match field.file_name() {
+            Some(file_name) => sanitize_file_name(file_name),
+            None => {
+                return Err(super::HandlerError::BadRequest(
+                    "The file field has no file name".to_string(),
+                ))
+            }
+        };
+
+        let temp_file = attachment_storage.temp_file()?;
+        let mut file = File::from_std(temp_file.reopen()?);
+        let mut head = Vec::with_capacity(SNIFF_BYTES);
+        let mut size: u64 = 0;
+
+        while let Some(chunk) = field.chunk().await? {
+            size += chunk.len() as u64;
+
+            if size > MAX_UPLOAD_BYTES {
+                return Err(super::HandlerError::PayloadTooLarge);
+            }
+
+            if head.len() < SNIFF_BYTES {
+                let wanted = (SNIFF_BYTES - head.len()).min(chunk.len());
+                head.extend_from_slice(&chunk[..wanted]);
+            }
+
+            file.write_all(&chunk).await?;
+        }
+
+        file.flush().await?;
+        drop(file);
+
+        // the file name says nothing about what's really in the file, so
+        // anything that isn't recognised is served as an opaque download
+        // rather than as, say, html or svg that a browser would run
+        let mime_type = sniff_mime_type(&head)
+            .unwrap_or(OPAQUE_MIME_TYPE)
+            .to_string();
+
+        return Ok(Upload {
+            file: temp_file.into_temp_path(),
+            name,
+            mime_type,
+        });
+    }
+
+    Err(super::HandlerError::BadRequest(
+        "No file was uploaded in the file field".to_string(),
+    ))
+}
+
+/// Records an upload as a new attachment and puts it into storage. If it can't
+/// be stored, the attachment is forgotten again rather than left pointing at
+/// nothing.
+async fn store_upload(
+    pool: &DbPool,
+    attachment_storage: &AttachmentStorage,
+    upload: Upload,
+    owner_user_id: Option<i64>,
+) -> Result<Attachment, super::HandlerError> {
+    let digests = digest_file(&upload.file).await?;
+    let attachment = Attachment::create(
+        &pool.get()?,
+        &upload.name,
+        &upload.mime_type,
+        owner_user_id,
+        &digests,
+    )?;
+
+    if let Err(e) = attachment_storage.store(&upload.file, attachment.id).await
+    {
+        attachment.delete(&pool.get()?)?;
+        return Err(e.into());
+    }
+
+    Ok(attachment)
+}
+
+/// File names end up in urls and in `Content-Disposition` headers, so only the
+/// last path component is kept and anything but a conservative set of
+/// characters is replaced.
+fn sanitize_file_name(file_name: &str) -> String {
+    let base_name = file_name
+        .rsplit(|c| c == '/' || c == '\\')
+        .next()
+        .unwrap_or("");
+    let sanitized: String = base_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let sanitized = sanitized.trim_start_matches('.');
+
+    if sanitized.is_empty() {
+        "file".to_string()
+    } else {
+        sanitized.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_parsing() {
+        assert_eq!(
+            ByteRange::Partial { start: 0, end: 499 },
+            parse_range("bytes=0-499", 1000)
+        );
+        assert_eq!(
+            ByteRange::Partial {
+                start: 500,
+                end: 999
+            },
+            parse_range("bytes=500-", 1000)
+        );
+        assert_eq!(
+            ByteRange::Partial {
+                start: 900,
+                end: 999
+            },
+            parse_range("bytes=-100", 1000)
+        );
+        assert_eq!(
+            ByteRange::Partial {
+                start: 900,
+                end: 999
+            },
+            parse_range("bytes=900-5000", 1000)
+        );
+        assert_eq!(ByteRange::Unsatisfiable, parse_range("bytes=1000-", 1000));
+        assert_eq!(ByteRange::Full, parse_range("bytes=0-1,5-9", 1000));
+        assert_eq!(ByteRange::Full, parse_range("bytes=9-5", 1000));
+        assert_eq!(ByteRange::Full, parse_range("lines=1-2", 1000));
+    }
+
+    #[test]
+    fn test_etag_matching() {
+        assert!(etag_matches("\"abc\"", "\"abc\""));
+        assert!(etag_matches("\"xyz\", W/\"abc\"", "\"abc\""));
+        assert!(etag_matches("*", "\"abc\""));
+        assert!(!etag_matches("\"xyz\"", "\"abc\""));
+    }
+
+    #[test]
+    fn test_disposition_file_name() {
+        assert_eq!("my game.zip", disposition_file_name("my game.zip"));
+        assert_eq!("a_b_c_.png", disposition_file_name("a\"b\\cé.png"));
+    }
+
+    #[test]
+    fn test_inline_mime_types() {
+        assert!(is_inline_mime_type("image/png"));
+        assert!(is_inline_mime_type("image/webp"));
+        assert!(!is_inline_mime_type("image/svg+xml"));
+        assert!(!is_inline_mime_type("text/html"));
+        assert!(!is_inline_mime_type("application/octet-stream"));
+    }
+
+    #[test]
+    fn test_file_name_sanitization() {
+        assert_eq!("my_game.zip", sanitize_file_name("my game.zip"));
+        assert_eq!("shot.png", sanitize_file_name("C:\\Users\\me\\shot.png"));
+        assert_eq!("passwd", sanitize_file_name("../../etc/passwd"));
+        assert_eq!("htaccess", sanitize_file_name(".htaccess"));
+        assert_eq!("file", sanitize_file_name("../"));
+    }
 }
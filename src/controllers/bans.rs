@@ -0,0 +1,55 @@
+use rocket::{get, State};
+use rocket_contrib::templates::Template;
+use serde::Serialize;
+
+use crate::{
+    db::DbPool,
+    models::Permission,
+    template_helpers::{
+        Breadcrumb, Breadcrumbs, BreadcrumbsContext, UserOptional,
+        UserOptionalContext,
+    },
+};
+
+// GET      /banned                 -> the logged in user's ban
+// bans are granted and revoked from the command line, as the banned
+// permission.
+
+#[derive(Debug, Serialize)]
+struct ShowBanContext {
+    auth: UserOptionalContext,
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    reason: Option<String>,
+    banned_at: String,
+}
+
+/// Tells a banned user that they are banned, since when, and why. There's
+/// nothing here for anyone else.
+#[get("/banned")]
+pub async fn show_ban(
+    pool: State<'_, DbPool>,
+    user: UserOptional,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let ban = match user.id() {
+        Some(id) => Permission::find_ban(&conn, id)?,
+        None => None,
+    };
+
+    let ban = match ban {
+        Some(ban) => ban,
+        None => return Err(super::HandlerError::NotFound),
+    };
+
+    let context = ShowBanContext {
+        auth: user.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![Breadcrumb::Home])
+            .to_context(),
+        title: "Banned".to_string(),
+        reason: ban.reason,
+        banned_at: ban.created_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+    };
+
+    Ok(Template::render("banned", &context))
+}
@@ -0,0 +1,222 @@
+//! A stand-in for Github's OAuth endpoints and API, so that logging in can be
+//! tried out without a network connection or a registered Github application.
+//! These routes are only mounted when serving with `--fake-github`, in which
+//! case [`super::gh_oauth::GhCredentials`] point here instead of at Github.
+//! Anyone can log in as anyone at all, so never do this in production!
+use rocket::{
+    get,
+    http::Status,
+    post,
+    request::{FromForm, FromRequest, LenientForm, Outcome, Request},
+    response::Redirect,
+    State,
+};
+use rocket_contrib::{json::Json, templates::Template};
+use serde::Serialize;
+
+use crate::{
+    db::DbPool,
+    models::GhUserRecord,
+    template_helpers::{Breadcrumbs, BreadcrumbsContext},
+};
+
+// GET      /fake_github/login/oauth/authorize?client_id&state  -> pick a user
+// GET      /fake_github/login/oauth/authorize_as?id&login&state
+//                                              -> redirect to /gh_callback
+// POST     /fake_github/login/oauth/access_token               -> token
+// GET      /fake_github/user                   -> the user the token is for
+// the code and the access token are both just `id:login`.
+
+/// Users who can always be logged in as, even with an empty database.
+const SEEDED_USERS: &[(i64, &str)] =
+    &[(1, "fake-admin"), (2, "fake-member"), (3, "fake-newcomer")];
+
+#[derive(Debug, Serialize)]
+struct FakeUserContext {
+    id: i64,
+    login: String,
+    code: String,
+}
+
+#[derive(Debug, Serialize)]
+struct AuthorizeContext {
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    suppress_auth_controls: bool,
+    state: String,
+    users: Vec<FakeUserContext>,
+}
+
+/// Stands in for Github asking the user whether to log in, by letting them
+/// pick who to log in as instead. Offers the seeded users and everyone who
+/// has logged in before.
+#[get("/login/oauth/authorize?<state>")]
+pub async fn authorize(
+    pool: State<'_, DbPool>,
+    state: String,
+) -> Result<Template, super::HandlerError> {
+    let mut users: Vec<(i64, String)> = SEEDED_USERS
+        .iter()
+        .map(|(id, login)| (*id, login.to_string()))
+        .collect();
+    for user in GhUserRecord::find_all(&pool.get()?)? {
+        if !users.iter().any(|(id, _)| *id == user.id) {
+            users.push((user.id, user.login));
+        }
+    }
+
+    let context = AuthorizeContext {
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![]).to_context(),
+        title: "Fake Github".to_string(),
+        suppress_auth_controls: true,
+        state,
+        users: users
+            .into_iter()
+            .map(|(id, login)| FakeUserContext {
+                id,
+                code: code_for(id, &login),
+                login,
+            })
+            .collect(),
+    };
+
+    Ok(Template::render("fake_github_authorize", &context))
+}
+
+/// Logs in as a made-up user, sending them back to the site just as Github
+/// would.
+#[get("/login/oauth/authorize_as?<id>&<login>&<state>")]
+pub fn authorize_as(
+    id: i64,
+    login: String,
+    state: String,
+) -> Result<Redirect, super::HandlerError> {
+    if !is_login(&login) {
+        return Err(super::HandlerError::BadRequest(
+            "A login may only have letters, numbers and dashes".to_string(),
+        ));
+    }
+
+    Ok(Redirect::to(format!(
+        "/gh_callback?code={}&state={}",
+        code_for(id, &login),
+        state
+    )))
+}
+
+#[derive(Debug, FromForm)]
+pub struct AccessTokenFormData {
+    code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccessTokenResponse {
+    access_token: String,
+    token_type: String,
+    scope: String,
+}
+
+/// Exchanges a code for an access token, which is the very same thing.
+#[post("/login/oauth/access_token", data = "<access_token_form_data>")]
+pub fn access_token(
+    access_token_form_data: LenientForm<AccessTokenFormData>,
+) -> Result<Json<AccessTokenResponse>, super::HandlerError> {
+    match parse_code(&access_token_form_data.code) {
+        Some(_) => Ok(Json(AccessTokenResponse {
+            access_token: access_token_form_data.code.clone(),
+            token_type: "bearer".to_string(),
+            scope: "".to_string(),
+        })),
+        None => Err(super::HandlerError::BadRequest(
+            "The code is not one this fake Github gave out".to_string(),
+        )),
+    }
+}
+
+/// Request guard for the user whose access token is in the `Authorization`
+/// header, the same way Github's API takes it.
+pub struct FakeAccessToken {
+    id: i64,
+    login: String,
+}
+
+#[rocket::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for FakeAccessToken {
+    type Error = ();
+
+    async fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let user = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("token "))
+            .and_then(parse_code);
+
+        match user {
+            Some((id, login)) => {
+                Outcome::Success(FakeAccessToken { id, login })
+            }
+            None => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserResponse {
+    id: i64,
+    login: String,
+    avatar_url: String,
+    html_url: String,
+}
+
+/// Describes the user an access token is for.
+#[get("/user")]
+pub fn user(token: FakeAccessToken) -> Json<UserResponse> {
+    Json(UserResponse {
+        id: token.id,
+        html_url: format!("https://github.com/{}", token.login),
+        login: token.login,
+        // something that loads without a network connection
+        avatar_url: "/static/cmdr_only.png".to_string(),
+    })
+}
+
+fn code_for(id: i64, login: &str) -> String {
+    format!("{}:{}", id, login)
+}
+
+/// Gets the user id and login back out of a code or access token.
+fn parse_code(code: &str) -> Option<(i64, String)> {
+    let mut parts = code.splitn(2, ':');
+    let id = parts.next()?.parse().ok()?;
+    let login = parts.next()?;
+
+    if is_login(login) {
+        Some((id, login.to_string()))
+    } else {
+        None
+    }
+}
+
+/// Whether this could be a Github login, which keeps codes and redirects free
+/// of anything that would need escaping.
+fn is_login(login: &str) -> bool {
+    !login.is_empty()
+        && login.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_code() {
+        assert_eq!(
+            Some((12, "fake-admin".to_string())),
+            parse_code(&code_for(12, "fake-admin"))
+        );
+        assert_eq!(None, parse_code("12"));
+        assert_eq!(None, parse_code("twelve:fake-admin"));
+        assert_eq!(None, parse_code("12:"));
+        assert_eq!(None, parse_code("12:fake&admin"));
+    }
+}
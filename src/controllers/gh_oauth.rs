@@ -1,16 +1,29 @@
 use crate::db::DbPool;
-use crate::models::GhUserRecord;
-use crate::template_helpers::{Breadcrumbs, BreadcrumbsContext};
-use reqwest::Client as ReqwestClient;
+use crate::models::{GhUserRecord, Permission, Session};
+use crate::template_helpers::{
+    reset_csrf_token, Breadcrumbs, BreadcrumbsContext, CsrfVerified,
+    UserOptional, SESSION_COOKIE,
+};
+use hex::{decode as hex_decode, encode as hex_encode};
+use hmac::{Hmac, Mac, NewMac};
+use rand::{rngs::OsRng, RngCore};
+use reqwest::{Client as ReqwestClient, Url};
 use rocket::{
-    delete, get, http::Cookie, http::CookieJar, response::Redirect, State,
+    delete, get,
+    http::Cookie,
+    http::CookieJar,
+    request::{FromRequest, Outcome, Request},
+    response::Redirect,
+    uri, State,
 };
 use rocket_contrib::templates::Template;
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::convert::Infallible;
 
-/// Describes the two bits of information needed from Github itself to
-/// successfully complete an OAuth workflow with them. These need to be loaded
-/// when the program starts and "wired" in.
+/// Describes the information needed from Github itself to successfully
+/// complete an OAuth workflow with them, and where to find them. These need to
+/// be loaded when the program starts and "wired" in.
 pub struct GhCredentials {
     /// The github client id. This one gets exposed publicly.
     pub client_id: String,
@@ -18,6 +31,125 @@ pub struct GhCredentials {
     /// The secret key that is known only to us on the server and to Github.
     /// Keep this one private!
     pub client_secret: String,
+
+    /// Where users are sent to authorize us, and where we exchange their code
+    /// for an access token. This is [`GH_OAUTH_BASE_URL`] unless we are
+    /// talking to a stand-in for Github.
+    pub oauth_base_url: String,
+
+    /// Where the Github API lives. This is [`GH_API_BASE_URL`] unless we are
+    /// talking to a stand-in for Github.
+    pub api_base_url: String,
+}
+
+/// Where Github's OAuth endpoints really are.
+pub const GH_OAUTH_BASE_URL: &str = "https://github.com";
+
+/// Where Github's API really is.
+pub const GH_API_BASE_URL: &str = "https://api.github.com";
+
+/// The key which signs the `state` we send through Github's OAuth flow, so
+/// that nobody can forge where a user is sent after they log in. This is
+/// derived from the app secret when the program starts.
+pub struct OAuthStateKey(Vec<u8>);
+
+/// The private cookie holding the nonce of the login in progress, which ties
+/// the `state` Github hands back to the browser that asked for it.
+const OAUTH_NONCE_COOKIE: &str = "gh_oauth_nonce";
+
+type HmacSha256 = Hmac<Sha256>;
+
+impl OAuthStateKey {
+    pub fn new(app_secret: &str) -> Self {
+        OAuthStateKey(format!("gh_oauth_state:{}", app_secret).into_bytes())
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_varkey(&self.0)
+            .expect("HMAC can take a key of any size");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    /// Makes a `state` carrying a nonce and the path to return to, in the
+    /// form `nonce.hex(return_to).hex(signature)`.
+    fn sign(&self, nonce: &str, return_to: &str) -> String {
+        let payload = format!("{}.{}", nonce, hex_encode(return_to));
+        let signature = hex_encode(self.mac(&payload).finalize().into_bytes());
+
+        format!("{}.{}", payload, signature)
+    }
+
+    /// Checks the signature of a `state`, giving back its nonce and the path
+    /// to return to if it is genuine.
+    fn verify(&self, state: &str) -> Option<(String, String)> {
+        let mut parts = state.splitn(3, '.');
+        let nonce = parts.next()?;
+        let return_to = parts.next()?;
+        let signature = hex_decode(parts.next()?).ok()?;
+
+        self.mac(&format!("{}.{}", nonce, return_to))
+            .verify(&signature)
+            .ok()?;
+
+        let return_to = String::from_utf8(hex_decode(return_to).ok()?).ok()?;
+
+        Some((nonce.to_string(), return_to))
+    }
+}
+
+/// Request guard for where to send a user once they have logged in. This is
+/// the `return_to` query parameter if there is one, or otherwise the page
+/// they followed the login link from. Anything that isn't a path on this site
+/// is ignored, leaving just the homepage.
+pub struct ReturnTo(String);
+
+#[rocket::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for ReturnTo {
+    type Error = Infallible;
+
+    async fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let from_query = req
+            .get_query_value::<String>("return_to")
+            .and_then(|r| r.ok());
+        let from_referer = || {
+            let referer = Url::parse(req.headers().get_one("Referer")?).ok()?;
+            let referer_host = match referer.port() {
+                Some(port) => format!("{}:{}", referer.host_str()?, port),
+                None => referer.host_str()?.to_string(),
+            };
+
+            if Some(referer_host.as_str()) != req.headers().get_one("Host") {
+                return None;
+            }
+
+            Some(match referer.query() {
+                Some(query) => format!("{}?{}", referer.path(), query),
+                None => referer.path().to_string(),
+            })
+        };
+
+        let return_to = from_query
+            .or_else(from_referer)
+            .filter(|path| is_return_to_path(path))
+            .unwrap_or_else(|| "/".to_string());
+
+        Outcome::Success(ReturnTo(return_to))
+    }
+}
+
+/// Whether it is safe to redirect to this path after logging in. It must be a
+/// path on this site, which rules out anything a browser might take as a url
+/// to somewhere else, such as `//example.com` or `/\example.com`, and it
+/// mustn't be part of logging in itself.
+pub(super) fn is_return_to_path(path: &str) -> bool {
+    path.starts_with('/')
+        && !path.starts_with("//")
+        && !path.contains('\\')
+        && !path.chars().any(|c| c.is_control())
+        && !path.starts_with("/login")
+        && !path.starts_with("/gh_callback")
+        && !path.starts_with("/logout")
 }
 
 /// Configures a Reqwest client that is compatible with what Github requires of
@@ -34,8 +166,17 @@ pub fn gh_client() -> ReqwestClient {
 /// Presents the login page. This is a simple page with a link to Github.com
 /// which is where users start the authorization process. Other OAuth providers
 /// may be supported in the future... but don't count on it.
+///
+/// The link carries a signed `state`, which Github hands back to
+/// [`gh_callback`] so that it knows the login really started here and where
+/// to send the user afterwards.
 #[get("/login")]
-pub fn login_with_github(gh_credentials: State<GhCredentials>) -> Template {
+pub fn login_with_github(
+    gh_credentials: State<GhCredentials>,
+    oauth_state_key: State<OAuthStateKey>,
+    cookies: &CookieJar<'_>,
+    return_to: ReturnTo,
+) -> Template {
     #[derive(Serialize)]
     struct Context {
         oauth_url: String,
@@ -43,10 +184,16 @@ pub fn login_with_github(gh_credentials: State<GhCredentials>) -> Template {
         suppress_auth_controls: bool,
     };
 
+    let mut nonce = [0u8; 16];
+    OsRng.fill_bytes(&mut nonce);
+    let nonce = hex_encode(nonce);
+    let state = oauth_state_key.sign(&nonce, &return_to.0);
+    cookies.add_private(Cookie::new(OAUTH_NONCE_COOKIE, nonce));
+
     let context = Context {
         oauth_url: format!(
-            "http://github.com/login/oauth/authorize?client_id={}",
-            gh_credentials.client_id
+            "{}/login/oauth/authorize?client_id={}&state={}",
+            gh_credentials.oauth_base_url, gh_credentials.client_id, state
         ),
         breadcrumbs: Breadcrumbs::from_crumbs(vec![]).to_context(),
         suppress_auth_controls: true,
@@ -60,21 +207,53 @@ pub fn login_with_github(gh_credentials: State<GhCredentials>) -> Template {
 /// can use to query the Github API as that user. Since we don't request any
 /// scopes the only thing we can do is query our current identity, which is all
 /// we wanted to do, anyway.
-#[get("/gh_callback?<code>")]
+///
+/// The `state` must be the one [`login_with_github`] gave this same browser,
+/// which stops anyone from logging a victim in as themselves. Once logged in,
+/// the user goes back to the page the state says they came from, unless they
+/// are banned, in which case they're told about it straight away.
+#[get("/gh_callback?<code>&<state>")]
 pub async fn gh_callback(
     gh_credentials: State<'_, GhCredentials>,
     gh_client: State<'_, ReqwestClient>,
     db_pool: State<'_, DbPool>,
+    oauth_state_key: State<'_, OAuthStateKey>,
     cookies: &CookieJar<'_>,
     code: String,
+    state: String,
 ) -> Result<Redirect, super::HandlerError> {
+    let expected_nonce = cookies
+        .get_private(OAUTH_NONCE_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+    cookies.remove_private(Cookie::named(OAUTH_NONCE_COOKIE));
+
+    let return_to = match oauth_state_key.verify(&state) {
+        Some((nonce, return_to))
+            if Some(&nonce) == expected_nonce.as_ref()
+                && is_return_to_path(&return_to) =>
+        {
+            return_to
+        }
+        _ => {
+            return Err(super::HandlerError::BadRequest(
+                "The login could not be verified, please try again".to_string(),
+            ))
+        }
+    };
+
     let user_record =
         auth_with_github(&gh_client, &db_pool, &gh_credentials, &code).await?;
-    let cookie = Cookie::new("gh_user_id", user_record.id.to_string());
+    let conn = db_pool.get()?;
+    let (_, token) = Session::create(&conn, user_record.id)?;
 
-    cookies.add_private(cookie);
+    cookies.add_private(Cookie::new(SESSION_COOKIE, token));
+    reset_csrf_token(cookies);
+
+    if Permission::find_ban(&conn, user_record.id)?.is_some() {
+        return Ok(Redirect::to(uri!(super::bans::show_ban)));
+    }
 
-    Ok(Redirect::to("/"))
+    Ok(Redirect::to(return_to))
 }
 
 /// The response we get back from Github with our access token, which allows us
@@ -115,8 +294,13 @@ async fn auth_with_github(
 ) -> Result<GhUserRecord, super::HandlerError> {
     let authorization =
         get_access_token(&gh_client, &gh_credentials, &code).await?;
-    let user =
-        get_or_update_user_detail(&gh_client, &db_pool, &authorization).await?;
+    let user = get_or_update_user_detail(
+        &gh_client,
+        &db_pool,
+        &gh_credentials,
+        &authorization,
+    )
+    .await?;
 
     Ok(user)
 }
@@ -134,7 +318,10 @@ async fn get_access_token(
     ];
 
     let r = gh_client
-        .post("https://github.com/login/oauth/access_token")
+        .post(&format!(
+            "{}/login/oauth/access_token",
+            gh_credentials.oauth_base_url
+        ))
         .form(&params)
         .header("Accept", "application/json")
         .send()
@@ -166,9 +353,43 @@ struct UserResponse {
 async fn get_or_update_user_detail(
     gh_client: &ReqwestClient,
     db_pool: &DbPool,
+    gh_credentials: &GhCredentials,
     authorization: &AuthorizationResponse,
 ) -> Result<GhUserRecord, super::HandlerError> {
-    let user = get_user_detail(&gh_client, &authorization.access_token).await?;
+    let user = get_user_detail(
+        &gh_client,
+        &gh_credentials,
+        &authorization.access_token,
+    )
+    .await?;
+    let gh_user_record = GhUserRecord::find_and_update(
+        &db_pool.get()?,
+        user.id,
+        &user.login,
+        &user.avatar_url,
+        &user.html_url,
+    )?;
+
+    Ok(gh_user_record)
+}
+
+/// Refreshes our cache of a user's details from Github, by their id so that
+/// it still works after they've changed their login. This doesn't need the
+/// user to be logged in, as the details are public.
+pub async fn sync_user_detail(
+    gh_client: &ReqwestClient,
+    db_pool: &DbPool,
+    api_base_url: &str,
+    gh_user_id: i64,
+) -> Result<GhUserRecord, super::HandlerError> {
+    let user: UserResponse = gh_client
+        .get(&format!("{}/user/{}", api_base_url, gh_user_id))
+        .header("Accept", "application/json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
     let gh_user_record = GhUserRecord::find_and_update(
         &db_pool.get()?,
         user.id,
@@ -183,10 +404,11 @@ async fn get_or_update_user_detail(
 /// Gets the user's details from Github.
 async fn get_user_detail(
     gh_client: &ReqwestClient,
+    gh_credentials: &GhCredentials,
     access_token: &String,
 ) -> Result<UserResponse, super::HandlerError> {
     let r = gh_client
-        .get("https://api.github.com/user")
+        .get(&format!("{}/user", gh_credentials.api_base_url))
         .header("Authorization", format!("token {}", access_token))
         .header("Accept", "application/json")
         .send()
@@ -197,10 +419,43 @@ async fn get_user_detail(
     Ok(r)
 }
 
-/// Logs the user out. Pitches all the cookies we set.
+/// Logs the user out. Ends their session and pitches all the cookies we set.
 #[delete("/logout")]
-pub async fn logout(cookies: &CookieJar<'_>) -> Template {
-    cookies.remove_private(Cookie::named("gh_user_id"));
+pub async fn logout(
+    db_pool: State<'_, DbPool>,
+    cookies: &CookieJar<'_>,
+    _csrf: CsrfVerified,
+) -> Result<Template, super::HandlerError> {
+    if let Some(cookie) = cookies.get_private(SESSION_COOKIE) {
+        let conn = db_pool.get()?;
+        if let Some(session) = Session::find_by_token(&conn, cookie.value())? {
+            session.delete(&conn)?;
+        }
+    }
+
+    Ok(forget_session(cookies))
+}
+
+/// Logs the user out of every browser they are logged in on, such as when
+/// they've lost a laptop. Banned users may do this too.
+#[delete("/sessions")]
+pub async fn logout_everywhere(
+    db_pool: State<'_, DbPool>,
+    user: UserOptional,
+    cookies: &CookieJar<'_>,
+    _csrf: CsrfVerified,
+) -> Result<Template, super::HandlerError> {
+    if let Some(id) = user.id() {
+        Session::delete_by_gh_user_id(&db_pool.get()?, id)?;
+    }
+
+    Ok(forget_session(cookies))
+}
+
+/// Pitches the cookies of a session which has ended, and says goodbye.
+fn forget_session(cookies: &CookieJar<'_>) -> Template {
+    cookies.remove_private(Cookie::named(SESSION_COOKIE));
+    reset_csrf_token(cookies);
 
     #[derive(Debug, Serialize)]
     struct Context {
@@ -213,3 +468,37 @@ pub async fn logout(cookies: &CookieJar<'_>) -> Template {
 
     Template::render("logout", &context)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oauth_state_round_trip() {
+        let key = OAuthStateKey::new("secret");
+        let state = key.sign("abc123", "/jams/1/my-jam?page=2");
+
+        assert_eq!(
+            Some(("abc123".to_string(), "/jams/1/my-jam?page=2".to_string())),
+            key.verify(&state)
+        );
+        assert_eq!(None, OAuthStateKey::new("other secret").verify(&state));
+
+        let forged = format!("abc123.{}.{}", hex_encode("/evil"), "00");
+        assert_eq!(None, key.verify(&forged));
+        assert_eq!(None, key.verify("garbage"));
+    }
+
+    #[test]
+    fn test_is_return_to_path() {
+        assert!(is_return_to_path("/"));
+        assert!(is_return_to_path("/jams/1/my-jam?page=2"));
+        assert!(!is_return_to_path("https://example.com/"));
+        assert!(!is_return_to_path("//example.com/"));
+        assert!(!is_return_to_path("/\\example.com/"));
+        assert!(!is_return_to_path("/jams\n"));
+        assert!(!is_return_to_path("/login"));
+        assert!(!is_return_to_path("/gh_callback?code=x"));
+        assert!(!is_return_to_path(""));
+    }
+}
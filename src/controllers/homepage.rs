@@ -1,4 +1,6 @@
-use crate::template_helpers::{JamContext, UserOptional, UserOptionalContext};
+use crate::template_helpers::{
+    JamContext, UserOptional, UserOptionalContext, ViewerTimezone,
+};
 use crate::{db::DbPool, models::Jam};
 use rocket::{get, State};
 use rocket_contrib::templates::Template;
@@ -8,6 +10,7 @@ use serde::Serialize;
 pub fn homepage(
     pool: State<'_, DbPool>,
     user: UserOptional,
+    viewer_timezone: ViewerTimezone,
     show_all_jams: Option<bool>,
 ) -> Result<Template, super::HandlerError> {
     let conn = pool.get()?;
@@ -16,7 +19,12 @@ pub fn homepage(
     // load the first three approved jams
     let mut jams = Vec::new();
     for j in Jam::find_all(&conn, !should_show_all_jams, 0, 3)? {
-        jams.push(JamContext::from_model(&conn, &j, false)?);
+        jams.push(JamContext::from_model(
+            &conn,
+            &j,
+            false,
+            viewer_timezone.get(),
+        )?);
     }
 
     println!("{:?}", jams);
@@ -1,7 +1,439 @@
+use diesel::Connection;
+use rocket::{delete, get, post, uri, State};
+use rocket::{
+    request::{FromForm, LenientForm},
+    response::Redirect,
+};
+use rocket_contrib::templates::Template;
+use serde::Serialize;
+
+use super::TemplateOrRedirect;
+use crate::{
+    db::{DbConn, DbPool},
+    models::{ApprovalState, ApprovalSubject, Jam, JamEntry, JamEntryUpdate},
+    template_helpers::{
+        Admin, ApprovalTransitionContext, AuthContext, Breadcrumb, Breadcrumbs,
+        BreadcrumbsContext, CsrfVerified, JamContext, JamEntryContext,
+        JamEntryUpdateContext, RequirePermission, UserOptional,
+        UserOptionalContext, UserRequired, ViewerTimezone, VoteCategoryContext,
+    },
+};
+
 // CREATE   /jams/:jam_id/entries               -> jam_entry_id     USERS ONLY
+// GET      /jams/:jam_id/entries/:jam_entry_id/edit                ADMIN/OWNER ONLY
 // UPDATE   /jams/:jam_id/entries/:jam_entry_id -> Result<()>       ADMIN/OWNER ONLY
-// marking a jam as published is admin-only.
+// only owners submit an entry, and only moderators approve or reject it.
 // GET      /jams/:jam_id/:jam_slug/entries     -> Vec<JamEntries>  All when admin,
 // GET      /jams/:jam_id/:jam_slug/:jam_entry_id/:jam_entry_slug   otherwise only
 //                                              -> Jam              published
 // DELETE   /jams/:jam_id/entries/:jam_entry_id -> Result<()>       ADMIN ONLY
+// entries are only created and changed until the jam ends, except by admins.
+
+/// Creates a new blank entry in a jam for the logged in user and immediately
+/// redirects to its edit page. Only admins may create entries in jams which
+/// are not yet approved, or which have ended.
+#[post("/jams/<jam_id>/entries")]
+pub async fn create_jam_entry(
+    pool: State<'_, DbPool>,
+    user: UserRequired,
+    _csrf: CsrfVerified,
+    jam_id: i32,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let jam = match Jam::find_by_id(&conn, jam_id)? {
+        Some(jam) => jam,
+        None => return Err(super::HandlerError::NotFound),
+    };
+
+    if jam.approval_state != ApprovalState::Approved && !user.is_admin() {
+        return Err(super::HandlerError::NotFound);
+    }
+
+    check_accepts_entries(&user, &jam)?;
+
+    let jam_entry = JamEntry::create(&conn, jam.id, user.id())?;
+
+    Ok(Redirect::to(uri!(edit_jam_entry: jam.id, jam_entry.id)))
+}
+
+#[derive(Debug, Serialize)]
+struct EditJamEntryContext {
+    auth: AuthContext,
+    breadcrumbs: BreadcrumbsContext,
+    jam: JamContext,
+    jam_entry: JamEntryContext,
+    approval_states: Vec<String>,
+    approval_transitions: Vec<ApprovalTransitionContext>,
+    can_delete: bool,
+}
+
+/// Renders out a form that the owner of the entry, or an admin, can use to
+/// edit it.
+#[get("/jams/<jam_id>/entries/<jam_entry_id>/edit")]
+pub async fn edit_jam_entry(
+    pool: State<'_, DbPool>,
+    user: UserRequired,
+    viewer_timezone: ViewerTimezone,
+    jam_id: i32,
+    jam_entry_id: i32,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry) = find_jam_and_entry(&conn, jam_id, jam_entry_id)?;
+
+    if !jam_entry.is_submitted_by(user.id()) && !user.is_admin() {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    let context =
+        edit_context(&conn, &user, &viewer_timezone, &jam, &jam_entry)?;
+
+    Ok(Template::render("edit_jam_entry", &context))
+}
+
+#[derive(Debug, FromForm)]
+pub struct JamEntryFormData {
+    title: String,
+    slug: String,
+    summary: String,
+    // summary_attachment_id to be set by ajax
+    // rich_text_id is already set, not changing that through web calls
+    rich_text_content: String,
+    approval_state: String,
+}
+
+/// Saves changes to an entry. Owners may move their entry between draft and
+/// submitted, but only a moderator may approve or reject it, which happens in
+/// the moderation queue.
+#[post(
+    "/jams/<jam_id>/entries/<jam_entry_id>",
+    data = "<jam_entry_form_data>"
+)]
+pub async fn update_jam_entry(
+    pool: State<'_, DbPool>,
+    user: UserRequired,
+    viewer_timezone: ViewerTimezone,
+    _csrf: CsrfVerified,
+    jam_id: i32,
+    jam_entry_id: i32,
+    jam_entry_form_data: LenientForm<JamEntryFormData>,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+
+    // do operations in a transaction so that all the updates roll back on
+    // failure
+    let (jam, jam_entry) = conn
+        .transaction::<(Jam, JamEntry), super::HandlerError, _>(|| {
+            let (jam, mut jam_entry) =
+                find_jam_and_entry(&conn, jam_id, jam_entry_id)?;
+
+            if !jam_entry.is_submitted_by(user.id()) && !user.is_admin() {
+                return Err(super::HandlerError::Forbidden);
+            }
+
+            check_accepts_entries(&user, &jam)?;
+
+            let approval_state = ApprovalState::from_human_str(
+                &jam_entry_form_data.approval_state,
+            )?;
+
+            let mut rich_text = jam_entry.load_rich_text(&conn)?;
+
+            jam_entry.title = jam_entry_form_data.title.clone();
+            jam_entry.slug = jam_entry_form_data.slug.clone();
+            jam_entry.summary = jam_entry_form_data.summary.clone();
+            rich_text.content = jam_entry_form_data.rich_text_content.clone();
+
+            // the requested state is applied first, so that an owner taking
+            // an approved entry back to a draft doesn't resubmit it instead
+            let roles = super::EDIT_FORM_APPROVAL_ROLES;
+            jam_entry.transition(
+                &conn,
+                approval_state,
+                user.id(),
+                &roles,
+                None,
+            )?;
+            jam_entry.revise(&conn, &rich_text, user.id(), &roles)?;
+            Ok((jam, jam_entry))
+        })?;
+
+    let context =
+        edit_context(&conn, &user, &viewer_timezone, &jam, &jam_entry)?;
+
+    Ok(Template::render("edit_jam_entry", &context))
+}
+
+/// Deletes an entry outright, along with everything it owns.
+#[delete("/jams/<jam_id>/entries/<jam_entry_id>")]
+pub async fn delete_jam_entry(
+    pool: State<'_, DbPool>,
+    _admin_only: RequirePermission<Admin>,
+    _csrf: CsrfVerified,
+    jam_id: i32,
+    jam_entry_id: i32,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry) = find_jam_and_entry(&conn, jam_id, jam_entry_id)?;
+
+    jam_entry.delete(&conn)?;
+
+    Ok(Redirect::to(
+        uri!(list_jam_entries: jam.id, jam.slug.as_str()),
+    ))
+}
+
+#[derive(Debug, Serialize)]
+struct ListJamEntriesContext {
+    auth: UserOptionalContext,
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    jam: JamContext,
+    jam_entries: Vec<JamEntryContext>,
+}
+
+/// Lists the entries in a jam. Everyone can see approved entries, logged in
+/// users can also see their own entries, and admins can see everything.
+#[get("/jams/<jam_id>/<jam_slug>/entries")]
+pub async fn list_jam_entries(
+    pool: State<'_, DbPool>,
+    user: UserOptional,
+    viewer_timezone: ViewerTimezone,
+    jam_id: i32,
+    jam_slug: String,
+) -> Result<TemplateOrRedirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let jam = match Jam::find_by_id(&conn, jam_id)? {
+        Some(jam) => jam,
+        None => return Err(super::HandlerError::NotFound),
+    };
+
+    if jam.approval_state != ApprovalState::Approved && !user.is_admin() {
+        return Err(super::HandlerError::NotFound);
+    }
+
+    if jam.slug != jam_slug {
+        return Ok(TemplateOrRedirect::Redirect(Redirect::to(uri!(
+            list_jam_entries: jam.id,
+            jam.slug.as_str()
+        ))));
+    }
+
+    let mut jam_entries = Vec::new();
+    for e in
+        JamEntry::find_by_jam_id(&conn, jam.id, !user.is_admin(), user.id())?
+    {
+        jam_entries.push(JamEntryContext::from_model(&conn, &e, false)?);
+    }
+
+    let context = ListJamEntriesContext {
+        auth: user.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::Jams,
+            Breadcrumb::from_jam(&jam),
+            Breadcrumb::entries_of_jam(&jam),
+        ])
+        .to_context(),
+        title: format!("Entries in {}", jam.title),
+        jam: JamContext::from_model(&conn, &jam, false, viewer_timezone.get())?,
+        jam_entries,
+    };
+
+    Ok(TemplateOrRedirect::Template(Template::render(
+        "jam_entries",
+        &context,
+    )))
+}
+
+#[derive(Debug, Serialize)]
+struct ShowJamEntryContext {
+    auth: UserOptionalContext,
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    jam: JamContext,
+    jam_entry: JamEntryContext,
+    jam_entry_updates: Vec<JamEntryUpdateContext>,
+    vote_categories: Vec<VoteCategoryContext>,
+    /// Whether the viewer may rate this entry right now.
+    can_vote: bool,
+}
+
+/// Shows an entry. Entries which are not approved are only visible to their
+/// owner and to admins. Stale slugs for either the jam or the entry redirect
+/// to the canonical url. While the jam's voting is open, the viewer's ratings
+/// are shown so they can change them, and once it's over everyone's are.
+#[get("/jams/<jam_id>/<jam_slug>/<jam_entry_id>/<jam_entry_slug>", rank = 2)]
+pub async fn show_jam_entry(
+    pool: State<'_, DbPool>,
+    user: UserOptional,
+    viewer_timezone: ViewerTimezone,
+    jam_id: i32,
+    jam_slug: String,
+    jam_entry_id: i32,
+    jam_entry_slug: String,
+) -> Result<TemplateOrRedirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry) = find_jam_and_entry(&conn, jam_id, jam_entry_id)?;
+
+    if !can_view(&conn, &user, &jam, &jam_entry)? {
+        return Err(super::HandlerError::NotFound);
+    }
+
+    if jam.slug != jam_slug || jam_entry.slug != jam_entry_slug {
+        return Ok(TemplateOrRedirect::Redirect(Redirect::to(uri!(
+            show_jam_entry: jam.id,
+            jam.slug.as_str(),
+            jam_entry.id,
+            jam_entry.slug.as_str()
+        ))));
+    }
+
+    // the owner is shown their drafts alongside everything else, so they can
+    // see what their devlog will look like
+    let mut jam_entry_updates = Vec::new();
+    for u in JamEntryUpdate::find_by_jam_entry_id(
+        &conn,
+        jam_entry.id,
+        !can_edit(&user, &jam_entry),
+    )? {
+        jam_entry_updates
+            .push(JamEntryUpdateContext::from_model(&conn, &u, false)?);
+    }
+
+    let phase = jam.phase();
+    let can_vote = match user.id() {
+        Some(uid) if !user.is_banned() => {
+            jam.approval_state == ApprovalState::Approved
+                && jam_entry.approval_state == ApprovalState::Approved
+                && !jam_entry.is_submitter_banned(&conn)?
+                && super::jam_entry_votes::check_can_vote(
+                    phase,
+                    jam_entry.is_submitted_by(uid),
+                )
+                .is_ok()
+        }
+        _ => false,
+    };
+    let vote_categories = VoteCategoryContext::for_jam_entry(
+        &conn,
+        jam_entry.id,
+        if can_vote { user.id() } else { None },
+        phase.shows_vote_results(),
+    )?;
+
+    let context = ShowJamEntryContext {
+        auth: user.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::Jams,
+            Breadcrumb::from_jam(&jam),
+            Breadcrumb::entries_of_jam(&jam),
+            Breadcrumb::from_jam_entry(&jam, &jam_entry),
+        ])
+        .to_context(),
+        title: jam_entry.title.clone(),
+        jam: JamContext::from_model(&conn, &jam, false, viewer_timezone.get())?,
+        jam_entry: JamEntryContext::from_model(&conn, &jam_entry, true)?,
+        jam_entry_updates,
+        vote_categories,
+        can_vote,
+    };
+
+    Ok(TemplateOrRedirect::Template(Template::render(
+        "jam_entry",
+        &context,
+    )))
+}
+
+/// Whether the user can see an entry. Everyone can see approved entries in
+/// approved jams unless their submitter is banned, and the entry's owner,
+/// admins and moderators can see it regardless.
+pub(super) fn can_view(
+    conn: &DbConn,
+    user: &UserOptional,
+    jam: &Jam,
+    jam_entry: &JamEntry,
+) -> Result<bool, super::HandlerError> {
+    if can_edit(user, jam_entry) || user.can_moderate() {
+        return Ok(true);
+    }
+
+    Ok(jam.approval_state == ApprovalState::Approved
+        && jam_entry.approval_state == ApprovalState::Approved
+        && !jam_entry.is_submitter_banned(conn)?)
+}
+
+/// Whether the user can change an entry, which is to say they own it or are
+/// an admin.
+pub(super) fn can_edit(user: &UserOptional, jam_entry: &JamEntry) -> bool {
+    user.id()
+        .map(|uid| jam_entry.is_submitted_by(uid))
+        .unwrap_or(false)
+        || user.is_admin()
+}
+
+/// Refuses to let anyone but an admin make or change an entry once the jam's
+/// submission window has closed.
+fn check_accepts_entries(
+    user: &UserRequired,
+    jam: &Jam,
+) -> Result<(), super::HandlerError> {
+    if jam.phase().accepts_entries() || user.is_admin() {
+        Ok(())
+    } else {
+        Err(super::HandlerError::BadRequest(
+            "This jam has ended, so its entries can no longer be changed"
+                .to_string(),
+        ))
+    }
+}
+
+/// Finds a jam and one of its entries, treating an entry which belongs to some
+/// other jam as though it doesn't exist.
+pub(super) fn find_jam_and_entry(
+    conn: &DbConn,
+    jam_id: i32,
+    jam_entry_id: i32,
+) -> Result<(Jam, JamEntry), super::HandlerError> {
+    let jam = match Jam::find_by_id(conn, jam_id)? {
+        Some(jam) => jam,
+        None => return Err(super::HandlerError::NotFound),
+    };
+    let jam_entry = match JamEntry::find_by_id(conn, jam_entry_id)? {
+        Some(jam_entry) if jam_entry.jam_id == jam.id => jam_entry,
+        _ => return Err(super::HandlerError::NotFound),
+    };
+
+    Ok((jam, jam_entry))
+}
+
+fn edit_context(
+    conn: &DbConn,
+    user: &UserRequired,
+    viewer_timezone: &ViewerTimezone,
+    jam: &Jam,
+    jam_entry: &JamEntry,
+) -> Result<EditJamEntryContext, super::HandlerError> {
+    Ok(EditJamEntryContext {
+        auth: user.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::Jams,
+            Breadcrumb::from_jam(jam),
+            Breadcrumb::entries_of_jam(jam),
+            Breadcrumb::from_jam_entry(jam, jam_entry),
+        ])
+        .to_context(),
+        jam: JamContext::from_model(conn, jam, false, viewer_timezone.get())?,
+        jam_entry: JamEntryContext::from_model(conn, jam_entry, false)?,
+        approval_states: super::approval_state_names(
+            jam_entry.approval_state,
+            &super::EDIT_FORM_APPROVAL_ROLES,
+        ),
+        approval_transitions: ApprovalTransitionContext::for_subject(
+            conn,
+            ApprovalSubject::JamEntry(jam_entry.id),
+        )?,
+        can_delete: user.is_admin(),
+    })
+}
@@ -0,0 +1,437 @@
+use diesel::Connection;
+use rocket::{delete, get, post, uri, State};
+use rocket::{
+    request::{FromForm, LenientForm},
+    response::Redirect,
+};
+use rocket_contrib::templates::Template;
+use serde::Serialize;
+
+use super::jam_entries::{can_edit, can_view, find_jam_and_entry};
+use super::TemplateOrRedirect;
+use crate::{
+    db::{DbConn, DbPool},
+    models::{
+        ApprovalState, ApprovalSubject, Jam, JamEntry, JamEntryUpdate,
+        JamEntryUpdateContent, JamPhase,
+    },
+    template_helpers::{
+        ApprovalTransitionContext, AuthContext, Breadcrumb, Breadcrumbs,
+        BreadcrumbsContext, CsrfVerified, JamContext, JamEntryContext,
+        JamEntryUpdateContext, UserOptional, UserOptionalContext, UserRequired,
+        ViewerTimezone,
+    },
+};
+
+// CREATE   /jams/:jam_id/entries/:jam_entry_id/updates         OWNER ONLY
+// GET      /jams/:jam_id/entries/:jam_entry_id/updates/:id/edit
+//                                                              ADMIN/OWNER ONLY
+// UPDATE   /jams/:jam_id/entries/:jam_entry_id/updates/:id     ADMIN/OWNER ONLY
+// DELETE   /jams/:jam_id/entries/:jam_entry_id/updates/:id     ADMIN/OWNER ONLY
+// GET      /jams/:jam_id/:jam_slug/:jam_entry_id/:jam_entry_slug/updates/:id/:slug
+//                                                              All when admin or
+//                                                              owner, otherwise
+//                                                              only published
+// the list of updates is the devlog shown on the jam entry page itself.
+// updates are only posted while the jam is on, though they can be changed
+// afterwards.
+
+#[derive(Debug, FromForm)]
+pub struct NewJamEntryUpdateFormData {
+    /// When given, the update links off-site to this url rather than being
+    /// written here.
+    external_content_url: Option<String>,
+}
+
+/// Creates a new draft update on an entry and immediately redirects to its
+/// edit page. Only the owner of an entry may post updates to it, and only while
+/// the jam is on.
+#[post(
+    "/jams/<jam_id>/entries/<jam_entry_id>/updates",
+    data = "<new_jam_entry_update_form_data>"
+)]
+pub async fn create_jam_entry_update(
+    pool: State<'_, DbPool>,
+    user: UserRequired,
+    _csrf: CsrfVerified,
+    jam_id: i32,
+    jam_entry_id: i32,
+    new_jam_entry_update_form_data: LenientForm<NewJamEntryUpdateFormData>,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry) = find_jam_and_entry(&conn, jam_id, jam_entry_id)?;
+
+    if !jam_entry.is_submitted_by(user.id()) {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    check_accepts_updates(jam.phase())?;
+
+    let content = match &new_jam_entry_update_form_data.external_content_url {
+        Some(url) if !url.trim().is_empty() => {
+            JamEntryUpdateContent::External(url.clone())
+        }
+        _ => JamEntryUpdateContent::RichText,
+    };
+
+    let jam_entry_update =
+        JamEntryUpdate::create(&conn, jam_entry.id, content)?;
+
+    Ok(Redirect::to(uri!(
+        edit_jam_entry_update: jam.id,
+        jam_entry.id,
+        jam_entry_update.id
+    )))
+}
+
+#[derive(Debug, Serialize)]
+struct EditJamEntryUpdateContext {
+    auth: AuthContext,
+    breadcrumbs: BreadcrumbsContext,
+    jam: JamContext,
+    jam_entry: JamEntryContext,
+    jam_entry_update: JamEntryUpdateContext,
+    approval_states: Vec<String>,
+    approval_transitions: Vec<ApprovalTransitionContext>,
+}
+
+/// Renders out a form that the owner of the entry, or an admin, can use to
+/// edit one of its updates.
+#[get(
+    "/jams/<jam_id>/entries/<jam_entry_id>/updates/<jam_entry_update_id>/edit"
+)]
+pub async fn edit_jam_entry_update(
+    pool: State<'_, DbPool>,
+    user: UserRequired,
+    viewer_timezone: ViewerTimezone,
+    jam_id: i32,
+    jam_entry_id: i32,
+    jam_entry_update_id: i32,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry, jam_entry_update) = find_jam_entry_and_update(
+        &conn,
+        jam_id,
+        jam_entry_id,
+        jam_entry_update_id,
+    )?;
+
+    if !jam_entry.is_submitted_by(user.id()) && !user.is_admin() {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    let context = edit_context(
+        &conn,
+        &user,
+        &viewer_timezone,
+        &jam,
+        &jam_entry,
+        &jam_entry_update,
+    )?;
+
+    Ok(Template::render("edit_jam_entry_update", &context))
+}
+
+#[derive(Debug, FromForm)]
+pub struct JamEntryUpdateFormData {
+    title: String,
+    slug: String,
+    summary: String,
+    // only one of these two is present, depending on where the update lives
+    rich_text_content: Option<String>,
+    external_content_url: Option<String>,
+    approval_state: String,
+}
+
+/// Saves changes to an update. Whether the update lives on the site or off of
+/// it is fixed when it is created, so only the matching content field is
+/// taken from the form.
+#[post(
+    "/jams/<jam_id>/entries/<jam_entry_id>/updates/<jam_entry_update_id>",
+    data = "<jam_entry_update_form_data>"
+)]
+pub async fn update_jam_entry_update(
+    pool: State<'_, DbPool>,
+    user: UserRequired,
+    viewer_timezone: ViewerTimezone,
+    _csrf: CsrfVerified,
+    jam_id: i32,
+    jam_entry_id: i32,
+    jam_entry_update_id: i32,
+    jam_entry_update_form_data: LenientForm<JamEntryUpdateFormData>,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+
+    // do operations in a transaction so that all the updates roll back on
+    // failure
+    let (jam, jam_entry, jam_entry_update) = conn
+        .transaction::<_, super::HandlerError, _>(|| {
+            let (jam, jam_entry, mut jam_entry_update) =
+                find_jam_entry_and_update(
+                    &conn,
+                    jam_id,
+                    jam_entry_id,
+                    jam_entry_update_id,
+                )?;
+
+            if !jam_entry.is_submitted_by(user.id()) && !user.is_admin() {
+                return Err(super::HandlerError::Forbidden);
+            }
+
+            let approval_state = ApprovalState::from_human_str(
+                &jam_entry_update_form_data.approval_state,
+            )?;
+
+            jam_entry_update.title = jam_entry_update_form_data.title.clone();
+            jam_entry_update.slug = jam_entry_update_form_data.slug.clone();
+            jam_entry_update.summary =
+                jam_entry_update_form_data.summary.clone();
+
+            if jam_entry_update.external_content_url.is_some() {
+                if let Some(url) =
+                    &jam_entry_update_form_data.external_content_url
+                {
+                    jam_entry_update.external_content_url = Some(url.clone());
+                }
+            }
+
+            let mut rich_text = jam_entry_update.load_rich_text(&conn)?;
+            if let (Some(rich_text), Some(content)) = (
+                &mut rich_text,
+                &jam_entry_update_form_data.rich_text_content,
+            ) {
+                rich_text.content = content.clone();
+            }
+
+            // the requested state is applied first, so that an owner taking
+            // an approved update back to a draft doesn't resubmit it instead
+            let roles = super::EDIT_FORM_APPROVAL_ROLES;
+            jam_entry_update.transition(
+                &conn,
+                approval_state,
+                user.id(),
+                &roles,
+                None,
+            )?;
+            jam_entry_update.revise(
+                &conn,
+                rich_text.as_ref(),
+                user.id(),
+                &roles,
+            )?;
+            Ok((jam, jam_entry, jam_entry_update))
+        })?;
+
+    let context = edit_context(
+        &conn,
+        &user,
+        &viewer_timezone,
+        &jam,
+        &jam_entry,
+        &jam_entry_update,
+    )?;
+
+    Ok(Template::render("edit_jam_entry_update", &context))
+}
+
+/// Deletes an update outright. Owners may take back their own updates.
+#[delete("/jams/<jam_id>/entries/<jam_entry_id>/updates/<jam_entry_update_id>")]
+pub async fn delete_jam_entry_update(
+    pool: State<'_, DbPool>,
+    user: UserRequired,
+    _csrf: CsrfVerified,
+    jam_id: i32,
+    jam_entry_id: i32,
+    jam_entry_update_id: i32,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry, jam_entry_update) = find_jam_entry_and_update(
+        &conn,
+        jam_id,
+        jam_entry_id,
+        jam_entry_update_id,
+    )?;
+
+    if !jam_entry.is_submitted_by(user.id()) && !user.is_admin() {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    jam_entry_update.delete(&conn)?;
+
+    Ok(Redirect::to(uri!(
+        super::jam_entries::show_jam_entry: jam.id,
+        jam.slug.as_str(),
+        jam_entry.id,
+        jam_entry.slug.as_str()
+    )))
+}
+
+#[derive(Debug, Serialize)]
+struct ShowJamEntryUpdateContext {
+    auth: UserOptionalContext,
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    jam: JamContext,
+    jam_entry: JamEntryContext,
+    jam_entry_update: JamEntryUpdateContext,
+}
+
+/// Shows an update. Updates which are not approved are only visible to the
+/// owner of the entry and to admins. Stale slugs redirect to the canonical
+/// url.
+#[get(
+    "/jams/<jam_id>/<jam_slug>/<jam_entry_id>/<jam_entry_slug>/updates/<jam_entry_update_id>/<jam_entry_update_slug>",
+    rank = 2
+)]
+pub async fn show_jam_entry_update(
+    pool: State<'_, DbPool>,
+    user: UserOptional,
+    viewer_timezone: ViewerTimezone,
+    jam_id: i32,
+    jam_slug: String,
+    jam_entry_id: i32,
+    jam_entry_slug: String,
+    jam_entry_update_id: i32,
+    jam_entry_update_slug: String,
+) -> Result<TemplateOrRedirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry, jam_entry_update) = find_jam_entry_and_update(
+        &conn,
+        jam_id,
+        jam_entry_id,
+        jam_entry_update_id,
+    )?;
+
+    if !can_view(&conn, &user, &jam, &jam_entry)?
+        || (jam_entry_update.approval_state != ApprovalState::Approved
+            && !can_edit(&user, &jam_entry)
+            && !user.can_moderate())
+    {
+        return Err(super::HandlerError::NotFound);
+    }
+
+    if jam.slug != jam_slug
+        || jam_entry.slug != jam_entry_slug
+        || jam_entry_update.slug != jam_entry_update_slug
+    {
+        return Ok(TemplateOrRedirect::Redirect(Redirect::to(uri!(
+            show_jam_entry_update: jam.id,
+            jam.slug.as_str(),
+            jam_entry.id,
+            jam_entry.slug.as_str(),
+            jam_entry_update.id,
+            jam_entry_update.slug.as_str()
+        ))));
+    }
+
+    let context = ShowJamEntryUpdateContext {
+        auth: user.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::Jams,
+            Breadcrumb::from_jam(&jam),
+            Breadcrumb::entries_of_jam(&jam),
+            Breadcrumb::from_jam_entry(&jam, &jam_entry),
+            Breadcrumb::from_jam_entry_update(
+                &jam,
+                &jam_entry,
+                &jam_entry_update,
+            ),
+        ])
+        .to_context(),
+        title: jam_entry_update.title.clone(),
+        jam: JamContext::from_model(&conn, &jam, false, viewer_timezone.get())?,
+        jam_entry: JamEntryContext::from_model(&conn, &jam_entry, false)?,
+        jam_entry_update: JamEntryUpdateContext::from_model(
+            &conn,
+            &jam_entry_update,
+            true,
+        )?,
+    };
+
+    Ok(TemplateOrRedirect::Template(Template::render(
+        "jam_entry_update",
+        &context,
+    )))
+}
+
+/// Finds a jam, one of its entries, and one of that entry's updates, treating
+/// anything which doesn't belong to its parent as though it doesn't exist.
+fn find_jam_entry_and_update(
+    conn: &DbConn,
+    jam_id: i32,
+    jam_entry_id: i32,
+    jam_entry_update_id: i32,
+) -> Result<(Jam, JamEntry, JamEntryUpdate), super::HandlerError> {
+    let (jam, jam_entry) = find_jam_and_entry(conn, jam_id, jam_entry_id)?;
+    let jam_entry_update =
+        match JamEntryUpdate::find_by_id(conn, jam_entry_update_id)? {
+            Some(u) if u.jam_entry_id == jam_entry.id => u,
+            _ => return Err(super::HandlerError::NotFound),
+        };
+
+    Ok((jam, jam_entry, jam_entry_update))
+}
+
+/// Refuses to post an update to an entry unless its jam is on, which is when
+/// the devlog is being written.
+fn check_accepts_updates(phase: JamPhase) -> Result<(), super::HandlerError> {
+    if phase.accepts_updates() {
+        Ok(())
+    } else {
+        Err(super::HandlerError::BadRequest(
+            "Updates can only be posted while the jam is on".to_string(),
+        ))
+    }
+}
+
+fn edit_context(
+    conn: &DbConn,
+    user: &UserRequired,
+    viewer_timezone: &ViewerTimezone,
+    jam: &Jam,
+    jam_entry: &JamEntry,
+    jam_entry_update: &JamEntryUpdate,
+) -> Result<EditJamEntryUpdateContext, super::HandlerError> {
+    Ok(EditJamEntryUpdateContext {
+        auth: user.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::Jams,
+            Breadcrumb::from_jam(jam),
+            Breadcrumb::entries_of_jam(jam),
+            Breadcrumb::from_jam_entry(jam, jam_entry),
+            Breadcrumb::from_jam_entry_update(jam, jam_entry, jam_entry_update),
+        ])
+        .to_context(),
+        jam: JamContext::from_model(conn, jam, false, viewer_timezone.get())?,
+        jam_entry: JamEntryContext::from_model(conn, jam_entry, false)?,
+        jam_entry_update: JamEntryUpdateContext::from_model(
+            conn,
+            jam_entry_update,
+            false,
+        )?,
+        approval_states: super::approval_state_names(
+            jam_entry_update.approval_state,
+            &super::EDIT_FORM_APPROVAL_ROLES,
+        ),
+        approval_transitions: ApprovalTransitionContext::for_subject(
+            conn,
+            ApprovalSubject::JamEntryUpdate(jam_entry_update.id),
+        )?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_accepts_updates() {
+        assert!(check_accepts_updates(JamPhase::Running).is_ok());
+        assert!(check_accepts_updates(JamPhase::Upcoming).is_err());
+        assert!(check_accepts_updates(JamPhase::SubmissionsClosed).is_err());
+        assert!(check_accepts_updates(JamPhase::Voting).is_err());
+        assert!(check_accepts_updates(JamPhase::Results).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use rocket::{post, uri, State};
+use rocket::{
+    request::{FromForm, LenientForm},
+    response::Redirect,
+};
+
+use crate::{
+    db::DbPool,
+    models::{ApprovalState, JamEntryVote, JamPhase, VoteCategory},
+    template_helpers::{CsrfVerified, UserRequired},
+};
+
+// UPDATE   /jams/:jam_id/entries/:jam_entry_id/votes -> the entry  USERS ONLY
+// one rating per category per voter, which may be changed until voting closes.
+// nobody votes on their own entry, and banned users can't vote at all.
+
+#[derive(Debug, FromForm)]
+pub struct VoteFormData {
+    /// The category being rated.
+    vote_category_id: i32,
+
+    /// How many stars the entry gets in that category.
+    rating: i32,
+}
+
+/// Casts or changes the logged in user's rating of an entry in one category,
+/// then goes back to the entry. Only approved entries can be voted on, and
+/// only while the jam's voting is open.
+#[post(
+    "/jams/<jam_id>/entries/<jam_entry_id>/votes",
+    data = "<vote_form_data>"
+)]
+pub async fn cast_jam_entry_vote(
+    pool: State<'_, DbPool>,
+    user: UserRequired,
+    _csrf: CsrfVerified,
+    jam_id: i32,
+    jam_entry_id: i32,
+    vote_form_data: LenientForm<VoteFormData>,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry) =
+        super::jam_entries::find_jam_and_entry(&conn, jam_id, jam_entry_id)?;
+
+    // entries nobody else can see aren't there to be voted on
+    if jam.approval_state != ApprovalState::Approved
+        || jam_entry.approval_state != ApprovalState::Approved
+        || jam_entry.is_submitter_banned(&conn)?
+    {
+        return Err(super::HandlerError::NotFound);
+    }
+
+    check_can_vote(jam.phase(), jam_entry.is_submitted_by(user.id()))?;
+
+    let vote_category =
+        VoteCategory::find_by_id(&conn, vote_form_data.vote_category_id)?
+            .ok_or_else(|| {
+                super::HandlerError::BadRequest(
+                    "That isn't something entries are rated on".to_string(),
+                )
+            })?;
+
+    JamEntryVote::cast(
+        &conn,
+        jam_entry.id,
+        user.id(),
+        vote_category.id,
+        vote_form_data.rating,
+    )?;
+
+    Ok(Redirect::to(uri!(
+        super::jam_entries::show_jam_entry: jam.id,
+        jam.slug.as_str(),
+        jam_entry.id,
+        jam_entry.slug.as_str()
+    )))
+}
+
+/// Refuses a vote cast outside of the jam's voting, or on the voter's own
+/// entry.
+pub(super) fn check_can_vote(
+    phase: JamPhase,
+    is_own_entry: bool,
+) -> Result<(), super::HandlerError> {
+    if !phase.accepts_votes() {
+        return Err(super::HandlerError::BadRequest(
+            "This jam isn't open for voting".to_string(),
+        ));
+    }
+
+    if is_own_entry {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_can_vote() {
+        assert!(check_can_vote(JamPhase::Voting, false).is_ok());
+        assert!(check_can_vote(JamPhase::Voting, true).is_err());
+        assert!(check_can_vote(JamPhase::Running, false).is_err());
+        assert!(check_can_vote(JamPhase::SubmissionsClosed, false).is_err());
+        assert!(check_can_vote(JamPhase::Results, false).is_err());
+    }
+}
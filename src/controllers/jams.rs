@@ -1,21 +1,24 @@
-use chrono::{NaiveDateTime, ParseError as DTParseError};
+use chrono::{NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
 use diesel::Connection;
 use rocket::{get, post, uri, State};
 use rocket::{
-    request::{Form, FromForm},
+    request::{FromForm, LenientForm},
     response::Redirect,
 };
 use rocket_contrib::templates::Template;
 use serde::Serialize;
 
+use super::TemplateOrRedirect;
 use crate::{
-    db::DbPool,
-    models::ApprovalState,
-    template_helpers::{AdminOnly, JamContext},
-};
-use crate::{
-    models::{Jam, RichText},
-    template_helpers::AdminOnlyContext,
+    db::{DbConn, DbPool},
+    models::{ApprovalRole, ApprovalState, ApprovalSubject, Jam, RichText},
+    template_helpers::{
+        Admin, ApprovalTransitionContext, AuthContext, Breadcrumb, Breadcrumbs,
+        BreadcrumbsContext, CsrfVerified, JamContext, PaginationContext,
+        RequirePermission, UserOptional, UserOptionalContext, ViewerTimezone,
+        JAM_TIME_INPUT_FORMAT,
+    },
 };
 
 // CREATE   /jams                   -> jam_id           ADMIN ONLY
@@ -30,11 +33,78 @@ use crate::{
 // CREATE   /jams/:jam_id/attachments                   create an attachment for this jam
 //                                  -> Result<Attachment>
 
+/// How many jams are shown on each page of the jam archive.
+const JAMS_PAGE_SIZE: i64 = 10;
+
+/// Jams belong to no one in particular, so the admins who edit them play both
+/// roles in moving them between approval states.
+const JAM_APPROVAL_ROLES: [ApprovalRole; 2] =
+    [ApprovalRole::Owner, ApprovalRole::Moderator];
+
+#[derive(Debug, Serialize)]
+struct ListJamsContext {
+    auth: UserOptionalContext,
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    jams: Vec<JamContext>,
+    pagination: PaginationContext,
+    showing_all_jams: bool,
+}
+
+/// Lists every jam there has ever been, newest first, a page at a time. Admins
+/// may opt into seeing unapproved jams the same way they can on the homepage.
+#[get("/jams?<page>&<show_all_jams>")]
+pub async fn list_jams(
+    pool: State<'_, DbPool>,
+    user: UserOptional,
+    viewer_timezone: ViewerTimezone,
+    page: Option<i64>,
+    show_all_jams: Option<bool>,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let should_show_all_jams =
+        user.is_admin() && show_all_jams.unwrap_or(false);
+    let total_jams = Jam::count(&conn, !should_show_all_jams)?;
+    let pagination =
+        PaginationContext::new(page.unwrap_or(0), JAMS_PAGE_SIZE, total_jams);
+
+    let mut jams = Vec::new();
+    for j in Jam::find_all(
+        &conn,
+        !should_show_all_jams,
+        pagination.page(),
+        JAMS_PAGE_SIZE,
+    )? {
+        jams.push(JamContext::from_model(
+            &conn,
+            &j,
+            false,
+            viewer_timezone.get(),
+        )?);
+    }
+
+    let context = ListJamsContext {
+        auth: user.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::Jams,
+        ])
+        .to_context(),
+        title: "Jams".to_string(),
+        jams,
+        pagination,
+        showing_all_jams: should_show_all_jams,
+    };
+
+    Ok(Template::render("jams", &context))
+}
+
 /// Creates a new blank jam and immediately redirects to its edit page.
 #[post("/jams")]
 pub async fn create_jam(
     pool: State<'_, DbPool>,
-    _admin_only: AdminOnly,
+    _admin_only: RequirePermission<Admin>,
+    _csrf: CsrfVerified,
 ) -> Result<Redirect, super::HandlerError> {
     let conn = pool.get()?;
     let jam = Jam::create(&conn)?;
@@ -43,15 +113,18 @@ pub async fn create_jam(
 
 #[derive(Debug, Serialize)]
 struct EditJamContext {
-    auth: AdminOnlyContext,
+    auth: AuthContext,
     jam: JamContext,
+    approval_states: Vec<String>,
+    approval_transitions: Vec<ApprovalTransitionContext>,
 }
 
 /// Renders out a lovely form that you can use to edit the jam.
 #[get("/jams/<jam_id>/edit")]
 pub async fn edit_jam(
     pool: State<'_, DbPool>,
-    admin_only: AdminOnly,
+    admin_only: RequirePermission<Admin>,
+    viewer_timezone: ViewerTimezone,
     jam_id: i32,
 ) -> Result<Template, super::HandlerError> {
     let conn = pool.get()?;
@@ -65,10 +138,7 @@ pub async fn edit_jam(
         None => return Err(super::HandlerError::NotFound),
     };
 
-    let context = EditJamContext {
-        auth: admin_only.to_context(),
-        jam: JamContext::from_model(&conn, &jam, false)?,
-    };
+    let context = edit_context(&conn, &admin_only, &viewer_timezone, &jam)?;
 
     Ok(Template::render("edit_jam", &context))
 }
@@ -82,17 +152,24 @@ pub struct JamFormData {
     // summary_attachment_id to be set by ajax
     // rich_text_id is already set, not changing that through web calls
     rich_text_content: String,
+    // the dates are all as the organizer would say them, in `timezone`
     start_date: String,
     end_date: String,
+    /// Empty for a jam without voting.
+    voting_end_date: String,
+    /// The IANA name of the organizer's timezone.
+    timezone: String,
     approval_state: String,
 }
 
 #[post("/jams/<jam_id>", data = "<jam_form_data>")]
 pub async fn update_jam(
     pool: State<'_, DbPool>,
-    admin_only: AdminOnly,
+    admin_only: RequirePermission<Admin>,
+    viewer_timezone: ViewerTimezone,
+    _csrf: CsrfVerified,
     jam_id: i32,
-    jam_form_data: Form<JamFormData>,
+    jam_form_data: LenientForm<JamFormData>,
 ) -> Result<Template, super::HandlerError> {
     let conn = pool.get()?;
 
@@ -113,39 +190,189 @@ pub async fn update_jam(
             jam.title = jam_form_data.title.clone();
             jam.slug = jam_form_data.slug.clone();
             jam.summary = jam_form_data.summary.clone();
-            jam.start_date = parse_date(&jam_form_data.start_date)?;
-            jam.end_date = parse_date(&jam_form_data.end_date)?;
-            jam.approval_state =
-                ApprovalState::from_human_str(&jam_form_data.approval_state)?;
+            let timezone = parse_timezone(&jam_form_data.timezone)?;
+            jam.timezone = timezone.name().to_string();
+            jam.start_date =
+                parse_local_datetime(&jam_form_data.start_date, timezone)?;
+            jam.end_date =
+                parse_local_datetime(&jam_form_data.end_date, timezone)?;
+            jam.voting_end_date = match jam_form_data.voting_end_date.trim() {
+                "" => None,
+                date => Some(parse_local_datetime(date, timezone)?),
+            };
+            if jam.voting_end_date.map_or(false, |d| d <= jam.end_date) {
+                return Err(super::HandlerError::BadRequest(
+                    "Voting has to end after the jam does".to_string(),
+                ));
+            }
             rich_text.content = jam_form_data.rich_text_content.clone();
 
             jam.update(&conn)?;
-            rich_text.update(&conn)?;
+            jam.transition(
+                &conn,
+                ApprovalState::from_human_str(&jam_form_data.approval_state)?,
+                admin_only.id(),
+                &JAM_APPROVAL_ROLES,
+                None,
+            )?;
+            rich_text.update(&conn, admin_only.id())?;
             Ok((jam, rich_text))
         })?;
 
-    let context = EditJamContext {
-        auth: admin_only.to_context(),
-        jam: JamContext::from_model(&conn, &txr.0, false)?,
-    };
+    let context = edit_context(&conn, &admin_only, &viewer_timezone, &txr.0)?;
 
     Ok(Template::render("edit_jam", &context))
 }
 
-fn parse_date(date: &str) -> Result<NaiveDateTime, DTParseError> {
-    NaiveDateTime::parse_from_str(
-        &format!("{} 00:00:00", date),
-        "%Y-%m-%d %H:%M:%S"
-    )
+#[derive(Debug, Serialize)]
+struct ShowJamContext {
+    auth: UserOptionalContext,
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    jam: JamContext,
+}
+
+/// Shows a jam, its dates, and its rules. Jams which are not approved are only
+/// visible to admins, everyone else gets a 404. Should the slug in the url not
+/// match the jam's current slug, such as when the jam was renamed, the user is
+/// redirected to the canonical url.
+#[get("/jams/<jam_id>/<jam_slug>", rank = 2)]
+pub async fn show_jam(
+    pool: State<'_, DbPool>,
+    user: UserOptional,
+    viewer_timezone: ViewerTimezone,
+    jam_id: i32,
+    jam_slug: String,
+) -> Result<TemplateOrRedirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let jam = match Jam::find_by_id(&conn, jam_id)? {
+        Some(jam) => jam,
+        None => return Err(super::HandlerError::NotFound),
+    };
+
+    if jam.approval_state != ApprovalState::Approved
+        && !user.is_admin()
+        && !user.can_moderate()
+    {
+        return Err(super::HandlerError::NotFound);
+    }
+
+    if jam.slug != jam_slug {
+        return Ok(TemplateOrRedirect::Redirect(Redirect::to(uri!(
+            show_jam: jam.id,
+            jam.slug.as_str()
+        ))));
+    }
+
+    let context = ShowJamContext {
+        auth: user.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::Jams,
+            Breadcrumb::from_jam(&jam),
+        ])
+        .to_context(),
+        title: jam.title.clone(),
+        jam: JamContext::from_model(&conn, &jam, true, viewer_timezone.get())?,
+    };
+
+    Ok(TemplateOrRedirect::Template(Template::render(
+        "jam", &context,
+    )))
+}
+
+fn edit_context(
+    conn: &DbConn,
+    admin_only: &RequirePermission<Admin>,
+    viewer_timezone: &ViewerTimezone,
+    jam: &Jam,
+) -> Result<EditJamContext, super::HandlerError> {
+    Ok(EditJamContext {
+        auth: admin_only.to_context(),
+        jam: JamContext::from_model(conn, jam, false, viewer_timezone.get())?,
+        approval_states: super::approval_state_names(
+            jam.approval_state,
+            &JAM_APPROVAL_ROLES,
+        ),
+        approval_transitions: ApprovalTransitionContext::for_subject(
+            conn,
+            ApprovalSubject::Jam(jam.id),
+        )?,
+    })
+}
+
+/// Reads an IANA timezone name, such as `America/Los_Angeles`.
+fn parse_timezone(timezone: &str) -> Result<Tz, super::HandlerError> {
+    timezone.trim().parse().map_err(|_| {
+        super::HandlerError::BadRequest(format!(
+            "{} isn't a timezone, try something like America/Los_Angeles",
+            timezone
+        ))
+    })
+}
+
+/// Reads a date and time as the organizer would say it in `timezone`, giving
+/// the UTC instant it means. Times which a change of clocks skips over don't
+/// mean anything, and times it repeats mean the first of the two.
+fn parse_local_datetime(
+    datetime: &str,
+    timezone: Tz,
+) -> Result<NaiveDateTime, super::HandlerError> {
+    let local = match NaiveDateTime::parse_from_str(
+        datetime.trim(),
+        JAM_TIME_INPUT_FORMAT,
+    ) {
+        Ok(local) => local,
+        Err(_) => {
+            return Err(super::HandlerError::BadRequest(format!(
+                "{} isn't a date and time like 2021-01-31T23:59",
+                datetime
+            )))
+        }
+    };
+
+    match timezone.from_local_datetime(&local).earliest() {
+        Some(instant) => Ok(instant.naive_utc()),
+        None => Err(super::HandlerError::BadRequest(format!(
+            "{} doesn't happen in {}, as the clocks change then",
+            datetime,
+            timezone.name()
+        ))),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parse_date;
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_parse_local_datetime() {
+        let pacific = Tz::America__Los_Angeles;
+
+        assert_eq!(
+            NaiveDate::from_ymd(2021, 1, 1).and_hms(7, 59, 0),
+            parse_local_datetime("2020-12-31T23:59", pacific).unwrap()
+        );
+        assert_eq!(
+            NaiveDate::from_ymd(2021, 1, 1).and_hms(0, 0, 0),
+            parse_local_datetime(" 2021-01-01T00:00 ", Tz::UTC).unwrap()
+        );
+        // the clocks skip from 2am to 3am, then repeat 1am
+        assert!(parse_local_datetime("2021-03-14T02:30", pacific).is_err());
+        assert_eq!(
+            NaiveDate::from_ymd(2021, 11, 7).and_hms(8, 30, 0),
+            parse_local_datetime("2021-11-07T01:30", pacific).unwrap()
+        );
+        assert!(parse_local_datetime("2021-01-01", pacific).is_err());
+    }
 
     #[test]
-    fn date_parsing() {
-        let foo = parse_date("2021-01-01");
-        assert!(foo.is_ok());
+    fn test_parse_timezone() {
+        assert_eq!(
+            Tz::America__Los_Angeles,
+            parse_timezone("America/Los_Angeles").unwrap()
+        );
+        assert!(parse_timezone("Pacific Time").is_err());
     }
 }
@@ -1,13 +1,23 @@
 pub mod attachments;
+pub mod bans;
+pub mod fake_github;
 pub mod gh_oauth;
 pub mod homepage;
 pub mod jam_entries;
+pub mod jam_entry_updates;
+pub mod jam_entry_votes;
 pub mod jams;
+pub mod moderation;
+pub mod rich_texts;
+pub mod timezones;
 
 use rocket::{
-    http::Status, response::Responder, response::Result as RocketResult,
-    Request,
+    http::Status, response::status, response::Redirect, response::Responder,
+    response::Result as RocketResult, Request,
 };
+use rocket_contrib::templates::Template;
+
+use crate::models::{ApprovalRole, ApprovalState, ModelError};
 use thiserror::Error;
 
 /// Unified error type for most (all?) handlers. Puts all the annoying
@@ -23,6 +33,9 @@ pub enum HandlerError {
     #[error("The resource was not found")]
     NotFound,
 
+    #[error("The user may not do this")]
+    Forbidden,
+
     #[error("Could not get a connection from the pool with error {0}")]
     PoolError(#[from] diesel::r2d2::PoolError),
 
@@ -43,14 +56,38 @@ pub enum HandlerError {
 
     #[error("Approval State Parse Error {0}")]
     ApprovalStateParseError(#[from] crate::models::ApprovalStateParseError),
+
+    #[error("Bad Request {0}")]
+    BadRequest(String),
+
+    #[error("The request body is too large")]
+    PayloadTooLarge,
+
+    #[error("Multipart Error {0}")]
+    MultipartError(#[from] multer::Error),
+
+    #[error("IO Error {0}")]
+    IoError(#[from] std::io::Error),
 }
 
 impl<'r, 'o: 'r> Responder<'r, 'o> for HandlerError {
-    fn respond_to(self, _request: &'r Request<'_>) -> RocketResult<'o> {
+    fn respond_to(self, request: &'r Request<'_>) -> RocketResult<'o> {
         let r = match self {
             HandlerError::AttachmentStorageError(_) => {
                 Status::InternalServerError
             }
+            HandlerError::DatabaseError(ModelError::InvalidUrl(_)) => {
+                Status::BadRequest
+            }
+            HandlerError::DatabaseError(
+                ModelError::UnownedAttachmentReference(_),
+            ) => Status::BadRequest,
+            HandlerError::DatabaseError(ModelError::RatingOutOfRange(_)) => {
+                Status::BadRequest
+            }
+            HandlerError::DatabaseError(
+                ModelError::ForbiddenApprovalTransition { .. },
+            ) => Status::Forbidden,
             HandlerError::DatabaseError(_) => Status::InternalServerError,
             HandlerError::PoolError(_) => Status::InternalServerError,
             HandlerError::HttpError(_) => Status::InternalServerError,
@@ -59,13 +96,56 @@ impl<'r, 'o: 'r> Responder<'r, 'o> for HandlerError {
                 Status::InternalServerError
             }
             HandlerError::DieselError(_) => Status::InternalServerError,
+            HandlerError::BadRequest(_) => Status::BadRequest,
+            HandlerError::PayloadTooLarge => Status::PayloadTooLarge,
+            HandlerError::MultipartError(_) => Status::BadRequest,
+            HandlerError::IoError(_) => Status::InternalServerError,
             HandlerError::NotFound => Status::NotFound,
+            HandlerError::Forbidden => Status::Forbidden,
         };
 
         if r == Status::InternalServerError {
             print!("Internal error {:?}", self);
         }
 
+        // whoever sent a bad request needs to know what was wrong with it, so
+        // the message is shown rather than left to the generic catcher
+        if r == Status::BadRequest {
+            let message = match self {
+                HandlerError::BadRequest(message) => message,
+                e => e.to_string(),
+            };
+            let page = crate::error_handlers::bad_request_page(&message);
+
+            return status::Custom(r, page).respond_to(request);
+        }
+
         Err(r)
     }
 }
+
+/// Some pages render a template most of the time, but redirect elsewhere when
+/// the request should really be looking at a different url, such as when a
+/// slug has gone stale.
+#[derive(rocket::Responder)]
+pub enum TemplateOrRedirect {
+    Template(Template),
+    Redirect(Redirect),
+}
+
+/// The roles a user plays when changing the approval state of an entry or an
+/// update from its edit form. Whoever may edit it there acts for its owner;
+/// approving and rejecting only happen in the moderation queue.
+const EDIT_FORM_APPROVAL_ROLES: [ApprovalRole; 1] = [ApprovalRole::Owner];
+
+/// The names of the approval states a user may leave some content in, for the
+/// edit form to offer.
+fn approval_state_names(
+    from: ApprovalState,
+    roles: &[ApprovalRole],
+) -> Vec<String> {
+    from.reachable_states(roles)
+        .iter()
+        .map(|s| s.to_human_str())
+        .collect()
+}
@@ -0,0 +1,340 @@
+use rocket::request::{FromForm, LenientForm};
+use rocket::{get, post, response::Redirect, uri, State};
+use rocket_contrib::templates::Template;
+use serde::Serialize;
+
+use crate::{
+    db::{DbConn, DbPool},
+    models::{
+        ApprovalRole, ApprovalState, Jam, JamEntry, JamEntryUpdate, ModelError,
+    },
+    template_helpers::{
+        AuthContext, Breadcrumb, Breadcrumbs, BreadcrumbsContext, CsrfVerified,
+        JamContext, JamEntryContext, JamEntryUpdateContext, Moderator,
+        RequirePermission, ViewerTimezone,
+    },
+};
+
+// GET      /moderation                             -> everything submitted
+//                                                          MODERATOR ONLY
+// UPDATE   /moderation/jams/:id                    -> approve or reject
+//                                                          MODERATOR ONLY
+// UPDATE   /moderation/jam_entries/:id             -> approve or reject
+//                                                          MODERATOR ONLY
+// UPDATE   /moderation/jam_entry_updates/:id       -> approve or reject
+//                                                          MODERATOR ONLY
+// content joins the queue when its author submits it, and leaves it when a
+// moderator decides on it or the author takes it back to a draft.
+
+#[derive(Debug, Serialize)]
+struct QueuedJamEntryContext {
+    jam: JamContext,
+    jam_entry: JamEntryContext,
+}
+
+#[derive(Debug, Serialize)]
+struct QueuedJamEntryUpdateContext {
+    jam: JamContext,
+    jam_entry: JamEntryContext,
+    jam_entry_update: JamEntryUpdateContext,
+}
+
+#[derive(Debug, Serialize)]
+struct ModerationQueueContext {
+    auth: AuthContext,
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    jams: Vec<JamContext>,
+    jam_entries: Vec<QueuedJamEntryContext>,
+    jam_entry_updates: Vec<QueuedJamEntryUpdateContext>,
+}
+
+/// Lists every jam, entry and update which has been submitted and is waiting
+/// for a moderator, oldest first.
+#[get("/moderation")]
+pub async fn moderation_queue(
+    pool: State<'_, DbPool>,
+    moderator: RequirePermission<Moderator>,
+    viewer_timezone: ViewerTimezone,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let submitted = ApprovalState::Submitted;
+
+    let mut jams = Vec::new();
+    for j in Jam::find_by_approval_state(&conn, submitted)? {
+        jams.push(JamContext::from_model(
+            &conn,
+            &j,
+            false,
+            viewer_timezone.get(),
+        )?);
+    }
+
+    let mut jam_entries = Vec::new();
+    for e in JamEntry::find_by_approval_state(&conn, submitted)? {
+        let jam = load_jam(&conn, e.jam_id)?;
+
+        jam_entries.push(QueuedJamEntryContext {
+            jam: JamContext::from_model(
+                &conn,
+                &jam,
+                false,
+                viewer_timezone.get(),
+            )?,
+            jam_entry: JamEntryContext::from_model(&conn, &e, false)?,
+        });
+    }
+
+    let mut jam_entry_updates = Vec::new();
+    for u in JamEntryUpdate::find_by_approval_state(&conn, submitted)? {
+        let jam_entry = load_jam_entry(&conn, u.jam_entry_id)?;
+        let jam = load_jam(&conn, jam_entry.jam_id)?;
+
+        jam_entry_updates.push(QueuedJamEntryUpdateContext {
+            jam: JamContext::from_model(
+                &conn,
+                &jam,
+                false,
+                viewer_timezone.get(),
+            )?,
+            jam_entry: JamEntryContext::from_model(&conn, &jam_entry, false)?,
+            jam_entry_update: JamEntryUpdateContext::from_model(
+                &conn, &u, false,
+            )?,
+        });
+    }
+
+    let context = ModerationQueueContext {
+        auth: moderator.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::Moderation,
+        ])
+        .to_context(),
+        title: "Moderation".to_string(),
+        jams,
+        jam_entries,
+        jam_entry_updates,
+    };
+
+    Ok(Template::render("moderation", &context))
+}
+
+#[derive(Debug, FromForm)]
+pub struct ModerationFormData {
+    /// Either "Approved" or "Rejected".
+    approval_state: String,
+
+    /// Why the content was rejected, which its author is shown. Ignored when
+    /// approving.
+    rejection_reason: String,
+}
+
+/// Approves or rejects a submitted jam.
+#[post("/moderation/jams/<jam_id>", data = "<moderation_form_data>")]
+pub async fn moderate_jam(
+    pool: State<'_, DbPool>,
+    moderator: RequirePermission<Moderator>,
+    _csrf: CsrfVerified,
+    jam_id: i32,
+    moderation_form_data: LenientForm<ModerationFormData>,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let mut jam = match Jam::find_by_id(&conn, jam_id)? {
+        Some(jam) => jam,
+        None => return Err(super::HandlerError::NotFound),
+    };
+
+    // jams belong to no one in particular
+    let (approval_state, rejection_reason) = decide(
+        jam.approval_state,
+        None,
+        moderator.id(),
+        &moderation_form_data,
+    )?;
+    jam.transition(
+        &conn,
+        approval_state,
+        moderator.id(),
+        &[ApprovalRole::Moderator],
+        rejection_reason.as_deref(),
+    )?;
+
+    Ok(Redirect::to(uri!(moderation_queue)))
+}
+
+/// Approves or rejects a submitted jam entry.
+#[post(
+    "/moderation/jam_entries/<jam_entry_id>",
+    data = "<moderation_form_data>"
+)]
+pub async fn moderate_jam_entry(
+    pool: State<'_, DbPool>,
+    moderator: RequirePermission<Moderator>,
+    _csrf: CsrfVerified,
+    jam_entry_id: i32,
+    moderation_form_data: LenientForm<ModerationFormData>,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let mut jam_entry = match JamEntry::find_by_id(&conn, jam_entry_id)? {
+        Some(jam_entry) => jam_entry,
+        None => return Err(super::HandlerError::NotFound),
+    };
+
+    let (approval_state, rejection_reason) = decide(
+        jam_entry.approval_state,
+        Some(jam_entry.submitter_user_id),
+        moderator.id(),
+        &moderation_form_data,
+    )?;
+    jam_entry.transition(
+        &conn,
+        approval_state,
+        moderator.id(),
+        &[ApprovalRole::Moderator],
+        rejection_reason.as_deref(),
+    )?;
+
+    Ok(Redirect::to(uri!(moderation_queue)))
+}
+
+/// Approves or rejects a submitted update to a jam entry.
+#[post(
+    "/moderation/jam_entry_updates/<jam_entry_update_id>",
+    data = "<moderation_form_data>"
+)]
+pub async fn moderate_jam_entry_update(
+    pool: State<'_, DbPool>,
+    moderator: RequirePermission<Moderator>,
+    _csrf: CsrfVerified,
+    jam_entry_update_id: i32,
+    moderation_form_data: LenientForm<ModerationFormData>,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let mut jam_entry_update =
+        match JamEntryUpdate::find_by_id(&conn, jam_entry_update_id)? {
+            Some(jam_entry_update) => jam_entry_update,
+            None => return Err(super::HandlerError::NotFound),
+        };
+
+    let jam_entry = load_jam_entry(&conn, jam_entry_update.jam_entry_id)?;
+    let (approval_state, rejection_reason) = decide(
+        jam_entry_update.approval_state,
+        Some(jam_entry.submitter_user_id),
+        moderator.id(),
+        &moderation_form_data,
+    )?;
+    jam_entry_update.transition(
+        &conn,
+        approval_state,
+        moderator.id(),
+        &[ApprovalRole::Moderator],
+        rejection_reason.as_deref(),
+    )?;
+
+    Ok(Redirect::to(uri!(moderation_queue)))
+}
+
+/// Works out what a moderator decided about something in the queue, giving
+/// the new approval state and the rejection reason to go with it. Something
+/// which isn't submitted anymore, such as when its author took it back to a
+/// draft in the meantime, isn't theirs to decide on, and nor is something they
+/// submitted themselves.
+fn decide(
+    current: ApprovalState,
+    owner_user_id: Option<i64>,
+    moderator_user_id: i64,
+    moderation_form_data: &ModerationFormData,
+) -> Result<(ApprovalState, Option<String>), super::HandlerError> {
+    if owner_user_id == Some(moderator_user_id) {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    if current != ApprovalState::Submitted {
+        return Err(super::HandlerError::BadRequest(
+            "This is no longer waiting for a moderator".to_string(),
+        ));
+    }
+
+    let rejection_reason = moderation_form_data.rejection_reason.trim();
+
+    match ApprovalState::from_human_str(&moderation_form_data.approval_state)? {
+        ApprovalState::Approved => Ok((ApprovalState::Approved, None)),
+        ApprovalState::Rejected if rejection_reason.is_empty() => {
+            Err(super::HandlerError::BadRequest(
+                "Please say why this is rejected, so its author can fix it"
+                    .to_string(),
+            ))
+        }
+        ApprovalState::Rejected => {
+            Ok((ApprovalState::Rejected, Some(rejection_reason.to_string())))
+        }
+        _ => Err(super::HandlerError::BadRequest(
+            "A moderator may only approve or reject".to_string(),
+        )),
+    }
+}
+
+/// Loads the jam something in the queue belongs to, which must exist.
+fn load_jam(conn: &DbConn, jam_id: i32) -> Result<Jam, ModelError> {
+    Jam::find_by_id(conn, jam_id)?.ok_or(ModelError::NotFound)
+}
+
+/// Loads the entry an update in the queue belongs to, which must exist.
+fn load_jam_entry(
+    conn: &DbConn,
+    jam_entry_id: i32,
+) -> Result<JamEntry, ModelError> {
+    JamEntry::find_by_id(conn, jam_entry_id)?.ok_or(ModelError::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(
+        approval_state: &str,
+        rejection_reason: &str,
+    ) -> ModerationFormData {
+        ModerationFormData {
+            approval_state: approval_state.to_string(),
+            rejection_reason: rejection_reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_decide() {
+        let submitted = ApprovalState::Submitted;
+
+        assert_eq!(
+            (ApprovalState::Approved, None),
+            decide(submitted, None, 1, &form("Approved", "ignored")).unwrap()
+        );
+        assert_eq!(
+            (ApprovalState::Rejected, Some("Spam".to_string())),
+            decide(submitted, None, 1, &form("Rejected", " Spam ")).unwrap()
+        );
+        assert!(decide(submitted, None, 1, &form("Rejected", "  ")).is_err());
+        assert!(decide(submitted, None, 1, &form("Draft", "")).is_err());
+        assert!(decide(ApprovalState::Draft, None, 1, &form("Approved", ""))
+            .is_err());
+        assert!(decide(
+            ApprovalState::Approved,
+            None,
+            1,
+            &form("Rejected", "Spam")
+        )
+        .is_err());
+        assert!(decide(submitted, Some(2), 1, &form("Approved", "")).is_ok());
+    }
+
+    #[test]
+    fn test_decide_own_submission() {
+        let submitted = ApprovalState::Submitted;
+
+        assert!(decide(submitted, Some(1), 1, &form("Approved", "")).is_err());
+        assert!(
+            decide(submitted, Some(1), 1, &form("Rejected", "Spam")).is_err()
+        );
+    }
+}
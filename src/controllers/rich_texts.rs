@@ -0,0 +1,159 @@
+use rocket::{get, post, response::Redirect, uri, State};
+use rocket_contrib::templates::Template;
+use serde::Serialize;
+
+use crate::{
+    db::{DbConn, DbPool},
+    models::{GhUserRecord, RichText, RichTextRevision},
+    template_helpers::{
+        Admin, AuthContext, Breadcrumb, Breadcrumbs, BreadcrumbsContext,
+        CsrfVerified, RequirePermission, RichTextRevisionContext,
+    },
+};
+
+// GET      /rich_texts/:rich_text_id/revisions                 ADMIN ONLY
+// GET      /rich_texts/:rich_text_id/revisions/:id             ADMIN ONLY
+// CREATE   /rich_texts/:rich_text_id/revisions/:id/restore     ADMIN ONLY
+// revisions are recorded by RichText::update, never created directly.
+
+#[derive(Debug, Serialize)]
+struct ListRichTextRevisionsContext {
+    auth: AuthContext,
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    rich_text_id: i32,
+    revisions: Vec<RichTextRevisionContext>,
+}
+
+/// Lists every revision of a rich text, newest first, along with how many
+/// lines each one changed.
+#[get("/rich_texts/<rich_text_id>/revisions")]
+pub async fn list_rich_text_revisions(
+    pool: State<'_, DbPool>,
+    admin_only: RequirePermission<Admin>,
+    rich_text_id: i32,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let rich_text = find_rich_text(&conn, rich_text_id)?;
+
+    let revisions =
+        RichTextRevision::find_by_rich_text_id(&conn, rich_text.id)?;
+    let author_ids: Vec<i64> =
+        revisions.iter().filter_map(|r| r.author_user_id).collect();
+    let authors = GhUserRecord::find_by_ids(&conn, &author_ids)?;
+
+    // revisions come newest first, so the one before each is next in the list
+    let revisions = revisions
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let author =
+                authors.iter().find(|a| Some(a.id) == r.author_user_id);
+            RichTextRevisionContext::from_model(r, revisions.get(i + 1), author)
+        })
+        .collect();
+
+    let context = ListRichTextRevisionsContext {
+        auth: admin_only.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::RichTextRevisions {
+                rich_text_id: rich_text.id,
+            },
+        ])
+        .to_context(),
+        title: "History".to_string(),
+        rich_text_id: rich_text.id,
+        revisions,
+    };
+
+    Ok(Template::render("rich_text_revisions", &context))
+}
+
+#[derive(Debug, Serialize)]
+struct ShowRichTextRevisionContext {
+    auth: AuthContext,
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    revision: RichTextRevisionContext,
+    is_current: bool,
+}
+
+/// Shows one revision of a rich text as a line diff against the revision
+/// before it, with a button to restore it.
+#[get("/rich_texts/<rich_text_id>/revisions/<revision_id>")]
+pub async fn show_rich_text_revision(
+    pool: State<'_, DbPool>,
+    admin_only: RequirePermission<Admin>,
+    rich_text_id: i32,
+    revision_id: i32,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let (rich_text, revision) =
+        find_rich_text_and_revision(&conn, rich_text_id, revision_id)?;
+
+    let context = ShowRichTextRevisionContext {
+        auth: admin_only.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::RichTextRevisions {
+                rich_text_id: rich_text.id,
+            },
+        ])
+        .to_context(),
+        title: format!("Revision {}", revision.id),
+        revision: RichTextRevisionContext::with_diff(
+            &revision,
+            revision.find_previous(&conn)?.as_ref(),
+            revision.load_author(&conn)?.as_ref(),
+        ),
+        is_current: rich_text.content == revision.content,
+    };
+
+    Ok(Template::render("rich_text_revision", &context))
+}
+
+/// Puts the content of an earlier revision back. This is recorded as a new
+/// revision, so a restore can itself be undone.
+#[post("/rich_texts/<rich_text_id>/revisions/<revision_id>/restore")]
+pub async fn restore_rich_text_revision(
+    pool: State<'_, DbPool>,
+    admin_only: RequirePermission<Admin>,
+    _csrf: CsrfVerified,
+    rich_text_id: i32,
+    revision_id: i32,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (mut rich_text, revision) =
+        find_rich_text_and_revision(&conn, rich_text_id, revision_id)?;
+
+    rich_text.restore(&conn, &revision, admin_only.id())?;
+
+    Ok(Redirect::to(uri!(list_rich_text_revisions: rich_text.id)))
+}
+
+fn find_rich_text(
+    conn: &DbConn,
+    rich_text_id: i32,
+) -> Result<RichText, super::HandlerError> {
+    match RichText::find_by_id(conn, rich_text_id)? {
+        Some(rich_text) => Ok(rich_text),
+        None => Err(super::HandlerError::NotFound),
+    }
+}
+
+/// Finds a revision, making sure it really belongs to the given rich text.
+fn find_rich_text_and_revision(
+    conn: &DbConn,
+    rich_text_id: i32,
+    revision_id: i32,
+) -> Result<(RichText, RichTextRevision), super::HandlerError> {
+    let rich_text = find_rich_text(conn, rich_text_id)?;
+
+    match RichTextRevision::find_by_id(conn, revision_id)? {
+        Some(revision) if revision.rich_text_id == rich_text.id => {
+            Ok((rich_text, revision))
+        }
+        _ => Err(super::HandlerError::NotFound),
+    }
+}
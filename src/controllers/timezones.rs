@@ -0,0 +1,52 @@
+use chrono_tz::Tz;
+use rocket::{
+    http::CookieJar,
+    post,
+    request::{FromForm, LenientForm},
+    response::Redirect,
+};
+
+use super::gh_oauth::is_return_to_path;
+use crate::template_helpers::{CsrfVerified, ViewerTimezone};
+
+// UPDATE   /timezone               -> back where the form was
+// the chosen timezone lives in a cookie, so it works without logging in.
+
+#[derive(Debug, FromForm)]
+pub struct TimezoneFormData {
+    /// An IANA timezone name, or empty to go back to the organizer's.
+    timezone: String,
+
+    /// The page to go back to, which must be on this site.
+    return_to: String,
+}
+
+/// Chooses the timezone that jam times are shown in, then goes back to the
+/// page the choice was made on.
+#[post("/timezone", data = "<timezone_form_data>")]
+pub fn choose_timezone(
+    cookies: &CookieJar<'_>,
+    _csrf: CsrfVerified,
+    timezone_form_data: LenientForm<TimezoneFormData>,
+) -> Result<Redirect, super::HandlerError> {
+    let timezone = match timezone_form_data.timezone.trim() {
+        "" => None,
+        name => Some(name.parse::<Tz>().map_err(|_| {
+            super::HandlerError::BadRequest(format!(
+                "{} isn't a timezone, try something like America/Los_Angeles",
+                name
+            ))
+        })?),
+    };
+
+    ViewerTimezone::choose(cookies, timezone);
+
+    // only pages on this site are gone back to, so that the form can't be
+    // used to send people elsewhere
+    let return_to = &timezone_form_data.return_to;
+    if is_return_to_path(return_to) {
+        Ok(Redirect::to(return_to.clone()))
+    } else {
+        Ok(Redirect::to("/"))
+    }
+}
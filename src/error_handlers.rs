@@ -1,11 +1,15 @@
-use rocket::catch;
+use rocket::{catch, Request};
 use rocket_contrib::templates::Template;
 use serde::Serialize;
 
+use crate::template_helpers::was_turned_away_banned;
+
 #[derive(Debug, Serialize)]
 struct ErrorContext {
     message: String,
     suppress_auth_controls: bool,
+    show_login: bool,
+    show_ban: bool,
 }
 
 impl ErrorContext {
@@ -13,10 +17,23 @@ impl ErrorContext {
         Self {
             message: format!("{}: {}", code, message),
             suppress_auth_controls: true,
+            show_login: false,
+            show_ban: false,
         }
     }
 }
 
+/// Explains why a request was refused, for when a handler knows what was wrong
+/// with it, such as a form field that didn't make sense.
+pub fn bad_request_page(message: &str) -> Template {
+    Template::render("error_page", &ErrorContext::new(400, message))
+}
+
+#[catch(400)]
+pub fn bad_request() -> Template {
+    bad_request_page("The request didn't make sense.")
+}
+
 #[catch(404)]
 pub fn not_found() -> Template {
     Template::render("error_page", &ErrorContext::new(404, "Page not found."))
@@ -26,12 +43,26 @@ pub fn not_found() -> Template {
 pub fn not_authorized() -> Template {
     Template::render(
         "error_page",
-        &ErrorContext::new(401, "You must be logged in to view this page."),
+        // the login page sends the user back here, as this is the referer
+        &ErrorContext {
+            show_login: true,
+            ..ErrorContext::new(401, "You must be logged in to view this page.")
+        },
     )
 }
 
 #[catch(403)]
-pub fn forbidden() -> Template {
+pub fn forbidden(req: &Request<'_>) -> Template {
+    if was_turned_away_banned(req) {
+        return Template::render(
+            "error_page",
+            &ErrorContext {
+                show_ban: true,
+                ..ErrorContext::new(403, "You have been banned.")
+            },
+        );
+    }
+
     Template::render(
         "error_page",
         &ErrorContext::new(403, "You cannot view this page."),
@@ -18,7 +18,9 @@ mod template_helpers;
 
 use crate::attachments::AttachmentStorage;
 use crate::cliopts::{Opts, SubCommand};
-use crate::controllers::gh_oauth::GhCredentials;
+use crate::controllers::gh_oauth::{
+    GhCredentials, GH_API_BASE_URL, GH_OAUTH_BASE_URL,
+};
 use crate::db::{get_pool, DbConn, DbPool};
 use crate::models::{GhUserRecord, ModelError};
 use clap::Clap;
@@ -27,6 +29,7 @@ use std::convert::TryFrom;
 use std::env;
 use std::num::ParseIntError;
 use std::path::PathBuf;
+use thiserror::Error;
 
 #[rocket::main]
 async fn main() {
@@ -42,33 +45,57 @@ async fn main() {
             println!("Migrating the database at {}", database_path);
             crate::db::migrate_db(&db_pool);
         }
-        SubCommand::Serve(_) => {
-            let attachment_storage = AttachmentStorage::new(PathBuf::from(
-                expect_env_string("UDEVGAMES_ATTACHMENT_STORAGE"),
-            ));
-            let gh_credentials = GhCredentials {
-                client_id: expect_env_string("GH_CLIENT_ID"),
-                client_secret: expect_env_string("GH_CLIENT_SECRET"),
+        SubCommand::Serve(serve) => {
+            let attachment_storage = attachment_storage_from_spec(
+                &expect_env_string("UDEVGAMES_ATTACHMENT_STORAGE"),
+            );
+            let address = expect_env_string("UDEVGAMES_APP_ADDRESS");
+            let port = expect_env_u16("UDEVGAMES_APP_PORT");
+            let gh_credentials = if serve.fake_github {
+                println!(
+                    "Logging in with a fake Github, which lets anyone log in \
+                        as anyone. Never do this in production!"
+                );
+                fake_gh_credentials(&address, port)
+            } else {
+                GhCredentials {
+                    client_id: expect_env_string("GH_CLIENT_ID"),
+                    client_secret: expect_env_string("GH_CLIENT_SECRET"),
+                    oauth_base_url: env::var("GH_OAUTH_BASE_URL")
+                        .unwrap_or_else(|_| GH_OAUTH_BASE_URL.to_string()),
+                    api_base_url: env::var("GH_API_BASE_URL")
+                        .unwrap_or_else(|_| GH_API_BASE_URL.to_string()),
+                }
             };
 
             crate::serve::serve(
-                expect_env_string("UDEVGAMES_APP_ADDRESS"),
-                expect_env_u16("UDEVGAMES_APP_PORT"),
+                address,
+                port,
                 expect_env_u16("UDEVGAMES_APP_WORKERS"),
                 expect_env_string("UDEVGAMES_APP_SECRET"),
                 db_pool,
                 gh_credentials,
                 attachment_storage,
+                serve.fake_github,
             )
             .await;
         }
-        SubCommand::Permission(subcmd) => perms_subcmd(&db_pool, subcmd),
+        SubCommand::Permission(subcmd) => perms_subcmd(&db_pool, subcmd).await,
+        SubCommand::User(subcmd) => user_subcmd(&db_pool, subcmd).await,
+        SubCommand::Session(subcmd) => session_subcmd(&db_pool, subcmd).await,
+        SubCommand::Attachments(subcmd) => {
+            let attachment_storage = attachment_storage_from_spec(
+                &expect_env_string("UDEVGAMES_ATTACHMENT_STORAGE"),
+            );
+
+            attachments_subcmd(&db_pool, &attachment_storage, subcmd).await
+        }
     }
 }
 
-fn perms_subcmd(pool: &DbPool, subcmd: crate::cliopts::Permission) {
+async fn perms_subcmd(pool: &DbPool, subcmd: crate::cliopts::Permission) {
     use crate::cliopts::PermissionSubCommand;
-    use crate::models::Permission;
+    use crate::models::{Permission, PermissionKind};
 
     let conn = pool
         .get()
@@ -76,46 +103,56 @@ fn perms_subcmd(pool: &DbPool, subcmd: crate::cliopts::Permission) {
 
     match subcmd.subcmd {
         PermissionSubCommand::Grant(grant) => {
-            let uid = UserIdentity::try_from(grant.user)
-                .expect(
-                    "Could not infer user; is your login \
-                        prefixed with @?",
-                )
-                .uid(&conn)
-                .expect("Could not query database");
-            Permission::grant_permission(&conn, uid, &grant.permission)
-                .expect("Could not grant permission");
+            if grant.permission == PermissionKind::Banned
+                && grant.reason.is_none()
+            {
+                panic!(
+                    "Please supply a reason for the ban, which is shown to \
+                        the banned user"
+                );
+            }
+
+            let uid = expect_user(pool, grant.user, UserIdentity::uid).await;
+            Permission::grant_permission(
+                &conn,
+                uid,
+                grant.permission,
+                grant.reason.as_deref(),
+            )
+            .expect("Could not grant permission");
             println!("Permission granted!");
         }
         PermissionSubCommand::Revoke(revoke) => {
-            let uid = UserIdentity::try_from(revoke.user)
-                .expect(
-                    "Could not infer user; is your login \
-                        prefixed with @?",
-                )
-                .uid(&conn)
-                .expect("Could not query database");
+            let uid = expect_user(pool, revoke.user, UserIdentity::uid).await;
             let r =
                 Permission::revoke_permission(&conn, uid, &revoke.permission)
                     .expect("Could not revoke permission");
             println!("Revoked {} permissions", r);
         }
         PermissionSubCommand::Show(show) => {
-            if show.user.is_some() {
-                let uid = UserIdentity::try_from(show.user.unwrap())
-                    .expect(
-                        "Could not infer user; is your login \
-                            prefixed with @?",
-                    )
-                    .uid(&conn)
-                    .expect("Could not query database");
+            if let Some(user) = show.user {
+                let uid = expect_user(pool, user, UserIdentity::uid).await;
                 let perms = Permission::find_by_gh_user_id(&conn, uid)
                     .expect("Could not query db");
 
                 if perms.len() > 0 {
                     println!("Permissions for user {}", uid);
                     for perm in perms {
-                        println!("  {}", perm.name);
+                        match perm.kind() {
+                            Some(_) => println!(
+                                "  {}, granted {} UTC{}",
+                                perm.name,
+                                perm.created_at.format("%Y-%m-%d %H:%M"),
+                                perm.reason
+                                    .map(|r| format!(": {}", r))
+                                    .unwrap_or_default()
+                            ),
+                            None => println!(
+                                "  {} (not a known permission, so it does \
+                                    nothing; revoke it)",
+                                perm.name
+                            ),
+                        }
                     }
                 } else {
                     println!("User {} has no permissions", uid);
@@ -142,6 +179,421 @@ fn perms_subcmd(pool: &DbPool, subcmd: crate::cliopts::Permission) {
     }
 }
 
+async fn user_subcmd(pool: &DbPool, subcmd: crate::cliopts::User) {
+    use crate::cliopts::UserSubCommand;
+    use crate::controllers::gh_oauth::{gh_client, sync_user_detail};
+    use crate::models::{Permission, Session};
+
+    let conn = pool
+        .get()
+        .expect("Could not get a connection from the pool");
+
+    match subcmd.subcmd {
+        UserSubCommand::List(_) => {
+            let users =
+                GhUserRecord::find_all(&conn).expect("Could not query db");
+
+            if users.len() > 0 {
+                for user in users {
+                    println!("{} @{}", user.id, user.login);
+                }
+            } else {
+                println!("Nobody has logged in yet");
+            }
+        }
+        UserSubCommand::Show(show) => {
+            let user = expect_user(pool, show.user, UserIdentity::find).await;
+            let permissions = Permission::find_by_gh_user_id(&conn, user.id)
+                .expect("Could not query db");
+            let sessions = Session::find_by_gh_user_id(&conn, user.id)
+                .expect("Could not query db");
+
+            println!("User {} @{}", user.id, user.login);
+            println!("  Profile: {}", user.html_url);
+            println!("  Avatar: {}", user.avatar_url);
+            println!(
+                "  Permissions: {}",
+                permissions
+                    .iter()
+                    .map(|p| p.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            println!("  Sessions: {}", sessions.len());
+        }
+        UserSubCommand::Sync(sync) => {
+            let user = expect_user(pool, sync.user, UserIdentity::find).await;
+            let api_base_url = env::var("GH_API_BASE_URL")
+                .unwrap_or_else(|_| GH_API_BASE_URL.to_string());
+            let synced =
+                sync_user_detail(&gh_client(), pool, &api_base_url, user.id)
+                    .await
+                    .expect("Could not sync user with Github");
+
+            if synced.login != user.login {
+                println!(
+                    "User {} is now @{}, formerly @{}",
+                    synced.id, synced.login, user.login
+                );
+            } else {
+                println!("User {} @{} is up to date", synced.id, synced.login);
+            }
+        }
+        UserSubCommand::Delete(delete) => {
+            let user = expect_user(pool, delete.user, UserIdentity::find).await;
+            let (id, login) = (user.id, user.login.clone());
+
+            user.delete(&conn).expect("Could not delete user");
+            println!("Deleted user {} @{}", id, login);
+        }
+    }
+}
+
+async fn session_subcmd(pool: &DbPool, subcmd: crate::cliopts::Session) {
+    use crate::cliopts::SessionSubCommand;
+    use crate::models::Session;
+
+    let conn = pool
+        .get()
+        .expect("Could not get a connection from the pool");
+
+    match subcmd.subcmd {
+        SessionSubCommand::List(list) => {
+            let sessions = match list.user {
+                Some(user) => {
+                    let uid = expect_user(pool, user, UserIdentity::uid).await;
+                    Session::find_by_gh_user_id(&conn, uid)
+                }
+                None => Session::find_all(&conn),
+            }
+            .expect("Could not query db");
+
+            if sessions.len() > 0 {
+                for session in sessions {
+                    println!(
+                        "Session {} of user {}, logged in {} UTC, last seen \
+                            {} UTC{}",
+                        session.id,
+                        session.gh_user_id,
+                        session.created_at.format("%Y-%m-%d %H:%M"),
+                        session.last_seen_at.format("%Y-%m-%d %H:%M"),
+                        if session.is_expired() {
+                            " (expired)"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            } else {
+                println!("There are no sessions");
+            }
+        }
+        SessionSubCommand::Revoke(revoke) => {
+            if let Some(id) = revoke.id {
+                let session = Session::find_by_id(&conn, id)
+                    .expect("Could not query db")
+                    .expect("No such session");
+                session.delete(&conn).expect("Could not revoke session");
+                println!("Revoked session {}", id);
+            } else if let Some(user) = revoke.user {
+                let uid = expect_user(pool, user, UserIdentity::uid).await;
+                let r = Session::delete_by_gh_user_id(&conn, uid)
+                    .expect("Could not revoke sessions");
+                println!("Revoked {} sessions", r);
+            } else {
+                panic!("Please supply either a session id or a user to revoke");
+            }
+        }
+    }
+}
+
+async fn attachments_subcmd(
+    pool: &DbPool,
+    attachment_storage: &AttachmentStorage,
+    subcmd: crate::cliopts::Attachments,
+) {
+    use crate::attachments::AttachmentStorageError;
+    use crate::cliopts::AttachmentsSubCommand;
+    use crate::models::Attachment;
+    use hex::encode as hex_encode;
+    use std::collections::HashSet;
+
+    let conn = pool
+        .get()
+        .expect("Could not get a connection from the pool");
+
+    match subcmd.subcmd {
+        AttachmentsSubCommand::Verify(verify) => {
+            let attachments =
+                Attachment::find_all(&conn).expect("Could not query db");
+            let attachment_count = attachments.len();
+            let known_ids: HashSet<i32> =
+                attachments.iter().map(|a| a.id).collect();
+            let mut problems = 0;
+
+            for mut attachment in attachments {
+                let corrupted =
+                    match attachment_storage.digest(attachment.id).await {
+                        Err(AttachmentStorageError::NotFound(location)) => {
+                            println!(
+                                "Attachment {} ({}) is missing from {}",
+                                attachment.id, attachment.name, location
+                            );
+                            true
+                        }
+                        Err(e) => panic!(
+                            "Could not read attachment {}: {}",
+                            attachment.id, e
+                        ),
+                        Ok(digests) => {
+                            let md5_matches = attachment.md5 == digests.md5;
+                            let sha256_matches = match &attachment.sha256 {
+                                Some(sha256) => *sha256 == digests.sha256,
+                                None => true,
+                            };
+
+                            if !md5_matches {
+                                println!(
+                                "Attachment {} ({}) has md5 {}, expected {}",
+                                attachment.id,
+                                attachment.name,
+                                hex_encode(digests.md5),
+                                hex_encode(&attachment.md5)
+                            );
+                            }
+
+                            if !sha256_matches {
+                                println!(
+                                "Attachment {} ({}) has sha256 {}, expected {}",
+                                attachment.id,
+                                attachment.name,
+                                hex_encode(digests.sha256),
+                                hex_encode(attachment.sha256.as_ref().unwrap())
+                            );
+                            }
+
+                            if md5_matches
+                                && attachment.sha256.is_none()
+                                && verify.record_missing_sha256
+                            {
+                                attachment.sha256 =
+                                    Some(digests.sha256.to_vec());
+                                attachment
+                                    .update(&conn)
+                                    .expect("Could not update attachment");
+                                println!(
+                                    "Recorded sha256 {} for attachment {} ({})",
+                                    hex_encode(digests.sha256),
+                                    attachment.id,
+                                    attachment.name
+                                );
+                            }
+
+                            !md5_matches || !sha256_matches
+                        }
+                    };
+
+                if corrupted {
+                    problems += 1;
+
+                    if verify.unpublish_corrupted && attachment.published {
+                        attachment.published = false;
+                        attachment
+                            .update(&conn)
+                            .expect("Could not update attachment");
+                        println!("  Unpublished attachment {}", attachment.id);
+                    }
+                }
+            }
+
+            let inventory = attachment_storage
+                .inventory()
+                .await
+                .expect("Could not list attachment storage");
+
+            for id in inventory.attachment_ids {
+                if !known_ids.contains(&id) {
+                    problems += 1;
+                    println!("Stored file {} has no attachment", id);
+                }
+            }
+
+            for location in inventory.unrecognized {
+                problems += 1;
+                println!("Stray file {} in storage", location);
+            }
+
+            if problems > 0 {
+                println!("Found {} problems", problems);
+                std::process::exit(1);
+            } else {
+                println!("Verified {} attachments", attachment_count);
+            }
+        }
+        AttachmentsSubCommand::MigrateStorage(migrate) => {
+            use crate::attachments::StorageSpec;
+
+            // copying attachments onto themselves and then deleting the
+            // "source" would lose every one of them
+            let source_spec: StorageSpec =
+                expect_env_string("UDEVGAMES_ATTACHMENT_STORAGE")
+                    .parse()
+                    .expect("Could not parse attachment storage");
+            let destination_spec: StorageSpec = migrate
+                .to
+                .parse()
+                .expect("Could not parse attachment storage");
+            if source_spec.same_location(&destination_spec) {
+                println!(
+                    "{} is where attachments are already stored",
+                    migrate.to
+                );
+                std::process::exit(1);
+            }
+
+            let destination = attachment_storage_from_spec(&migrate.to);
+            let attachments =
+                Attachment::find_all(&conn).expect("Could not query db");
+            let mut failures = 0;
+
+            for attachment in &attachments {
+                match migrate_attachment(
+                    attachment,
+                    attachment_storage,
+                    &destination,
+                )
+                .await
+                {
+                    Ok(()) => {
+                        println!(
+                            "Copied attachment {} ({})",
+                            attachment.id, attachment.name
+                        );
+
+                        if migrate.delete_source {
+                            attachment_storage
+                                .delete(attachment.id)
+                                .await
+                                .expect("Could not delete attachment");
+                        }
+                    }
+                    Err(e) => {
+                        failures += 1;
+                        println!(
+                            "Could not copy attachment {} ({}): {}",
+                            attachment.id, attachment.name, e
+                        );
+                    }
+                }
+            }
+
+            if failures > 0 {
+                println!(
+                    "Could not copy {} of {} attachments",
+                    failures,
+                    attachments.len()
+                );
+                std::process::exit(1);
+            } else {
+                println!(
+                    "Copied {} attachments. Set UDEVGAMES_ATTACHMENT_STORAGE \
+                        to {} to start using them.",
+                    attachments.len(),
+                    migrate.to
+                );
+            }
+        }
+    }
+}
+
+/// Copies one attachment from one storage to another by way of a temporary
+/// file, checking along the way that it is what was originally uploaded.
+async fn migrate_attachment(
+    attachment: &crate::models::Attachment,
+    source: &AttachmentStorage,
+    destination: &AttachmentStorage,
+) -> anyhow::Result<()> {
+    use crate::attachments::digest_file;
+    use rocket::tokio::{
+        fs::File,
+        io::{copy, AsyncWriteExt},
+    };
+
+    let size = source.size(attachment.id).await?;
+    let mut body = source.load(attachment.id, 0, size).await?;
+    let temp_file = destination.temp_file()?;
+    let mut file = File::from_std(temp_file.reopen()?);
+    copy(&mut body, &mut file).await?;
+    file.flush().await?;
+    drop(file);
+
+    let temp_path = temp_file.into_temp_path();
+    let digests = digest_file(&temp_path).await?;
+
+    if attachment.md5 != digests.md5
+        || attachment
+            .sha256
+            .iter()
+            .any(|sha256| *sha256 != digests.sha256)
+    {
+        anyhow::bail!("it does not match its recorded hashes");
+    }
+
+    destination.store(&temp_path, attachment.id).await?;
+
+    Ok(())
+}
+
+/// Sets up attachment storage as described by a
+/// [`crate::attachments::StorageSpec`], reading the details of an object store
+/// from the environment.
+fn attachment_storage_from_spec(spec: &str) -> AttachmentStorage {
+    use crate::attachments::{S3Backend, S3Config, StorageSpec};
+
+    match spec.parse().expect("Could not parse attachment storage") {
+        StorageSpec::Directory(layout, path) => {
+            AttachmentStorage::directory(layout, path)
+        }
+        StorageSpec::S3(bucket) => {
+            let config = S3Config {
+                endpoint: expect_env_string("UDEVGAMES_S3_ENDPOINT")
+                    .parse()
+                    .expect("Could not parse UDEVGAMES_S3_ENDPOINT as a url"),
+                region: expect_env_string("UDEVGAMES_S3_REGION"),
+                bucket,
+                access_key: expect_env_string("UDEVGAMES_S3_ACCESS_KEY"),
+                secret_key: expect_env_string("UDEVGAMES_S3_SECRET_KEY"),
+            };
+            let upload_path = env::var("UDEVGAMES_ATTACHMENT_UPLOADS")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| env::temp_dir());
+
+            AttachmentStorage::new(
+                Box::new(S3Backend::new(config)),
+                upload_path,
+            )
+        }
+    }
+}
+
+/// Points the OAuth workflow at the fake Github which the site serves itself
+/// under `/fake_github`. Both the browser and the site itself have to be able
+/// to reach it at the same url.
+fn fake_gh_credentials(address: &str, port: u16) -> GhCredentials {
+    let host = match address {
+        "0.0.0.0" | "::" => "localhost",
+        address => address,
+    };
+    let base_url = format!("http://{}:{}/fake_github", host, port);
+
+    GhCredentials {
+        client_id: "fake".to_string(),
+        client_secret: "fake".to_string(),
+        oauth_base_url: base_url.clone(),
+        api_base_url: base_url,
+    }
+}
+
 /// A user can be known either by id or by login. This enum abstracts over the
 /// two.
 enum UserIdentity {
@@ -152,30 +604,138 @@ enum UserIdentity {
     Id(i64),
 }
 
+/// Why a [`UserIdentity`] could not be narrowed down to a single user.
+#[derive(Debug, Error)]
+enum UserLookupError {
+    #[error("No such user")]
+    NoSuchUser,
+
+    /// More than one user has had the login, as far as our cache knows.
+    #[error("More than one user has had that login")]
+    Ambiguous(Vec<GhUserRecord>),
+
+    #[error("Could not query database: {0}")]
+    ModelError(#[from] ModelError),
+}
+
 impl UserIdentity {
-    /// The uid for this identity.
-    fn uid(&self, conn: &DbConn) -> Result<i64, ModelError> {
+    /// The uid for this identity. An id is taken at its word, without looking
+    /// for a user, so that permissions can be granted before someone's first
+    /// login.
+    fn uid(&self, conn: &DbConn) -> Result<i64, UserLookupError> {
         match self {
             UserIdentity::Id(id) => Ok(*id),
-            UserIdentity::Login(_) => Ok(self
-                .find(conn)
-                .expect("Could not query db")
-                .expect("No such user")
-                .id),
+            UserIdentity::Login(_) => Ok(self.find(conn)?.id),
         }
     }
 
-    /// Find a GhUserRecord for this UserIdentity, if one exists.
-    fn find(&self, conn: &DbConn) -> Result<Option<GhUserRecord>, ModelError> {
-        match self {
+    /// Find the one GhUserRecord for this UserIdentity.
+    fn find(&self, conn: &DbConn) -> Result<GhUserRecord, UserLookupError> {
+        let mut users = match self {
             UserIdentity::Login(login) => {
-                GhUserRecord::find_by_login(conn, login)
+                GhUserRecord::find_by_login(conn, login)?
+            }
+            UserIdentity::Id(id) => {
+                GhUserRecord::find_by_id(conn, *id)?.into_iter().collect()
             }
-            UserIdentity::Id(id) => GhUserRecord::find_by_id(conn, *id),
+        };
+
+        match users.len() {
+            0 => Err(UserLookupError::NoSuchUser),
+            1 => Ok(users.remove(0)),
+            _ => Err(UserLookupError::Ambiguous(users)),
+        }
+    }
+}
+
+/// Looks up the user given on the command line with `lookup`, which is either
+/// [`UserIdentity::uid`] or [`UserIdentity::find`], and exits if there isn't
+/// exactly one. Should a login belong to more than one user in our cache, the
+/// candidates are listed and the operator is offered a sync with Github,
+/// which finds out who has the login now.
+async fn expect_user<T>(
+    pool: &DbPool,
+    user: String,
+    lookup: fn(&UserIdentity, &DbConn) -> Result<T, UserLookupError>,
+) -> T {
+    use crate::controllers::gh_oauth::{gh_client, sync_user_detail};
+
+    let identity = UserIdentity::try_from(user)
+        .expect("Could not infer user; is your login prefixed with @?");
+    let lookup_with_pool = || {
+        let conn = pool
+            .get()
+            .expect("Could not get a connection from the pool");
+        lookup(&identity, &conn)
+    };
+
+    let candidates = match lookup_with_pool() {
+        Ok(found) => return found,
+        Err(UserLookupError::Ambiguous(candidates)) => candidates,
+        Err(e) => exit_with_lookup_error(e),
+    };
+
+    println!("More than one user has had that login:");
+    for candidate in &candidates {
+        println!("  {} @{}", candidate.id, candidate.login);
+    }
+
+    if !confirm("Sync these users with Github to see who has it now?") {
+        println!("Please give the id of the user you mean instead");
+        std::process::exit(1);
+    }
+
+    let api_base_url = env::var("GH_API_BASE_URL")
+        .unwrap_or_else(|_| GH_API_BASE_URL.to_string());
+    let client = gh_client();
+    for candidate in candidates {
+        let synced =
+            sync_user_detail(&client, pool, &api_base_url, candidate.id)
+                .await
+                .expect("Could not sync user with Github");
+        println!("User {} is @{}", synced.id, synced.login);
+    }
+
+    lookup_with_pool().unwrap_or_else(|e| exit_with_lookup_error(e))
+}
+
+fn exit_with_lookup_error(e: UserLookupError) -> ! {
+    match e {
+        UserLookupError::Ambiguous(candidates) => {
+            println!(
+                "Users {} all still have that login; please give the id of \
+                    the user you mean instead",
+                candidates
+                    .iter()
+                    .map(|c| c.id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            std::process::exit(1);
         }
+        UserLookupError::NoSuchUser => {
+            println!("No such user");
+            std::process::exit(1);
+        }
+        e => panic!("{}", e),
     }
 }
 
+/// Asks a yes or no question on the terminal, taking anything but yes as no.
+fn confirm(question: &str) -> bool {
+    use std::io::{stdin, stdout, Write};
+
+    print!("{} [y/N] ", question);
+    stdout().flush().expect("Could not write to stdout");
+
+    let mut answer = String::new();
+    stdin()
+        .read_line(&mut answer)
+        .expect("Could not read from stdin");
+
+    matches!(answer.trim(), "y" | "Y" | "yes" | "Yes")
+}
+
 impl TryFrom<String> for UserIdentity {
     type Error = ParseIntError;
 
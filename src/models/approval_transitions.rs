@@ -0,0 +1,152 @@
+use crate::db::DbConn;
+use crate::models::{ApprovalRole, ApprovalState, GhUserRecord, ModelError};
+use chrono::NaiveDateTime;
+
+/// The content an [`ApprovalTransition`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalSubject {
+    Jam(i32),
+    JamEntry(i32),
+    JamEntryUpdate(i32),
+}
+
+impl ApprovalSubject {
+    /// The name stored in `subject_kind`.
+    fn kind(&self) -> &'static str {
+        match self {
+            ApprovalSubject::Jam(_) => "jam",
+            ApprovalSubject::JamEntry(_) => "jam_entry",
+            ApprovalSubject::JamEntryUpdate(_) => "jam_entry_update",
+        }
+    }
+
+    /// The id stored in `subject_id`.
+    fn id(&self) -> i32 {
+        match self {
+            ApprovalSubject::Jam(id)
+            | ApprovalSubject::JamEntry(id)
+            | ApprovalSubject::JamEntryUpdate(id) => *id,
+        }
+    }
+}
+
+/// Models one move of a jam, entry or update between approval states. These
+/// are recorded as the move is made, and are never changed afterwards.
+#[derive(Debug, Queryable)]
+pub struct ApprovalTransition {
+    pub id: i32,
+    pub subject_kind: String,
+    pub subject_id: i32,
+    pub from_state: ApprovalState,
+    pub to_state: ApprovalState,
+    pub actor_user_id: Option<i64>,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl ApprovalTransition {
+    /// Moves `subject` from one approval state to another on behalf of a user
+    /// playing `roles`, checking that they may and recording that they did.
+    /// Gives back the rejection reason the content now has, which is `reason`
+    /// when it has been rejected and nothing otherwise. Content models call
+    /// this from their own `transition`, rather than it being called directly.
+    pub(super) fn apply(
+        conn: &DbConn,
+        subject: ApprovalSubject,
+        from: ApprovalState,
+        to: ApprovalState,
+        the_actor_user_id: i64,
+        roles: &[ApprovalRole],
+        the_reason: Option<&str>,
+    ) -> Result<Option<String>, ModelError> {
+        use crate::schema::approval_transitions::dsl::{
+            actor_user_id, approval_transitions, created_at, from_state,
+            reason, subject_id, subject_kind, to_state,
+        };
+        use crate::schema::{jam_entries, jam_entry_updates, jams};
+        use diesel::prelude::*;
+
+        if !from.may_transition(to, roles) {
+            return Err(ModelError::ForbiddenApprovalTransition { from, to });
+        }
+
+        let rejection_reason = match to {
+            ApprovalState::Rejected => the_reason.map(|r| r.to_string()),
+            _ => None,
+        };
+
+        conn.transaction::<(), ModelError, _>(|| {
+            diesel::insert_into(approval_transitions)
+                .values((
+                    subject_kind.eq(subject.kind()),
+                    subject_id.eq(subject.id()),
+                    from_state.eq(from),
+                    to_state.eq(to),
+                    actor_user_id.eq(Some(the_actor_user_id)),
+                    reason.eq(the_reason),
+                    created_at.eq(chrono::offset::Utc::now().naive_utc()),
+                ))
+                .execute(conn)?;
+
+            match subject {
+                ApprovalSubject::Jam(jam_id) => {
+                    diesel::update(jams::table.find(jam_id))
+                        .set((
+                            jams::approval_state.eq(to),
+                            jams::rejection_reason.eq(&rejection_reason),
+                        ))
+                        .execute(conn)?
+                }
+                ApprovalSubject::JamEntry(jam_entry_id) => {
+                    diesel::update(jam_entries::table.find(jam_entry_id))
+                        .set((
+                            jam_entries::approval_state.eq(to),
+                            jam_entries::rejection_reason.eq(&rejection_reason),
+                        ))
+                        .execute(conn)?
+                }
+                ApprovalSubject::JamEntryUpdate(jam_entry_update_id) => {
+                    diesel::update(
+                        jam_entry_updates::table.find(jam_entry_update_id),
+                    )
+                    .set((
+                        jam_entry_updates::approval_state.eq(to),
+                        jam_entry_updates::rejection_reason
+                            .eq(&rejection_reason),
+                    ))
+                    .execute(conn)?
+                }
+            };
+
+            Ok(())
+        })?;
+
+        Ok(rejection_reason)
+    }
+
+    /// Finds every move some content has made, oldest first.
+    pub fn find_by_subject(
+        conn: &DbConn,
+        subject: ApprovalSubject,
+    ) -> Result<Vec<ApprovalTransition>, ModelError> {
+        use crate::schema::approval_transitions::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(approval_transitions
+            .filter(subject_kind.eq(subject.kind()))
+            .filter(subject_id.eq(subject.id()))
+            .order(id.asc())
+            .load::<ApprovalTransition>(conn)?)
+    }
+
+    /// Loads whoever made the move, unless they've since been deleted.
+    pub fn load_actor(
+        &self,
+        conn: &DbConn,
+    ) -> Result<Option<GhUserRecord>, ModelError> {
+        match self.actor_user_id {
+            Some(user_id) => GhUserRecord::find_by_id(conn, user_id),
+            None => Ok(None),
+        }
+    }
+}
@@ -1,10 +1,9 @@
 use crate::{
-    attachments::AttachmentStorage,
+    attachments::Digests,
     db::DbConn,
     models::{last_insert_rowid, r_to_opt, ModelError},
 };
 use diesel::result::Error as DieselError;
-use std::path::PathBuf;
 
 /// An attachment, which is a file on disk.
 #[derive(Debug, Queryable)]
@@ -25,36 +24,43 @@ pub struct Attachment {
     /// The MD5 of the file. If this differs from what is on disk, we may be
     /// experiencing bitrot or an attack.
     pub md5: Vec<u8>,
+
+    /// The user who uploaded this attachment. Attachments from before this was
+    /// tracked have no owner.
+    pub owner_user_id: Option<i64>,
+
+    /// The SHA-256 of the file, which is what users should check their
+    /// downloads against. Attachments stored before this was tracked don't
+    /// have one until they are rehashed.
+    pub sha256: Option<Vec<u8>>,
 }
 
 impl Attachment {
-    /// Create a new attachment from a temporary file. Copies it to a permanent
-    /// storage location and md5's it.
+    /// Records a new attachment with the digests of its file. The file itself
+    /// is put into [`crate::attachments::AttachmentStorage`] under the id of
+    /// the returned attachment, once it has one.
     pub fn create(
         conn: &DbConn,
-        attachment_storage: &AttachmentStorage,
-        the_file: impl AsRef<PathBuf>,
         the_name: &str,
         the_mime_type: &str,
+        the_owner_user_id: Option<i64>,
+        digests: &Digests,
     ) -> Result<Attachment, ModelError> {
         use crate::schema::attachments::dsl::{
-            attachments, id, md5, mime_type, name,
+            attachments, id, md5, mime_type, name, owner_user_id, sha256,
         };
         use diesel::prelude::*;
 
-        let the_file = the_file.as_ref();
-
-        if !the_file.exists() {
-            return Err(ModelError::FileNotFoundError(the_file.clone()));
-        }
-
         let attachment =
             // transaction so last_insert_rowid doesn't do anything untoward
             conn.transaction::<Attachment, DieselError, _>(|| {
                 diesel::insert_into(attachments)
                     .values((
-                        name.eq(the_name), mime_type.eq(the_mime_type),
-                        md5.eq(vec![]) // how to insert binary data?
+                        name.eq(the_name),
+                        mime_type.eq(the_mime_type),
+                        md5.eq(digests.md5.to_vec()),
+                        owner_user_id.eq(the_owner_user_id),
+                        sha256.eq(Some(digests.sha256.to_vec())),
                     ))
                     .execute(conn)?;
                 let rowid = diesel::select(last_insert_rowid)
@@ -67,13 +73,6 @@ impl Attachment {
                 )
             })?;
 
-        let mut stored_attachment =
-            attachment_storage.store(&the_file, attachment.id)?;
-
-        diesel::update(attachments)
-            .set(md5.eq(stored_attachment.get_or_compute_md5()?.to_vec()))
-            .execute(conn)?;
-
         Ok(attachment)
     }
 
@@ -111,6 +110,52 @@ impl Attachment {
         r_to_opt(attachment)
     }
 
+    /// Finds every attachment, in the order they were created.
+    pub fn find_all(conn: &DbConn) -> Result<Vec<Attachment>, ModelError> {
+        use crate::schema::attachments::dsl::{attachments, id};
+        use diesel::prelude::*;
+
+        Ok(attachments.order(id.asc()).load::<Attachment>(conn)?)
+    }
+
+    /// Updates an Attachment by making what's in the database look like what's
+    /// on the model.
+    pub fn update(&self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::attachments::dsl::{
+            attachments, md5, mime_type, name, owner_user_id, published, sha256,
+        };
+        use diesel::prelude::*;
+
+        diesel::update(attachments.find(self.id))
+            .set((
+                name.eq(&self.name),
+                published.eq(self.published),
+                mime_type.eq(&self.mime_type),
+                md5.eq(&self.md5),
+                owner_user_id.eq(self.owner_user_id),
+                sha256.eq(&self.sha256),
+            ))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Deletes the record of this attachment. Whatever is in storage for it
+    /// has to be cleaned up separately.
+    pub fn delete(self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::attachments::dsl::attachments;
+        use diesel::prelude::*;
+
+        diesel::delete(attachments.find(self.id)).execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Whether the given user uploaded this attachment.
+    pub fn is_owned_by(&self, user_id: i64) -> bool {
+        self.owner_user_id == Some(user_id)
+    }
+
     pub fn url(&self) -> String {
         format!("/attachments/{}/{}", self.id, self.name)
     }
@@ -1,6 +1,6 @@
 use crate::{
     db::DbConn,
-    models::{r_to_opt, ModelError},
+    models::{r_to_opt, JamEntryVote, ModelError, Permission, Session},
 };
 
 /// Local cache of part of Github's understanding of who a user is. Particularly
@@ -85,19 +85,99 @@ impl GhUserRecord {
         r_to_opt(user_record)
     }
 
-    /// Finds a given GhUserRecord by its login.
+    /// Finds every GhUserRecord with one of the given ids, in no particular
+    /// order. Ids nobody has are skipped.
+    pub fn find_by_ids(
+        conn: &DbConn,
+        gh_user_ids: &[i64],
+    ) -> Result<Vec<GhUserRecord>, ModelError> {
+        use crate::schema::gh_user_records::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(gh_user_records
+            .filter(id.eq_any(gh_user_ids))
+            .load::<GhUserRecord>(conn)?)
+    }
+
+    /// Finds every GhUserRecord with a given login, ordered by id. There can
+    /// be more than one, since a login someone gave up can be taken by
+    /// somebody else before either of them logs in again to update our cache.
     pub fn find_by_login(
         conn: &DbConn,
         gh_login: &str,
-    ) -> Result<Option<GhUserRecord>, ModelError> {
+    ) -> Result<Vec<GhUserRecord>, ModelError> {
         use crate::schema::gh_user_records::dsl::*;
         use diesel::prelude::*;
 
-        let user_record = gh_user_records
+        Ok(gh_user_records
             .filter(login.eq(gh_login))
-            .limit(1)
-            .first::<GhUserRecord>(conn);
+            .order(id.asc())
+            .load::<GhUserRecord>(conn)?)
+    }
 
-        r_to_opt(user_record)
+    /// Finds every GhUserRecord, ordered by login.
+    pub fn find_all(conn: &DbConn) -> Result<Vec<GhUserRecord>, ModelError> {
+        use crate::schema::gh_user_records::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(gh_user_records
+            .order(login.asc())
+            .load::<GhUserRecord>(conn)?)
+    }
+
+    /// Deletes a GhUserRecord along with their permissions, sessions and votes.
+    /// The attachments, revisions and approval transitions they made are kept,
+    /// but no longer say who made them. Users who have entered jams can't be
+    /// deleted, as their entries would be left without a submitter, and nor can
+    /// banned users, as their ban would go with them.
+    pub fn delete(self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::{
+            approval_transitions, attachments, gh_user_records, jam_entries,
+            permissions, rich_text_revisions,
+        };
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            let entry_count = jam_entries::table
+                .filter(jam_entries::submitter_user_id.eq(self.id))
+                .count()
+                .get_result::<i64>(conn)?;
+            if entry_count > 0 {
+                return Err(ModelError::UserHasJamEntries(self.id));
+            }
+
+            if Permission::find_ban(conn, self.id)?.is_some() {
+                return Err(ModelError::UserIsBanned(self.id));
+            }
+
+            Session::delete_by_gh_user_id(conn, self.id)?;
+            JamEntryVote::delete_by_voter_user_id(conn, self.id)?;
+            diesel::delete(
+                permissions::table.filter(permissions::gh_user_id.eq(self.id)),
+            )
+            .execute(conn)?;
+            diesel::update(
+                attachments::table
+                    .filter(attachments::owner_user_id.eq(self.id)),
+            )
+            .set(attachments::owner_user_id.eq(None::<i64>))
+            .execute(conn)?;
+            diesel::update(
+                rich_text_revisions::table
+                    .filter(rich_text_revisions::author_user_id.eq(self.id)),
+            )
+            .set(rich_text_revisions::author_user_id.eq(None::<i64>))
+            .execute(conn)?;
+            diesel::update(
+                approval_transitions::table
+                    .filter(approval_transitions::actor_user_id.eq(self.id)),
+            )
+            .set(approval_transitions::actor_user_id.eq(None::<i64>))
+            .execute(conn)?;
+            diesel::delete(gh_user_records::table.find(self.id))
+                .execute(conn)?;
+
+            Ok(())
+        })
     }
 }
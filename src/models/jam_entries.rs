@@ -0,0 +1,317 @@
+use crate::db::DbConn;
+use crate::models::{
+    last_insert_rowid, r_to_opt, ApprovalRole, ApprovalState, ApprovalSubject,
+    ApprovalTransition, Attachment, GhUserRecord, JamEntryUpdate, JamEntryVote,
+    ModelError, Permission, PermissionKind, RichText,
+};
+
+/// Models an entry into a game jam, which is a game that someone is making for
+/// that jam.
+#[derive(Debug, Queryable)]
+pub struct JamEntry {
+    pub id: i32,
+    pub jam_id: i32,
+    pub submitter_user_id: i64,
+    pub approval_state: ApprovalState,
+    pub title: String,
+    pub slug: String,
+    pub summary: String,
+    pub summary_attachment_id: Option<i32>,
+    pub rich_text_id: i32,
+    /// Why a moderator rejected this entry, while it is rejected.
+    pub rejection_reason: Option<String>,
+}
+
+impl JamEntry {
+    /// Creates a new draft entry in the given jam, owned by the given user.
+    pub fn create(
+        conn: &DbConn,
+        for_jam_id: i32,
+        for_submitter_user_id: i64,
+    ) -> Result<JamEntry, ModelError> {
+        use crate::schema::jam_entries::dsl::{
+            approval_state, id, jam_entries, jam_id, rich_text_id, slug,
+            submitter_user_id, summary, summary_attachment_id, title,
+        };
+        use diesel::prelude::*;
+
+        let jam_entry = conn.transaction::<JamEntry, ModelError, _>(|| {
+            let rich_text = RichText::create(conn)?;
+
+            diesel::insert_into(jam_entries)
+                .values((
+                    jam_id.eq(for_jam_id),
+                    submitter_user_id.eq(for_submitter_user_id),
+                    approval_state.eq(ApprovalState::Draft),
+                    title.eq("My Entry"),
+                    slug.eq("my-entry"),
+                    summary.eq("My really cool game"),
+                    summary_attachment_id.eq::<Option<i32>>(None),
+                    rich_text_id.eq(rich_text.id),
+                ))
+                .execute(conn)?;
+
+            let rowid =
+                diesel::select(last_insert_rowid).get_result::<i32>(conn)?;
+
+            Ok(jam_entries
+                .filter(id.eq(rowid))
+                .limit(1)
+                .first::<JamEntry>(conn)?)
+        })?;
+
+        Ok(jam_entry)
+    }
+
+    /// Finds a JamEntry by its id.
+    pub fn find_by_id(
+        conn: &DbConn,
+        jam_entry_id: i32,
+    ) -> Result<Option<JamEntry>, ModelError> {
+        use crate::schema::jam_entries::dsl::*;
+        use diesel::prelude::*;
+
+        let jam_entry = jam_entries
+            .filter(id.eq(jam_entry_id))
+            .limit(1)
+            .first::<JamEntry>(conn);
+
+        r_to_opt(jam_entry)
+    }
+
+    /// Finds all the entries in a Jam.
+    ///
+    /// * `approved_only` when `true` returns only
+    ///   [`crate::models::jam_entries::JamEntry`]s which have the
+    ///   `approval_state` [`crate::models::ApprovalState::Approved`] and
+    ///   whose submitter isn't banned.
+    /// * `or_submitted_by` additionally returns entries submitted by this user
+    ///   regardless of their `approval_state`, so that people can see their
+    ///   own entries before they are approved.
+    pub fn find_by_jam_id(
+        conn: &DbConn,
+        for_jam_id: i32,
+        approved_only: bool,
+        or_submitted_by: Option<i64>,
+    ) -> Result<Vec<JamEntry>, ModelError> {
+        use crate::schema::jam_entries::dsl::*;
+        use crate::schema::permissions;
+        use diesel::prelude::*;
+
+        let q = jam_entries.filter(jam_id.eq(for_jam_id)).order(id.asc());
+        let public = || {
+            approval_state.eq(ApprovalState::Approved).and(
+                submitter_user_id.ne_all(
+                    permissions::table.select(permissions::gh_user_id).filter(
+                        permissions::name.eq(PermissionKind::Banned.name()),
+                    ),
+                ),
+            )
+        };
+
+        let r = match (approved_only, or_submitted_by) {
+            (false, _) => q.load::<JamEntry>(conn)?,
+            (true, None) => q.filter(public()).load::<JamEntry>(conn)?,
+            (true, Some(user_id)) => q
+                .filter(public().or(submitter_user_id.eq(user_id)))
+                .load::<JamEntry>(conn)?,
+        };
+
+        Ok(r)
+    }
+
+    /// Finds every JamEntry in the given approval state, oldest first, such as
+    /// those [`crate::models::ApprovalState::Submitted`] and waiting for a
+    /// moderator.
+    pub fn find_by_approval_state(
+        conn: &DbConn,
+        state: ApprovalState,
+    ) -> Result<Vec<JamEntry>, ModelError> {
+        use crate::schema::jam_entries::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(jam_entries
+            .filter(approval_state.eq(state))
+            .order(id.asc())
+            .load::<JamEntry>(conn)?)
+    }
+
+    /// Counts every JamEntry in the given approval state.
+    pub fn count_by_approval_state(
+        conn: &DbConn,
+        state: ApprovalState,
+    ) -> Result<i64, ModelError> {
+        use crate::schema::jam_entries::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(jam_entries
+            .filter(approval_state.eq(state))
+            .count()
+            .get_result(conn)?)
+    }
+
+    /// Updates a JamEntry by making what's in the database look like what's on
+    /// the model. The approval state is left alone, as it only changes through
+    /// [`JamEntry::transition`].
+    pub fn update(&self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::jam_entries::dsl::{
+            jam_entries, jam_id, rich_text_id, slug, submitter_user_id,
+            summary, summary_attachment_id, title,
+        };
+        use diesel::prelude::*;
+
+        diesel::update(jam_entries.find(self.id))
+            .set((
+                jam_id.eq(self.jam_id),
+                submitter_user_id.eq(self.submitter_user_id),
+                title.eq(&self.title),
+                slug.eq(&self.slug),
+                summary.eq(&self.summary),
+                summary_attachment_id.eq(self.summary_attachment_id),
+                rich_text_id.eq(self.rich_text_id),
+            ))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Saves an edit to this JamEntry and its rich text on behalf of a user
+    /// playing `roles`. An owner who changes an approved entry puts it back up
+    /// for approval, so that a moderator sees what's now being shown.
+    pub fn revise(
+        &mut self,
+        conn: &DbConn,
+        rich_text: &RichText,
+        editor_user_id: i64,
+        roles: &[ApprovalRole],
+    ) -> Result<(), ModelError> {
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            let previous = JamEntry::find_by_id(conn, self.id)?
+                .ok_or(ModelError::NotFound)?;
+            let rich_text_changed = rich_text.update(conn, editor_user_id)?;
+            self.update(conn)?;
+
+            let changed = rich_text_changed
+                || previous.title != self.title
+                || previous.slug != self.slug
+                || previous.summary != self.summary
+                || previous.summary_attachment_id != self.summary_attachment_id;
+
+            if changed
+                && self.approval_state == ApprovalState::Approved
+                && roles.contains(&ApprovalRole::Owner)
+            {
+                self.transition(
+                    conn,
+                    ApprovalState::Submitted,
+                    editor_user_id,
+                    roles,
+                    None,
+                )?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Moves this JamEntry to another approval state on behalf of a user
+    /// playing `roles`, as [`ApprovalTransition::apply`] describes. Leaving
+    /// the state as it is does nothing.
+    pub fn transition(
+        &mut self,
+        conn: &DbConn,
+        to: ApprovalState,
+        actor_user_id: i64,
+        roles: &[ApprovalRole],
+        reason: Option<&str>,
+    ) -> Result<(), ModelError> {
+        if to == self.approval_state {
+            return Ok(());
+        }
+
+        self.rejection_reason = ApprovalTransition::apply(
+            conn,
+            ApprovalSubject::JamEntry(self.id),
+            self.approval_state,
+            to,
+            actor_user_id,
+            roles,
+            reason,
+        )?;
+        self.approval_state = to;
+
+        Ok(())
+    }
+
+    /// Deletes this JamEntry along with the rich text, updates and votes it
+    /// owns.
+    pub fn delete(self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::jam_entries::dsl::jam_entries;
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            let rich_text = self.load_rich_text(conn)?;
+
+            for update in
+                JamEntryUpdate::find_by_jam_entry_id(conn, self.id, false)?
+            {
+                update.delete(conn)?;
+            }
+            JamEntryVote::delete_by_jam_entry_id(conn, self.id)?;
+
+            diesel::delete(jam_entries.find(self.id)).execute(conn)?;
+            rich_text.delete(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Whether the given user submitted this entry.
+    pub fn is_submitted_by(&self, user_id: i64) -> bool {
+        self.submitter_user_id == user_id
+    }
+
+    /// Whether the submitter of this entry is banned, which hides it from
+    /// everyone but admins.
+    pub fn is_submitter_banned(
+        &self,
+        conn: &DbConn,
+    ) -> Result<bool, ModelError> {
+        Ok(Permission::find_ban(conn, self.submitter_user_id)?.is_some())
+    }
+
+    /// Loads the user who submitted this entry. Since every entry has a
+    /// submitter, the absence of this is a schema violation and is returned as
+    /// a [`crate::models::ModelError::NotFound`].
+    pub fn load_submitter(
+        &self,
+        conn: &DbConn,
+    ) -> Result<GhUserRecord, ModelError> {
+        GhUserRecord::find_by_id(conn, self.submitter_user_id)?
+            .ok_or(ModelError::NotFound)
+    }
+
+    /// Loads the summary attachment, if present.
+    pub fn load_attachment(
+        &self,
+        conn: &DbConn,
+    ) -> Result<Option<Attachment>, ModelError> {
+        match self.summary_attachment_id {
+            Some(id) => Ok(Attachment::find_by_id(conn, id)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Loads the rich text description of this JamEntry. Since every JamEntry
+    /// has a rich text description, the absence of this is a schema violation
+    /// and is returned as a [`crate::models::ModelError::NotFound`].
+    pub fn load_rich_text(
+        &self,
+        conn: &DbConn,
+    ) -> Result<RichText, ModelError> {
+        RichText::find_by_id(conn, self.rich_text_id)?
+            .ok_or(ModelError::NotFound)
+    }
+}
@@ -0,0 +1,325 @@
+use crate::db::DbConn;
+use crate::models::{
+    last_insert_rowid, r_to_opt, ApprovalRole, ApprovalState, ApprovalSubject,
+    ApprovalTransition, ModelError, RichText,
+};
+use chrono::NaiveDateTime;
+use reqwest::Url;
+
+/// Models an update to a jam entry, which is a devlog post describing some
+/// progress on the entry. The content of an update lives either here on the
+/// site as a rich text, or somewhere else on the Internet as an external
+/// link, but never both.
+#[derive(Debug, Queryable)]
+pub struct JamEntryUpdate {
+    pub id: i32,
+    pub jam_entry_id: i32,
+    pub title: String,
+    pub slug: String,
+    pub summary: String,
+    pub rich_text_id: Option<i32>,
+    pub external_content_url: Option<String>,
+    pub approval_state: ApprovalState,
+    pub created_at: NaiveDateTime,
+    /// Why a moderator rejected this update, while it is rejected.
+    pub rejection_reason: Option<String>,
+}
+
+/// Where the content of a new [`JamEntryUpdate`] is going to live.
+pub enum JamEntryUpdateContent {
+    /// The update is written on this site, in a new blank rich text.
+    RichText,
+
+    /// The update is written elsewhere, at this url.
+    External(String),
+}
+
+impl JamEntryUpdate {
+    /// Creates a new draft update on the given jam entry.
+    pub fn create(
+        conn: &DbConn,
+        for_jam_entry_id: i32,
+        content: JamEntryUpdateContent,
+    ) -> Result<JamEntryUpdate, ModelError> {
+        use crate::schema::jam_entry_updates::dsl::{
+            approval_state, created_at, external_content_url, id, jam_entry_id,
+            jam_entry_updates, rich_text_id, slug, summary, title,
+        };
+        use diesel::prelude::*;
+
+        conn.transaction::<JamEntryUpdate, ModelError, _>(|| {
+            let (the_rich_text_id, the_external_content_url) = match content {
+                JamEntryUpdateContent::RichText => {
+                    (Some(RichText::create(conn)?.id), None)
+                }
+                JamEntryUpdateContent::External(url) => {
+                    (None, Some(validate_external_content_url(&url)?))
+                }
+            };
+
+            diesel::insert_into(jam_entry_updates)
+                .values((
+                    jam_entry_id.eq(for_jam_entry_id),
+                    title.eq("My Update"),
+                    slug.eq("my-update"),
+                    summary.eq("What I got done today"),
+                    rich_text_id.eq(the_rich_text_id),
+                    external_content_url.eq(the_external_content_url),
+                    approval_state.eq(ApprovalState::Draft),
+                    created_at.eq(chrono::offset::Utc::now().naive_utc()),
+                ))
+                .execute(conn)?;
+
+            let rowid =
+                diesel::select(last_insert_rowid).get_result::<i32>(conn)?;
+
+            Ok(jam_entry_updates
+                .filter(id.eq(rowid))
+                .limit(1)
+                .first::<JamEntryUpdate>(conn)?)
+        })
+    }
+
+    /// Finds a JamEntryUpdate by its id.
+    pub fn find_by_id(
+        conn: &DbConn,
+        jam_entry_update_id: i32,
+    ) -> Result<Option<JamEntryUpdate>, ModelError> {
+        use crate::schema::jam_entry_updates::dsl::*;
+        use diesel::prelude::*;
+
+        let jam_entry_update = jam_entry_updates
+            .filter(id.eq(jam_entry_update_id))
+            .limit(1)
+            .first::<JamEntryUpdate>(conn);
+
+        r_to_opt(jam_entry_update)
+    }
+
+    /// Finds all the updates on a jam entry, oldest first, which is the order
+    /// a devlog reads in.
+    ///
+    /// * `approved_only` when `true` returns only
+    ///   [`crate::models::jam_entry_updates::JamEntryUpdate`]s which have the
+    ///   `approval_state` [`crate::models::ApprovalState::Approved`].
+    pub fn find_by_jam_entry_id(
+        conn: &DbConn,
+        for_jam_entry_id: i32,
+        approved_only: bool,
+    ) -> Result<Vec<JamEntryUpdate>, ModelError> {
+        use crate::schema::jam_entry_updates::dsl::*;
+        use diesel::prelude::*;
+
+        let q = jam_entry_updates
+            .filter(jam_entry_id.eq(for_jam_entry_id))
+            .order((created_at.asc(), id.asc()));
+
+        let r = if approved_only {
+            q.filter(approval_state.eq(ApprovalState::Approved))
+                .load::<JamEntryUpdate>(conn)?
+        } else {
+            q.load::<JamEntryUpdate>(conn)?
+        };
+
+        Ok(r)
+    }
+
+    /// Finds every JamEntryUpdate in the given approval state, oldest first,
+    /// such as those [`crate::models::ApprovalState::Submitted`] and waiting
+    /// for a moderator.
+    pub fn find_by_approval_state(
+        conn: &DbConn,
+        state: ApprovalState,
+    ) -> Result<Vec<JamEntryUpdate>, ModelError> {
+        use crate::schema::jam_entry_updates::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(jam_entry_updates
+            .filter(approval_state.eq(state))
+            .order((created_at.asc(), id.asc()))
+            .load::<JamEntryUpdate>(conn)?)
+    }
+
+    /// Counts every JamEntryUpdate in the given approval state.
+    pub fn count_by_approval_state(
+        conn: &DbConn,
+        state: ApprovalState,
+    ) -> Result<i64, ModelError> {
+        use crate::schema::jam_entry_updates::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(jam_entry_updates
+            .filter(approval_state.eq(state))
+            .count()
+            .get_result(conn)?)
+    }
+
+    /// Updates a JamEntryUpdate by making what's in the database look like
+    /// what's on the model. Refuses to save an update which has both or
+    /// neither of a rich text and an external content url. The approval state
+    /// is left alone, as it only changes through
+    /// [`JamEntryUpdate::transition`].
+    pub fn update(&self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::jam_entry_updates::dsl::{
+            external_content_url, jam_entry_id, jam_entry_updates,
+            rich_text_id, slug, summary, title,
+        };
+        use diesel::prelude::*;
+
+        if self.rich_text_id.is_some() == self.external_content_url.is_some() {
+            return Err(ModelError::JamEntryUpdateContentError);
+        }
+
+        let the_external_content_url = match &self.external_content_url {
+            Some(url) => Some(validate_external_content_url(url)?),
+            None => None,
+        };
+
+        diesel::update(jam_entry_updates.find(self.id))
+            .set((
+                jam_entry_id.eq(self.jam_entry_id),
+                title.eq(&self.title),
+                slug.eq(&self.slug),
+                summary.eq(&self.summary),
+                rich_text_id.eq(self.rich_text_id),
+                external_content_url.eq(the_external_content_url),
+            ))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Saves an edit to this JamEntryUpdate and its rich text, if it has one,
+    /// on behalf of a user playing `roles`. An owner who changes an approved
+    /// update puts it back up for approval, so that a moderator sees what's
+    /// now being shown.
+    pub fn revise(
+        &mut self,
+        conn: &DbConn,
+        rich_text: Option<&RichText>,
+        editor_user_id: i64,
+        roles: &[ApprovalRole],
+    ) -> Result<(), ModelError> {
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            let previous = JamEntryUpdate::find_by_id(conn, self.id)?
+                .ok_or(ModelError::NotFound)?;
+            let rich_text_changed = match rich_text {
+                Some(rich_text) => rich_text.update(conn, editor_user_id)?,
+                None => false,
+            };
+            self.update(conn)?;
+
+            let changed = rich_text_changed
+                || previous.title != self.title
+                || previous.slug != self.slug
+                || previous.summary != self.summary
+                || previous.external_content_url != self.external_content_url;
+
+            if changed
+                && self.approval_state == ApprovalState::Approved
+                && roles.contains(&ApprovalRole::Owner)
+            {
+                self.transition(
+                    conn,
+                    ApprovalState::Submitted,
+                    editor_user_id,
+                    roles,
+                    None,
+                )?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Moves this JamEntryUpdate to another approval state on behalf of a user
+    /// playing `roles`, as [`ApprovalTransition::apply`] describes. Leaving
+    /// the state as it is does nothing.
+    pub fn transition(
+        &mut self,
+        conn: &DbConn,
+        to: ApprovalState,
+        actor_user_id: i64,
+        roles: &[ApprovalRole],
+        reason: Option<&str>,
+    ) -> Result<(), ModelError> {
+        if to == self.approval_state {
+            return Ok(());
+        }
+
+        self.rejection_reason = ApprovalTransition::apply(
+            conn,
+            ApprovalSubject::JamEntryUpdate(self.id),
+            self.approval_state,
+            to,
+            actor_user_id,
+            roles,
+            reason,
+        )?;
+        self.approval_state = to;
+
+        Ok(())
+    }
+
+    /// Deletes this JamEntryUpdate along with the rich text it owns, if any.
+    pub fn delete(self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::jam_entry_updates::dsl::jam_entry_updates;
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            let rich_text = self.load_rich_text(conn)?;
+
+            diesel::delete(jam_entry_updates.find(self.id)).execute(conn)?;
+
+            if let Some(rich_text) = rich_text {
+                rich_text.delete(conn)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Loads the rich text content of this update, if it has any. An update
+    /// which claims to have a rich text that doesn't exist is a schema
+    /// violation and is returned as a
+    /// [`crate::models::ModelError::NotFound`].
+    pub fn load_rich_text(
+        &self,
+        conn: &DbConn,
+    ) -> Result<Option<RichText>, ModelError> {
+        match self.rich_text_id {
+            Some(id) => Ok(Some(
+                RichText::find_by_id(conn, id)?.ok_or(ModelError::NotFound)?,
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+/// External content is linked to from the site, so it had better be something
+/// a browser will treat as a web page rather than, say, a script.
+fn validate_external_content_url(url: &str) -> Result<String, ModelError> {
+    match Url::parse(url.trim()) {
+        Ok(u) if u.scheme() == "http" || u.scheme() == "https" => {
+            Ok(u.into_string())
+        }
+        _ => Err(ModelError::InvalidUrl(url.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_external_content_url;
+
+    #[test]
+    fn test_external_content_url_validation() {
+        assert!(
+            validate_external_content_url("https://example.com/devlog").is_ok()
+        );
+        assert!(validate_external_content_url(" http://example.com ").is_ok());
+        assert!(validate_external_content_url("javascript:alert(1)").is_err());
+        assert!(validate_external_content_url("example.com").is_err());
+    }
+}
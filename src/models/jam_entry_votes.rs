@@ -0,0 +1,216 @@
+use crate::db::DbConn;
+use crate::models::{last_insert_rowid, r_to_opt, ModelError, PermissionKind};
+use chrono::NaiveDateTime;
+use std::collections::BTreeMap;
+
+/// The lowest rating a voter may give.
+pub const MIN_RATING: i32 = 1;
+
+/// The highest rating a voter may give.
+pub const MAX_RATING: i32 = 5;
+
+/// Models one user's rating of one jam entry in one
+/// [`crate::models::VoteCategory`]. A voter has at most one of these per entry
+/// and category, which changes in place when they change their mind.
+#[derive(Debug, Queryable)]
+pub struct JamEntryVote {
+    pub id: i32,
+    pub jam_entry_id: i32,
+    pub voter_user_id: i64,
+    pub vote_category_id: i32,
+
+    /// From [`MIN_RATING`] to [`MAX_RATING`] stars.
+    pub rating: i32,
+
+    /// When the vote was first cast.
+    pub created_at: NaiveDateTime,
+
+    /// When the vote was last changed.
+    pub updated_at: NaiveDateTime,
+}
+
+/// How an entry was rated in one category, counting only voters who aren't
+/// banned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoteTally {
+    pub vote_category_id: i32,
+    pub average: f64,
+    pub count: usize,
+}
+
+impl JamEntryVote {
+    /// Records a user's rating of an entry in a category, replacing whatever
+    /// they rated it before. Whether they may vote at all is up to the caller.
+    pub fn cast(
+        conn: &DbConn,
+        for_jam_entry_id: i32,
+        for_voter_user_id: i64,
+        for_vote_category_id: i32,
+        new_rating: i32,
+    ) -> Result<JamEntryVote, ModelError> {
+        use crate::schema::jam_entry_votes::dsl::{
+            created_at, id, jam_entry_id, jam_entry_votes, rating, updated_at,
+            vote_category_id, voter_user_id,
+        };
+        use diesel::prelude::*;
+
+        if new_rating < MIN_RATING || new_rating > MAX_RATING {
+            return Err(ModelError::RatingOutOfRange(new_rating));
+        }
+
+        let now = chrono::offset::Utc::now().naive_utc();
+
+        conn.transaction::<JamEntryVote, ModelError, _>(|| {
+            let existing = r_to_opt(
+                jam_entry_votes
+                    .filter(jam_entry_id.eq(for_jam_entry_id))
+                    .filter(voter_user_id.eq(for_voter_user_id))
+                    .filter(vote_category_id.eq(for_vote_category_id))
+                    .limit(1)
+                    .first::<JamEntryVote>(conn),
+            )?;
+
+            let rowid = match existing {
+                Some(vote) => {
+                    diesel::update(jam_entry_votes.find(vote.id))
+                        .set((rating.eq(new_rating), updated_at.eq(now)))
+                        .execute(conn)?;
+                    vote.id
+                }
+                None => {
+                    diesel::insert_into(jam_entry_votes)
+                        .values((
+                            jam_entry_id.eq(for_jam_entry_id),
+                            voter_user_id.eq(for_voter_user_id),
+                            vote_category_id.eq(for_vote_category_id),
+                            rating.eq(new_rating),
+                            created_at.eq(now),
+                            updated_at.eq(now),
+                        ))
+                        .execute(conn)?;
+                    diesel::select(last_insert_rowid).get_result::<i32>(conn)?
+                }
+            };
+
+            Ok(jam_entry_votes
+                .filter(id.eq(rowid))
+                .limit(1)
+                .first::<JamEntryVote>(conn)?)
+        })
+    }
+
+    /// Finds every vote a user has cast on an entry, one per category they've
+    /// rated it in.
+    pub fn find_by_jam_entry_and_voter(
+        conn: &DbConn,
+        for_jam_entry_id: i32,
+        for_voter_user_id: i64,
+    ) -> Result<Vec<JamEntryVote>, ModelError> {
+        use crate::schema::jam_entry_votes::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(jam_entry_votes
+            .filter(jam_entry_id.eq(for_jam_entry_id))
+            .filter(voter_user_id.eq(for_voter_user_id))
+            .load::<JamEntryVote>(conn)?)
+    }
+
+    /// Tallies the votes on an entry by category. Votes cast by users who have
+    /// since been banned are left out, but kept in case the ban is lifted.
+    pub fn tally_by_jam_entry_id(
+        conn: &DbConn,
+        for_jam_entry_id: i32,
+    ) -> Result<Vec<VoteTally>, ModelError> {
+        use crate::schema::jam_entry_votes::dsl::*;
+        use crate::schema::permissions;
+        use diesel::prelude::*;
+
+        let ratings = jam_entry_votes
+            .select((vote_category_id, rating))
+            .filter(jam_entry_id.eq(for_jam_entry_id))
+            .filter(voter_user_id.ne_all(
+                permissions::table.select(permissions::gh_user_id).filter(
+                    permissions::name.eq(PermissionKind::Banned.name()),
+                ),
+            ))
+            .load::<(i32, i32)>(conn)?;
+
+        Ok(tally(&ratings))
+    }
+
+    /// Deletes every vote on an entry, for when the entry itself is deleted.
+    pub(super) fn delete_by_jam_entry_id(
+        conn: &DbConn,
+        for_jam_entry_id: i32,
+    ) -> Result<(), ModelError> {
+        use crate::schema::jam_entry_votes::dsl::*;
+        use diesel::prelude::*;
+
+        diesel::delete(
+            jam_entry_votes.filter(jam_entry_id.eq(for_jam_entry_id)),
+        )
+        .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Deletes every vote a user has cast, for when the user is deleted.
+    pub(super) fn delete_by_voter_user_id(
+        conn: &DbConn,
+        for_voter_user_id: i64,
+    ) -> Result<(), ModelError> {
+        use crate::schema::jam_entry_votes::dsl::*;
+        use diesel::prelude::*;
+
+        diesel::delete(
+            jam_entry_votes.filter(voter_user_id.eq(for_voter_user_id)),
+        )
+        .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+/// Averages `(vote_category_id, rating)` pairs by category, ordered by
+/// category id.
+fn tally(ratings: &[(i32, i32)]) -> Vec<VoteTally> {
+    let mut sums: BTreeMap<i32, (i64, usize)> = BTreeMap::new();
+    for (category_id, rating) in ratings {
+        let sum = sums.entry(*category_id).or_insert((0, 0));
+        sum.0 += i64::from(*rating);
+        sum.1 += 1;
+    }
+
+    sums.into_iter()
+        .map(|(category_id, (total, count))| VoteTally {
+            vote_category_id: category_id,
+            average: total as f64 / count as f64,
+            count,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tally() {
+        assert_eq!(Vec::<VoteTally>::new(), tally(&[]));
+        assert_eq!(
+            vec![
+                VoteTally {
+                    vote_category_id: 1,
+                    average: 4.5,
+                    count: 2
+                },
+                VoteTally {
+                    vote_category_id: 3,
+                    average: 1.0,
+                    count: 1
+                },
+            ],
+            tally(&[(3, 1), (1, 5), (1, 4)])
+        );
+    }
+}
@@ -1,13 +1,16 @@
 use crate::db::DbConn;
 use crate::models::{
-    last_insert_rowid, ApprovalState, Attachment, ModelError, RichText,
+    last_insert_rowid, ApprovalRole, ApprovalState, ApprovalSubject,
+    ApprovalTransition, Attachment, ModelError, RichText,
 };
 use chrono::NaiveDateTime;
+use chrono_tz::Tz;
 use diesel::debug_query;
 
 use super::r_to_opt;
 
-/// Models a game jam.
+/// Models a game jam. Its dates are all instants in UTC, which are shown in
+/// the organizer's `timezone` or the viewer's own.
 #[derive(Debug, Queryable)]
 pub struct Jam {
     pub id: i32,
@@ -19,6 +22,64 @@ pub struct Jam {
     pub start_date: NaiveDateTime,
     pub end_date: NaiveDateTime,
     pub approval_state: ApprovalState,
+    /// Why a moderator rejected this jam, while it is rejected.
+    pub rejection_reason: Option<String>,
+    /// When voting closes. Voting opens at `end_date`, and a jam without this
+    /// has no voting.
+    pub voting_end_date: Option<NaiveDateTime>,
+    /// The IANA name of the timezone the organizer thinks in.
+    pub timezone: String,
+}
+
+/// Where a [`Jam`] is in its life, which follows from its dates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JamPhase {
+    /// The jam hasn't started yet, but entries can be made ahead of time.
+    Upcoming,
+    /// The jam is on, and entries are being worked on.
+    Running,
+    /// The jam is over and has no voting.
+    SubmissionsClosed,
+    /// The jam is over and its entries are being voted on.
+    Voting,
+    /// Voting is over and the results are in.
+    Results,
+}
+
+impl JamPhase {
+    /// Format the JamPhase as something we can show to humans on the
+    /// Internet.
+    pub fn to_human_str(&self) -> String {
+        match self {
+            JamPhase::Upcoming => "Upcoming".to_string(),
+            JamPhase::Running => "Running".to_string(),
+            JamPhase::SubmissionsClosed => "Submissions closed".to_string(),
+            JamPhase::Voting => "Voting".to_string(),
+            JamPhase::Results => "Results".to_string(),
+        }
+    }
+
+    /// Whether entries may be made or changed, which stops when the jam ends.
+    pub fn accepts_entries(&self) -> bool {
+        matches!(self, JamPhase::Upcoming | JamPhase::Running)
+    }
+
+    /// Whether entries may have updates posted to their devlog, which only
+    /// happens while the jam is on.
+    pub fn accepts_updates(&self) -> bool {
+        *self == JamPhase::Running
+    }
+
+    /// Whether entries may be voted on, or votes changed.
+    pub fn accepts_votes(&self) -> bool {
+        *self == JamPhase::Voting
+    }
+
+    /// Whether the votes on entries are shown, which waits until voting is
+    /// over so that nobody is swayed by how others voted.
+    pub fn shows_vote_results(&self) -> bool {
+        *self == JamPhase::Results
+    }
 }
 
 impl Jam {
@@ -86,7 +147,7 @@ impl Jam {
         let q = jams
             .order(start_date.desc())
             .limit(page_size)
-            .offset(page * page_size);
+            .offset(page.saturating_mul(page_size));
 
         let r = if approved_only {
             q.filter(approval_state.eq(ApprovalState::Approved))
@@ -98,12 +159,65 @@ impl Jam {
         Ok(r)
     }
 
+    /// Counts all Jams, for paging through them.
+    ///
+    /// * `approved_only` when `true` counts only [`crate::models::jams::Jam`]s
+    ///   which have the the `approval_state`
+    ///   [`crate::models::ApprovalState::Approved`].
+    pub fn count(
+        conn: &DbConn,
+        approved_only: bool,
+    ) -> Result<i64, ModelError> {
+        use crate::schema::jams::dsl::*;
+        use diesel::prelude::*;
+
+        let r = if approved_only {
+            jams.filter(approval_state.eq(ApprovalState::Approved))
+                .count()
+                .get_result(conn)?
+        } else {
+            jams.count().get_result(conn)?
+        };
+
+        Ok(r)
+    }
+
+    /// Finds every Jam in the given approval state, oldest first, such as those
+    /// [`crate::models::ApprovalState::Submitted`] and waiting for a moderator.
+    pub fn find_by_approval_state(
+        conn: &DbConn,
+        state: ApprovalState,
+    ) -> Result<Vec<Jam>, ModelError> {
+        use crate::schema::jams::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(jams
+            .filter(approval_state.eq(state))
+            .order(id.asc())
+            .load::<Jam>(conn)?)
+    }
+
+    /// Counts every Jam in the given approval state.
+    pub fn count_by_approval_state(
+        conn: &DbConn,
+        state: ApprovalState,
+    ) -> Result<i64, ModelError> {
+        use crate::schema::jams::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(jams
+            .filter(approval_state.eq(state))
+            .count()
+            .get_result(conn)?)
+    }
+
     /// Updates a Jam by making what's in the database look like what's on the
-    /// model.
+    /// model. The approval state is left alone, as it only changes through
+    /// [`Jam::transition`].
     pub fn update(&self, conn: &DbConn) -> Result<(), ModelError> {
         use crate::schema::jams::dsl::{
-            approval_state, end_date, jams, rich_text_id, slug, start_date,
-            summary, summary_attachment_id, title,
+            end_date, jams, rich_text_id, slug, start_date, summary,
+            summary_attachment_id, timezone, title, voting_end_date,
         };
         use diesel::prelude::*;
 
@@ -116,13 +230,106 @@ impl Jam {
                 rich_text_id.eq(self.rich_text_id),
                 start_date.eq(self.start_date),
                 end_date.eq(self.end_date),
-                approval_state.eq(self.approval_state),
+                voting_end_date.eq(self.voting_end_date),
+                timezone.eq(&self.timezone),
             ))
             .execute(conn)?;
 
         Ok(())
     }
 
+    /// Makes an attachment the summary image of the Jam with the given id.
+    /// Only that column is written, so edits made to the Jam in the meantime
+    /// are kept. Nothing links to the image it replaces anymore, so that one
+    /// is unpublished.
+    pub fn replace_summary_attachment(
+        conn: &DbConn,
+        jam_id: i32,
+        attachment_id: i32,
+    ) -> Result<(), ModelError> {
+        use crate::schema::attachments::dsl::{attachments, published};
+        use crate::schema::jams::dsl::{jams, summary_attachment_id};
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            let replaced = jams
+                .find(jam_id)
+                .select(summary_attachment_id)
+                .first::<Option<i32>>(conn)
+                .optional()?
+                .ok_or(ModelError::NotFound)?;
+
+            diesel::update(jams.find(jam_id))
+                .set(summary_attachment_id.eq(Some(attachment_id)))
+                .execute(conn)?;
+
+            if let Some(replaced) = replaced.filter(|r| *r != attachment_id) {
+                diesel::update(attachments.find(replaced))
+                    .set(published.eq(false))
+                    .execute(conn)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Moves this Jam to another approval state on behalf of a user
+    /// playing `roles`, as [`ApprovalTransition::apply`] describes. Leaving
+    /// the state as it is does nothing.
+    pub fn transition(
+        &mut self,
+        conn: &DbConn,
+        to: ApprovalState,
+        actor_user_id: i64,
+        roles: &[ApprovalRole],
+        reason: Option<&str>,
+    ) -> Result<(), ModelError> {
+        if to == self.approval_state {
+            return Ok(());
+        }
+
+        self.rejection_reason = ApprovalTransition::apply(
+            conn,
+            ApprovalSubject::Jam(self.id),
+            self.approval_state,
+            to,
+            actor_user_id,
+            roles,
+            reason,
+        )?;
+        self.approval_state = to;
+
+        Ok(())
+    }
+
+    /// The timezone the organizer thinks in. A name chrono-tz doesn't know,
+    /// which the edit form never saves, is taken to be UTC.
+    pub fn organizer_timezone(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+
+    /// Where the jam is in its life right now.
+    pub fn phase(&self) -> JamPhase {
+        self.phase_at(chrono::offset::Utc::now().naive_utc())
+    }
+
+    /// Where the jam is in its life at the given time.
+    pub fn phase_at(&self, now: NaiveDateTime) -> JamPhase {
+        if now < self.start_date {
+            JamPhase::Upcoming
+        } else if now < self.end_date {
+            JamPhase::Running
+        } else {
+            match self.voting_end_date {
+                None => JamPhase::SubmissionsClosed,
+                Some(voting_end_date) if now < voting_end_date => {
+                    JamPhase::Voting
+                }
+                Some(_) => JamPhase::Results,
+            }
+        }
+    }
+
     /// Loads the summary attachment, if present.
     pub fn load_attachment(
         &self,
@@ -145,3 +352,55 @@ impl Jam {
             .ok_or(ModelError::NotFound)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn day(d: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2021, 1, d).and_hms(0, 0, 0)
+    }
+
+    fn jam(voting_end_date: Option<NaiveDateTime>) -> Jam {
+        Jam {
+            id: 1,
+            title: "My Jam".to_string(),
+            slug: "my-jam".to_string(),
+            summary: "".to_string(),
+            summary_attachment_id: None,
+            rich_text_id: 1,
+            start_date: day(10),
+            end_date: day(20),
+            approval_state: ApprovalState::Approved,
+            rejection_reason: None,
+            voting_end_date,
+            timezone: "America/Los_Angeles".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_organizer_timezone() {
+        let mut j = jam(None);
+        assert_eq!(Tz::America__Los_Angeles, j.organizer_timezone());
+
+        j.timezone = "Mars/Olympus_Mons".to_string();
+        assert_eq!(Tz::UTC, j.organizer_timezone());
+    }
+
+    #[test]
+    fn test_phase_at() {
+        let with_voting = jam(Some(day(25)));
+        assert_eq!(JamPhase::Upcoming, with_voting.phase_at(day(1)));
+        assert_eq!(JamPhase::Running, with_voting.phase_at(day(10)));
+        assert_eq!(JamPhase::Voting, with_voting.phase_at(day(20)));
+        assert_eq!(JamPhase::Results, with_voting.phase_at(day(25)));
+
+        let without_voting = jam(None);
+        assert_eq!(JamPhase::Running, without_voting.phase_at(day(19)));
+        assert_eq!(
+            JamPhase::SubmissionsClosed,
+            without_voting.phase_at(day(30))
+        );
+    }
+}
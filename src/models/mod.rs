@@ -3,22 +3,32 @@
 //! intended to keep the database consistent. You should never manipulate the
 //! database directly from either command-line tool or controller code.
 
+mod approval_transitions;
 mod attachments;
 mod gh_user_records;
+mod jam_entries;
+mod jam_entry_updates;
+mod jam_entry_votes;
 mod jams;
 mod permissions;
+mod rich_text_revisions;
 mod rich_texts;
+mod sessions;
+mod vote_categories;
 
 use crate::attachments::AttachmentStorageError;
 pub use crate::models::{
-    attachments::*, gh_user_records::*, jams::*, permissions::*, rich_texts::*,
+    approval_transitions::*, attachments::*, gh_user_records::*,
+    jam_entries::*, jam_entry_updates::*, jam_entry_votes::*, jams::*,
+    permissions::*, rich_text_revisions::*, rich_texts::*, sessions::*,
+    vote_categories::*,
 };
 use diesel::{
     backend::Backend, deserialize, r2d2::PoolError,
     result::Error as DieselError, serialize, serialize::Output,
     sql_types::Integer, types::FromSql, types::ToSql,
 };
-use std::{io::Write, path::PathBuf};
+use std::io::Write;
 use thiserror::Error;
 
 /// An error common to model helper functions.
@@ -38,11 +48,44 @@ pub enum ModelError {
     #[error("The entity was not found.")]
     NotFound,
 
-    #[error("The file {0} could not be found. Send in Search and Rescue")]
-    FileNotFoundError(PathBuf),
-
     #[error("Could not store attachment with error {0}")]
     AttachmentStorageError(#[from] AttachmentStorageError),
+
+    /// A jam entry update must live either on the site or off of it.
+    #[error(
+        "A jam entry update must have either rich text or an external url, \
+            but not both"
+    )]
+    JamEntryUpdateContentError,
+
+    #[error("The url {0} is not an http or https url")]
+    InvalidUrl(String),
+
+    /// Rich text may only refer to attachments that it has had before, or that
+    /// were uploaded by whoever is editing it.
+    #[error("Attachment {0} doesn't exist or belongs to somebody else")]
+    UnownedAttachmentReference(i32),
+
+    /// Deleting a user would leave their jam entries without a submitter.
+    #[error("User {0} has entered jams, so their entries must go first")]
+    UserHasJamEntries(i64),
+
+    /// Deleting a banned user would forget the ban, letting them back in the
+    /// next time they log in with Github.
+    #[error("User {0} is banned, so they can't be deleted")]
+    UserIsBanned(i64),
+
+    /// Content may only move between approval states along the paths
+    /// [`ApprovalState::may_transition`] allows, and only for the right roles.
+    #[error("Moving from {from:?} to {to:?} isn't allowed")]
+    ForbiddenApprovalTransition {
+        from: ApprovalState,
+        to: ApprovalState,
+    },
+
+    /// Votes are cast from [`MIN_RATING`] to [`MAX_RATING`] stars.
+    #[error("A rating of {0} is out of range")]
+    RatingOutOfRange(i32),
 }
 
 // Gets the most recently inserted row. Please only use this from within a
@@ -67,7 +110,7 @@ fn r_to_opt<T>(
 }
 
 #[repr(i32)]
-#[derive(Debug, Clone, Copy, FromSqlRow, AsExpression)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromSqlRow, AsExpression)]
 #[sql_type = "Integer"]
 pub enum ApprovalState {
     Draft = 0,
@@ -76,6 +119,69 @@ pub enum ApprovalState {
     Rejected = 8,
 }
 
+/// The part someone plays in moving content between approval states. One user
+/// can play several, such as a moderator editing their own entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalRole {
+    /// Whoever the content belongs to.
+    Owner,
+
+    /// Someone who decides what others have submitted.
+    Moderator,
+}
+
+/// Every move between approval states that is allowed, and who may make it.
+/// Owners put their content up for approval and take it back, and put approved
+/// content up again when they change it, while only moderators decide on it.
+/// Staying in the same state isn't a move at all.
+const APPROVAL_TRANSITIONS: &[(ApprovalState, ApprovalState, ApprovalRole)] = &[
+    (
+        ApprovalState::Draft,
+        ApprovalState::Submitted,
+        ApprovalRole::Owner,
+    ),
+    (
+        ApprovalState::Submitted,
+        ApprovalState::Draft,
+        ApprovalRole::Owner,
+    ),
+    (
+        ApprovalState::Approved,
+        ApprovalState::Draft,
+        ApprovalRole::Owner,
+    ),
+    (
+        ApprovalState::Approved,
+        ApprovalState::Submitted,
+        ApprovalRole::Owner,
+    ),
+    (
+        ApprovalState::Rejected,
+        ApprovalState::Draft,
+        ApprovalRole::Owner,
+    ),
+    (
+        ApprovalState::Rejected,
+        ApprovalState::Submitted,
+        ApprovalRole::Owner,
+    ),
+    (
+        ApprovalState::Submitted,
+        ApprovalState::Approved,
+        ApprovalRole::Moderator,
+    ),
+    (
+        ApprovalState::Submitted,
+        ApprovalState::Rejected,
+        ApprovalRole::Moderator,
+    ),
+    (
+        ApprovalState::Approved,
+        ApprovalState::Rejected,
+        ApprovalRole::Moderator,
+    ),
+];
+
 #[derive(Debug, Error)]
 pub enum ApprovalStateParseError {
     #[error("Unrecognized Approval State {0}")]
@@ -83,6 +189,40 @@ pub enum ApprovalStateParseError {
 }
 
 impl ApprovalState {
+    /// Every approval state, in the order they're offered to users.
+    pub const ALL: [ApprovalState; 4] = [
+        ApprovalState::Draft,
+        ApprovalState::Submitted,
+        ApprovalState::Approved,
+        ApprovalState::Rejected,
+    ];
+
+    /// Whether someone playing `roles` may move content from this state to
+    /// `to`. Leaving it where it is, is always allowed.
+    pub fn may_transition(
+        self,
+        to: ApprovalState,
+        roles: &[ApprovalRole],
+    ) -> bool {
+        self == to
+            || APPROVAL_TRANSITIONS.iter().any(|(from, allowed_to, role)| {
+                *from == self && *allowed_to == to && roles.contains(role)
+            })
+    }
+
+    /// The states someone playing `roles` may leave content in, including the
+    /// one it's already in.
+    pub fn reachable_states(
+        self,
+        roles: &[ApprovalRole],
+    ) -> Vec<ApprovalState> {
+        ApprovalState::ALL
+            .iter()
+            .copied()
+            .filter(|to| self.may_transition(*to, roles))
+            .collect()
+    }
+
     pub fn from_human_str(s: &str) -> Result<Self, ApprovalStateParseError> {
         Ok(match s.to_lowercase().as_str() {
             "draft" => ApprovalState::Draft,
@@ -134,3 +274,41 @@ where
         (*self as i32).to_sql(out)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_may_transition() {
+        use ApprovalRole::*;
+        use ApprovalState::*;
+
+        assert!(Draft.may_transition(Submitted, &[Owner]));
+        assert!(Rejected.may_transition(Submitted, &[Owner]));
+        assert!(!Submitted.may_transition(Approved, &[Owner]));
+        assert!(!Draft.may_transition(Approved, &[Moderator]));
+        assert!(Submitted.may_transition(Approved, &[Moderator]));
+        assert!(Submitted.may_transition(Rejected, &[Owner, Moderator]));
+        assert!(!Rejected.may_transition(Approved, &[Owner, Moderator]));
+        assert!(Approved.may_transition(Approved, &[]));
+        assert!(Approved.may_transition(Submitted, &[Owner]));
+    }
+
+    #[test]
+    fn test_reachable_states() {
+        use ApprovalRole::*;
+        use ApprovalState::*;
+
+        assert_eq!(vec![Draft, Submitted], Draft.reachable_states(&[Owner]));
+        assert_eq!(vec![Draft], Draft.reachable_states(&[Moderator]));
+        assert_eq!(
+            vec![Draft, Submitted, Approved, Rejected],
+            Submitted.reachable_states(&[Owner, Moderator])
+        );
+        assert_eq!(
+            vec![Draft, Submitted, Approved],
+            Approved.reachable_states(&[Owner])
+        );
+    }
+}
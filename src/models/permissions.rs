@@ -2,13 +2,16 @@ use crate::{
     db::DbConn,
     models::{r_to_opt, ModelError},
 };
+use chrono::NaiveDateTime;
+use std::{fmt, str::FromStr};
+use thiserror::Error;
 
 /// Permissions sloppily model, well, permissions. A GhUserRecord may "have"
-/// zero or more permissions. Permissions are known by their name, which is
-/// special and hard-coded into various parts of the website. For example,
-/// having the "admin" permission enables some UI that other users cannot see.
-/// Or having the "banned" permission prevents a user from all site
-/// participation.
+/// zero or more permissions. Permissions are known by their name, which must
+/// be one of the [`PermissionKind`]s hard-coded into various parts of the
+/// website. For example, having the "admin" permission enables some UI that
+/// other users cannot see. Or having the "banned" permission prevents a user
+/// from all site participation.
 #[derive(Debug, Queryable)]
 pub struct Permission {
     /// Id of this permission grant.
@@ -19,9 +22,99 @@ pub struct Permission {
 
     /// The name of the permission granted.
     pub name: String,
+
+    /// Why the permission was granted, if anyone said. A banned user is shown
+    /// the reason for their ban.
+    pub reason: Option<String>,
+
+    /// When the permission was granted.
+    pub created_at: NaiveDateTime,
+}
+
+/// Every permission the site knows about. Only these can be granted, though
+/// the database may still hold names granted before they were checked, which
+/// mean nothing and can only be revoked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionKind {
+    /// May do anything, and counts as having every other permission but
+    /// banned.
+    Admin,
+
+    /// May not take part in the site at all.
+    Banned,
+
+    /// May look after what other users have posted.
+    Moderator,
+
+    /// May run jams.
+    JamHost,
+}
+
+#[derive(Debug, Error)]
+pub enum PermissionKindParseError {
+    #[error(
+        "Unknown permission {0}; the known permissions are admin, banned, \
+            moderator and jam_host"
+    )]
+    UnknownPermission(String),
+}
+
+impl PermissionKind {
+    /// All of the known permissions.
+    pub const ALL: &'static [PermissionKind] = &[
+        PermissionKind::Admin,
+        PermissionKind::Banned,
+        PermissionKind::Moderator,
+        PermissionKind::JamHost,
+    ];
+
+    /// The name this permission is stored under, and known by in templates.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PermissionKind::Admin => "admin",
+            PermissionKind::Banned => "banned",
+            PermissionKind::Moderator => "moderator",
+            PermissionKind::JamHost => "jam_host",
+        }
+    }
+
+    /// Whether a user with `permissions` should be treated as having this
+    /// permission, which admins are for everything but a ban.
+    pub fn is_held_by(&self, permissions: &[PermissionKind]) -> bool {
+        permissions.contains(self)
+            || (*self != PermissionKind::Banned
+                && permissions.contains(&PermissionKind::Admin))
+    }
+}
+
+impl FromStr for PermissionKind {
+    type Err = PermissionKindParseError;
+
+    /// Parses a permission by its exact name, so that a typo can't be granted
+    /// as a permission that does nothing.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PermissionKind::ALL
+            .iter()
+            .find(|kind| kind.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                PermissionKindParseError::UnknownPermission(s.to_string())
+            })
+    }
+}
+
+impl fmt::Display for PermissionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
 impl Permission {
+    /// The kind of permission this is, unless its name isn't a known one.
+    pub fn kind(&self) -> Option<PermissionKind> {
+        self.name.parse().ok()
+    }
+
     /// Finds all permissions on a given user.
     pub fn find_by_gh_user_id(
         conn: &DbConn,
@@ -53,20 +146,23 @@ impl Permission {
         Ok(perms)
     }
 
-    /// Grant a permission to a user by id.
+    /// Grant a permission to a user by id, giving the reason for it.
     pub fn grant_permission(
         conn: &DbConn,
         user_id: i64,
-        permission_name: &str,
+        kind: PermissionKind,
+        grant_reason: Option<&str>,
     ) -> Result<(), ModelError> {
         use crate::schema::permissions::dsl::*;
         use diesel::prelude::*;
 
+        let permission_name = kind.name();
+
         // if an existing equivalent permission exists, nop
         let existing_permission = Permission::find_by_user_id_and_name(
             &conn,
             user_id,
-            &permission_name,
+            permission_name,
         )?;
 
         if existing_permission.is_some() {
@@ -75,13 +171,18 @@ impl Permission {
 
         // no existing permission, make a new one
         diesel::insert_into(permissions)
-            .values((gh_user_id.eq(user_id), name.eq(permission_name)))
+            .values((
+                gh_user_id.eq(user_id),
+                name.eq(permission_name),
+                reason.eq(grant_reason),
+            ))
             .execute(conn)?;
 
         Ok(())
     }
 
-    /// Revoke a permission from a user.
+    /// Revoke a permission from a user. This goes by name rather than
+    /// [`PermissionKind`] so that unknown names can be cleaned up too.
     pub fn revoke_permission(
         conn: &DbConn,
         user_id: i64,
@@ -100,6 +201,18 @@ impl Permission {
         Ok(r)
     }
 
+    /// Finds the ban on a user, if they are banned.
+    pub fn find_ban(
+        conn: &DbConn,
+        user_id: i64,
+    ) -> Result<Option<Permission>, ModelError> {
+        Permission::find_by_user_id_and_name(
+            conn,
+            user_id,
+            PermissionKind::Banned.name(),
+        )
+    }
+
     /// Find a permission by both user id and name.
     pub fn find_by_user_id_and_name(
         conn: &DbConn,
@@ -118,3 +231,30 @@ impl Permission {
         r_to_opt(perm)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permission_kind_names() {
+        for kind in PermissionKind::ALL {
+            assert_eq!(*kind, kind.name().parse::<PermissionKind>().unwrap());
+        }
+        assert!("Admin".parse::<PermissionKind>().is_err());
+        assert!("admn".parse::<PermissionKind>().is_err());
+        assert!("".parse::<PermissionKind>().is_err());
+    }
+
+    #[test]
+    fn test_permission_kind_is_held_by() {
+        let admin = [PermissionKind::Admin];
+        let moderator = [PermissionKind::Moderator];
+
+        assert!(PermissionKind::Moderator.is_held_by(&admin));
+        assert!(PermissionKind::Moderator.is_held_by(&moderator));
+        assert!(!PermissionKind::Admin.is_held_by(&moderator));
+        assert!(!PermissionKind::Banned.is_held_by(&admin));
+        assert!(!PermissionKind::JamHost.is_held_by(&[]));
+    }
+}
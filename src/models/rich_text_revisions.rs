@@ -0,0 +1,125 @@
+use crate::db::DbConn;
+use crate::models::{last_insert_rowid, r_to_opt, GhUserRecord, ModelError};
+use chrono::NaiveDateTime;
+
+/// Models one saved version of a [`crate::models::RichText`]. Revisions are
+/// recorded as the rich text is updated, and are never changed afterwards.
+#[derive(Debug, Queryable)]
+pub struct RichTextRevision {
+    pub id: i32,
+    pub rich_text_id: i32,
+    pub content: String,
+    pub author_user_id: Option<i64>,
+    pub created_at: NaiveDateTime,
+}
+
+impl RichTextRevision {
+    /// Records a new revision of a rich text.
+    pub fn create(
+        conn: &DbConn,
+        for_rich_text_id: i32,
+        the_content: &str,
+        the_author_user_id: Option<i64>,
+    ) -> Result<RichTextRevision, ModelError> {
+        use crate::schema::rich_text_revisions::dsl::{
+            author_user_id, content, created_at, id, rich_text_id,
+            rich_text_revisions,
+        };
+        use diesel::prelude::*;
+
+        conn.transaction::<RichTextRevision, ModelError, _>(|| {
+            diesel::insert_into(rich_text_revisions)
+                .values((
+                    rich_text_id.eq(for_rich_text_id),
+                    content.eq(the_content),
+                    author_user_id.eq(the_author_user_id),
+                    created_at.eq(chrono::offset::Utc::now().naive_utc()),
+                ))
+                .execute(conn)?;
+
+            let rowid =
+                diesel::select(last_insert_rowid).get_result::<i32>(conn)?;
+
+            Ok(rich_text_revisions
+                .filter(id.eq(rowid))
+                .limit(1)
+                .first::<RichTextRevision>(conn)?)
+        })
+    }
+
+    /// Finds a RichTextRevision by its id.
+    pub fn find_by_id(
+        conn: &DbConn,
+        revision_id: i32,
+    ) -> Result<Option<RichTextRevision>, ModelError> {
+        use crate::schema::rich_text_revisions::dsl::*;
+        use diesel::prelude::*;
+
+        let revision = rich_text_revisions
+            .filter(id.eq(revision_id))
+            .limit(1)
+            .first::<RichTextRevision>(conn);
+
+        r_to_opt(revision)
+    }
+
+    /// Finds every revision of a rich text, newest first.
+    pub fn find_by_rich_text_id(
+        conn: &DbConn,
+        for_rich_text_id: i32,
+    ) -> Result<Vec<RichTextRevision>, ModelError> {
+        use crate::schema::rich_text_revisions::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(rich_text_revisions
+            .filter(rich_text_id.eq(for_rich_text_id))
+            .order(id.desc())
+            .load::<RichTextRevision>(conn)?)
+    }
+
+    /// Finds the revision which came just before this one, if there was one.
+    pub fn find_previous(
+        &self,
+        conn: &DbConn,
+    ) -> Result<Option<RichTextRevision>, ModelError> {
+        use crate::schema::rich_text_revisions::dsl::*;
+        use diesel::prelude::*;
+
+        let revision = rich_text_revisions
+            .filter(rich_text_id.eq(self.rich_text_id))
+            .filter(id.lt(self.id))
+            .order(id.desc())
+            .limit(1)
+            .first::<RichTextRevision>(conn);
+
+        r_to_opt(revision)
+    }
+
+    /// Deletes every revision of a rich text, which is only done when the rich
+    /// text itself is deleted.
+    pub fn delete_by_rich_text_id(
+        conn: &DbConn,
+        for_rich_text_id: i32,
+    ) -> Result<(), ModelError> {
+        use crate::schema::rich_text_revisions::dsl::*;
+        use diesel::prelude::*;
+
+        diesel::delete(
+            rich_text_revisions.filter(rich_text_id.eq(for_rich_text_id)),
+        )
+        .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Loads the user who saved this revision, if known.
+    pub fn load_author(
+        &self,
+        conn: &DbConn,
+    ) -> Result<Option<GhUserRecord>, ModelError> {
+        match self.author_user_id {
+            Some(user_id) => GhUserRecord::find_by_id(conn, user_id),
+            None => Ok(None),
+        }
+    }
+}
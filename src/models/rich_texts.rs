@@ -1,9 +1,11 @@
 use crate::{
     db::DbConn,
-    models::{last_insert_rowid, ModelError},
+    models::{last_insert_rowid, Attachment, ModelError, RichTextRevision},
 };
+use ammonia::Builder;
 use diesel::result::Error as DieselError;
-use pulldown_cmark::{html, Options, Parser};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use std::collections::{BTreeSet, HashMap};
 
 use super::r_to_opt;
 
@@ -52,30 +54,399 @@ impl RichText {
         r_to_opt(rich_text)
     }
 
-    /// Updates an existing RichText.
-    pub fn update(&self, conn: &DbConn) -> Result<(), ModelError> {
+    /// Updates an existing RichText, recording the new content as a revision
+    /// by the given user and saying whether there was anything new. Saving the
+    /// same content again doesn't count as a revision. Any attachments the
+    /// content refers to are attached to the rich text, which is only allowed
+    /// for attachments the user uploaded or that the rich text has had before.
+    pub fn update(
+        &self,
+        conn: &DbConn,
+        author_user_id: i64,
+    ) -> Result<bool, ModelError> {
         use crate::schema::rich_texts::dsl::{content, rich_texts};
         use diesel::prelude::*;
 
-        diesel::update(rich_texts.find(self.id))
-            .set(content.eq(&self.content))
-            .execute(conn)?;
+        conn.transaction::<bool, ModelError, _>(|| {
+            let previous = RichText::find_by_id(conn, self.id)?
+                .ok_or(ModelError::NotFound)?;
+
+            if previous.content == self.content {
+                return Ok(false);
+            }
+
+            diesel::update(rich_texts.find(self.id))
+                .set(content.eq(&self.content))
+                .execute(conn)?;
+
+            RichTextAttachment::replace_for_rich_text(
+                conn,
+                self.id,
+                &attachment_references(&self.content),
+                author_user_id,
+            )?;
+
+            RichTextRevision::create(
+                conn,
+                self.id,
+                &self.content,
+                Some(author_user_id),
+            )?;
+
+            Ok(true)
+        })
+    }
+
+    /// Puts the content of an earlier revision back, which is itself recorded
+    /// as a new revision by the given user.
+    pub fn restore(
+        &mut self,
+        conn: &DbConn,
+        revision: &RichTextRevision,
+        author_user_id: i64,
+    ) -> Result<(), ModelError> {
+        if revision.rich_text_id != self.id {
+            return Err(ModelError::NotFound);
+        }
+
+        self.content = revision.content.clone();
+        self.update(conn, author_user_id)?;
 
         Ok(())
     }
 
-    /// Renders the rich text's markdown to HTML.
-    pub fn render(&self) -> String {
-        let parser = Parser::new_ext(&self.content, Options::all());
+    /// Deletes this RichText along with its revisions. The attachments it had
+    /// are left alone, as they may still be linked from elsewhere.
+    pub fn delete(self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::rich_texts::dsl::rich_texts;
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            RichTextRevision::delete_by_rich_text_id(conn, self.id)?;
+            RichTextAttachment::delete_by_rich_text_id(conn, self.id)?;
+            diesel::delete(rich_texts.find(self.id)).execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Renders the rich text's markdown to HTML, with references to its
+    /// attachments such as `![screenshot](attachment:42)` pointing at the
+    /// attachment itself. Anyone with a GitHub account can write a rich text,
+    /// so the result is sanitized down to the tags and attributes that
+    /// Markdown itself produces, with links and images only to the web or
+    /// elsewhere on this site.
+    pub fn render(&self, conn: &DbConn) -> Result<String, ModelError> {
+        let attachment_urls =
+            RichTextAttachment::load_attachments(conn, self.id)?
+                .into_iter()
+                .map(|attachment| (attachment.id, attachment.url()))
+                .collect();
+
+        Ok(self.render_with_attachment_urls(&attachment_urls))
+    }
+
+    /// Renders using already-known urls for the rich text's attachments, by
+    /// attachment id. A reference to anything else is left as is, which the
+    /// sanitizer then strips as it isn't a web url.
+    fn render_with_attachment_urls(
+        &self,
+        attachment_urls: &HashMap<i32, String>,
+    ) -> String {
+        let parser =
+            Parser::new_ext(&self.content, Options::all()).map(|event| {
+                match event {
+                    Event::Start(Tag::Link(link_type, destination, title)) => {
+                        Event::Start(Tag::Link(
+                            link_type,
+                            resolve_attachment_reference(
+                                destination,
+                                attachment_urls,
+                            ),
+                            title,
+                        ))
+                    }
+                    Event::Start(Tag::Image(link_type, destination, title)) => {
+                        Event::Start(Tag::Image(
+                            link_type,
+                            resolve_attachment_reference(
+                                destination,
+                                attachment_urls,
+                            ),
+                            title,
+                        ))
+                    }
+                    event => event,
+                }
+            });
         let mut html_output = String::new();
         html::push_html(&mut html_output, parser);
-        html_output
+        sanitize_html(&html_output)
+    }
+}
+
+/// Marks a link or image destination as referring to an attachment by id.
+const ATTACHMENT_REFERENCE_PREFIX: &str = "attachment:";
+
+/// Gets the attachment id out of a link or image destination like
+/// `attachment:42`, if it is one.
+fn parse_attachment_reference(destination: &str) -> Option<i32> {
+    let id = destination.strip_prefix(ATTACHMENT_REFERENCE_PREFIX)?;
+
+    match id.parse::<i32>() {
+        Ok(attachment_id) if attachment_id.to_string() == id => {
+            Some(attachment_id)
+        }
+        _ => None,
+    }
+}
+
+/// Points a link or image destination at an attachment, if it refers to one
+/// whose url is known.
+fn resolve_attachment_reference<'a>(
+    destination: CowStr<'a>,
+    attachment_urls: &HashMap<i32, String>,
+) -> CowStr<'a> {
+    match parse_attachment_reference(&destination)
+        .and_then(|id| attachment_urls.get(&id))
+    {
+        Some(url) => url.clone().into(),
+        None => destination,
     }
 }
 
+/// Finds the ids of every attachment that some Markdown links to or embeds.
+fn attachment_references(content: &str) -> BTreeSet<i32> {
+    Parser::new_ext(content, Options::all())
+        .filter_map(|event| match event {
+            Event::Start(Tag::Link(_, destination, _))
+            | Event::Start(Tag::Image(_, destination, _)) => {
+                parse_attachment_reference(&destination)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn sanitize_html(html: &str) -> String {
+    Builder::default()
+        .url_schemes(["http", "https", "mailto"].iter().cloned().collect())
+        .link_rel(Some("nofollow ugc noopener noreferrer"))
+        // task lists render as disabled checkboxes
+        .add_tags(&["input"])
+        .add_tag_attributes("input", &["checked", "disabled"])
+        .add_tag_attribute_values("input", "type", &["checkbox"])
+        .clean(html)
+        .to_string()
+}
+
+/// Records that a rich text refers to an attachment, which lets the rich text
+/// render it no matter who is editing the rich text later on.
 #[derive(Debug, Queryable)]
 pub struct RichTextAttachment {
-    id: i32,
-    rich_text_id: i32,
-    attachment_id: i32,
+    pub id: i32,
+    pub rich_text_id: i32,
+    pub attachment_id: i32,
+}
+
+impl RichTextAttachment {
+    /// Finds which attachments a rich text has.
+    pub fn find_by_rich_text_id(
+        conn: &DbConn,
+        for_rich_text_id: i32,
+    ) -> Result<Vec<RichTextAttachment>, ModelError> {
+        use crate::schema::rich_text_attachments::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(rich_text_attachments
+            .filter(rich_text_id.eq(for_rich_text_id))
+            .order(attachment_id.asc())
+            .load::<RichTextAttachment>(conn)?)
+    }
+
+    /// Loads every attachment a rich text has.
+    pub fn load_attachments(
+        conn: &DbConn,
+        for_rich_text_id: i32,
+    ) -> Result<Vec<Attachment>, ModelError> {
+        use crate::schema::attachments::dsl::{attachments, id};
+        use crate::schema::rich_text_attachments::dsl::{
+            attachment_id, rich_text_attachments, rich_text_id,
+        };
+        use diesel::prelude::*;
+
+        Ok(attachments
+            .filter(
+                id.eq_any(
+                    rich_text_attachments
+                        .select(attachment_id)
+                        .filter(rich_text_id.eq(for_rich_text_id)),
+                ),
+            )
+            .load::<Attachment>(conn)?)
+    }
+
+    /// Forgets every attachment a rich text has, which is only done when the
+    /// rich text itself is deleted.
+    pub fn delete_by_rich_text_id(
+        conn: &DbConn,
+        for_rich_text_id: i32,
+    ) -> Result<(), ModelError> {
+        use crate::schema::rich_text_attachments::dsl::*;
+        use diesel::prelude::*;
+
+        diesel::delete(
+            rich_text_attachments.filter(rich_text_id.eq(for_rich_text_id)),
+        )
+        .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Makes the attachments of a rich text exactly those with the given ids.
+    /// Attachments new to the rich text must have been uploaded by the given
+    /// user, so nobody can show off somebody else's files, unless an earlier
+    /// revision of the rich text already showed them. That lets an admin
+    /// restore an old revision, or edit someone else's content, without
+    /// tripping over attachments its author uploaded.
+    pub fn replace_for_rich_text(
+        conn: &DbConn,
+        for_rich_text_id: i32,
+        attachment_ids: &BTreeSet<i32>,
+        user_id: i64,
+    ) -> Result<(), ModelError> {
+        use crate::schema::rich_text_attachments::dsl::*;
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            let existing = RichTextAttachment::find_by_rich_text_id(
+                conn,
+                for_rich_text_id,
+            )?;
+
+            for rich_text_attachment in &existing {
+                if !attachment_ids.contains(&rich_text_attachment.attachment_id)
+                {
+                    diesel::delete(
+                        rich_text_attachments.find(rich_text_attachment.id),
+                    )
+                    .execute(conn)?;
+                }
+            }
+
+            // attachments an earlier revision showed were vetted when it was
+            // saved, so they may be shown again
+            let previously_referenced =
+                RichTextRevision::find_by_rich_text_id(conn, for_rich_text_id)?
+                    .iter()
+                    .flat_map(|revision| {
+                        attachment_references(&revision.content)
+                    })
+                    .collect::<BTreeSet<i32>>();
+
+            for &new_attachment_id in attachment_ids {
+                if existing
+                    .iter()
+                    .any(|e| e.attachment_id == new_attachment_id)
+                {
+                    continue;
+                }
+
+                let allowed =
+                    match Attachment::find_by_id(conn, new_attachment_id)? {
+                        Some(attachment) => {
+                            attachment.is_owned_by(user_id)
+                                || previously_referenced
+                                    .contains(&attachment.id)
+                        }
+                        None => false,
+                    };
+                if !allowed {
+                    return Err(ModelError::UnownedAttachmentReference(
+                        new_attachment_id,
+                    ));
+                }
+
+                diesel::insert_into(rich_text_attachments)
+                    .values((
+                        rich_text_id.eq(for_rich_text_id),
+                        attachment_id.eq(new_attachment_id),
+                    ))
+                    .execute(conn)?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(content: &str) -> String {
+        RichText {
+            id: 0,
+            content: content.to_string(),
+        }
+        .render_with_attachment_urls(&HashMap::new())
+    }
+
+    #[test]
+    fn test_rendering_is_sanitized() {
+        assert_eq!(
+            "<p><em>hello</em></p>\n",
+            render("*hello*<script>alert(1)</script>")
+        );
+        assert_eq!(
+            "<p><a href=\"https://example.com\" \
+             rel=\"nofollow ugc noopener noreferrer\">a link</a></p>\n",
+            render("[a link](https://example.com)")
+        );
+        assert_eq!(
+            "<p><a rel=\"nofollow ugc noopener noreferrer\">xss</a></p>\n",
+            render("[xss](javascript:alert(1))")
+        );
+        assert_eq!(
+            "<p><img src=\"/attachments/1/a.png\" alt=\"pic\"></p>\n",
+            render("![pic](/attachments/1/a.png)")
+        );
+        assert_eq!(
+            "<p>hi <img src=\"x\" alt=\"x\"></p>\n",
+            render("hi <img src=x onerror=alert(1) alt=x>")
+        );
+        assert!(
+            !render("<div onclick=\"alert(1)\">hi</div>").contains("onclick")
+        );
+    }
+
+    #[test]
+    fn test_attachment_references() {
+        assert_eq!(
+            vec![7, 42],
+            attachment_references(
+                "![pic](attachment:42) [file](attachment:7 \"title\") \
+                 <attachment:42> [not](attachment:07) [nor](attachment:x) \
+                 `attachment:3`"
+            )
+            .into_iter()
+            .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_rendering_resolves_attachments() {
+        let rich_text = RichText {
+            id: 0,
+            content: "![pic](attachment:42) [file](attachment:7)".to_string(),
+        };
+        let mut attachment_urls = HashMap::new();
+        attachment_urls.insert(42, "/attachments/42/a.png".to_string());
+
+        assert_eq!(
+            "<p><img src=\"/attachments/42/a.png\" alt=\"pic\"> \
+             <a rel=\"nofollow ugc noopener noreferrer\">file</a></p>\n",
+            rich_text.render_with_attachment_urls(&attachment_urls)
+        );
+    }
 }
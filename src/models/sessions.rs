@@ -0,0 +1,191 @@
+use crate::{
+    db::DbConn,
+    models::{last_insert_rowid, r_to_opt, ModelError},
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// How long a session may go unused before it expires.
+const SESSION_IDLE_DAYS: i64 = 30;
+
+/// How stale `last_seen_at` may get before it is updated, so that not every
+/// request has to write to the database.
+const LAST_SEEN_PRECISION_MINUTES: i64 = 5;
+
+/// A user logged in from one browser, which holds the session's token in a
+/// cookie. Deleting a session logs that browser out.
+#[derive(Debug, Queryable)]
+pub struct Session {
+    pub id: i32,
+
+    /// The user who is logged in.
+    pub gh_user_id: i64,
+
+    /// SHA-256 of the token. The token itself is only ever known to the
+    /// browser.
+    pub token_sha256: Vec<u8>,
+
+    /// When the user logged in.
+    pub created_at: NaiveDateTime,
+
+    /// When the session was last used, give or take a few minutes.
+    pub last_seen_at: NaiveDateTime,
+}
+
+impl Session {
+    /// Starts a new session for a user, giving back the token to hand to
+    /// their browser.
+    pub fn create(
+        conn: &DbConn,
+        for_gh_user_id: i64,
+    ) -> Result<(Session, String), ModelError> {
+        use crate::schema::sessions::dsl::{
+            created_at, gh_user_id, id, last_seen_at, sessions, token_sha256,
+        };
+        use diesel::prelude::*;
+
+        let mut token = [0u8; 32];
+        OsRng.fill_bytes(&mut token);
+        let token = hex::encode(token);
+        let now = Utc::now().naive_utc();
+
+        let session = conn.transaction::<Session, ModelError, _>(|| {
+            diesel::insert_into(sessions)
+                .values((
+                    gh_user_id.eq(for_gh_user_id),
+                    token_sha256.eq(hash_token(&token)),
+                    created_at.eq(now),
+                    last_seen_at.eq(now),
+                ))
+                .execute(conn)?;
+
+            let rowid =
+                diesel::select(last_insert_rowid).get_result::<i32>(conn)?;
+
+            Ok(sessions
+                .filter(id.eq(rowid))
+                .limit(1)
+                .first::<Session>(conn)?)
+        })?;
+
+        Ok((session, token))
+    }
+
+    /// Finds the session a browser's token belongs to, so long as it hasn't
+    /// expired. Expired sessions are deleted when they are found.
+    pub fn find_by_token(
+        conn: &DbConn,
+        token: &str,
+    ) -> Result<Option<Session>, ModelError> {
+        use crate::schema::sessions::dsl::*;
+        use diesel::prelude::*;
+
+        let session = r_to_opt(
+            sessions
+                .filter(token_sha256.eq(hash_token(token)))
+                .limit(1)
+                .first::<Session>(conn),
+        )?;
+
+        match session {
+            Some(session) if session.is_expired() => {
+                session.delete(conn)?;
+                Ok(None)
+            }
+            session => Ok(session),
+        }
+    }
+
+    /// Finds a session by its id.
+    pub fn find_by_id(
+        conn: &DbConn,
+        session_id: i32,
+    ) -> Result<Option<Session>, ModelError> {
+        use crate::schema::sessions::dsl::*;
+        use diesel::prelude::*;
+
+        let session = sessions
+            .filter(id.eq(session_id))
+            .limit(1)
+            .first::<Session>(conn);
+
+        r_to_opt(session)
+    }
+
+    /// Finds every session of a user, most recently used first.
+    pub fn find_by_gh_user_id(
+        conn: &DbConn,
+        for_gh_user_id: i64,
+    ) -> Result<Vec<Session>, ModelError> {
+        use crate::schema::sessions::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(sessions
+            .filter(gh_user_id.eq(for_gh_user_id))
+            .order(last_seen_at.desc())
+            .load::<Session>(conn)?)
+    }
+
+    /// Finds every session, most recently used first.
+    pub fn find_all(conn: &DbConn) -> Result<Vec<Session>, ModelError> {
+        use crate::schema::sessions::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(sessions.order(last_seen_at.desc()).load::<Session>(conn)?)
+    }
+
+    /// Records that the session is still in use.
+    pub fn touch(&self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::sessions::dsl::{last_seen_at, sessions};
+        use diesel::prelude::*;
+
+        let now = Utc::now().naive_utc();
+        if now - self.last_seen_at
+            < Duration::minutes(LAST_SEEN_PRECISION_MINUTES)
+        {
+            return Ok(());
+        }
+
+        diesel::update(sessions.find(self.id))
+            .set(last_seen_at.eq(now))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Deletes this session, logging its browser out.
+    pub fn delete(self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::sessions::dsl::sessions;
+        use diesel::prelude::*;
+
+        diesel::delete(sessions.find(self.id)).execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Deletes every session of a user, logging them out everywhere. Gives
+    /// back how many sessions there were.
+    pub fn delete_by_gh_user_id(
+        conn: &DbConn,
+        for_gh_user_id: i64,
+    ) -> Result<usize, ModelError> {
+        use crate::schema::sessions::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(
+            diesel::delete(sessions.filter(gh_user_id.eq(for_gh_user_id)))
+                .execute(conn)?,
+        )
+    }
+
+    /// Whether the session has gone unused for too long to be trusted.
+    pub fn is_expired(&self) -> bool {
+        Utc::now().naive_utc() - self.last_seen_at
+            > Duration::days(SESSION_IDLE_DAYS)
+    }
+}
+
+fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
@@ -0,0 +1,46 @@
+use crate::db::DbConn;
+use crate::models::{r_to_opt, ModelError};
+
+/// Models something entries are rated on once a jam's voting opens, such as
+/// gameplay or graphics. Every jam uses the same categories.
+#[derive(Debug, Queryable)]
+pub struct VoteCategory {
+    pub id: i32,
+
+    /// The short name shown beside the ratings.
+    pub name: String,
+
+    /// What voters should consider when rating this.
+    pub description: String,
+
+    /// Where the category is shown, lowest first.
+    pub position: i32,
+}
+
+impl VoteCategory {
+    /// Finds every category, in the order they are shown.
+    pub fn find_all(conn: &DbConn) -> Result<Vec<VoteCategory>, ModelError> {
+        use crate::schema::vote_categories::dsl::*;
+        use diesel::prelude::*;
+
+        Ok(vote_categories
+            .order((position.asc(), id.asc()))
+            .load::<VoteCategory>(conn)?)
+    }
+
+    /// Finds a category by its id.
+    pub fn find_by_id(
+        conn: &DbConn,
+        vote_category_id: i32,
+    ) -> Result<Option<VoteCategory>, ModelError> {
+        use crate::schema::vote_categories::dsl::*;
+        use diesel::prelude::*;
+
+        let vote_category = vote_categories
+            .filter(id.eq(vote_category_id))
+            .limit(1)
+            .first::<VoteCategory>(conn);
+
+        r_to_opt(vote_category)
+    }
+}
@@ -1,3 +1,16 @@
+table! {
+    approval_transitions (id) {
+        id -> Integer,
+        subject_kind -> Text,
+        subject_id -> Integer,
+        from_state -> Integer,
+        to_state -> Integer,
+        actor_user_id -> Nullable<BigInt>,
+        reason -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     attachments (id) {
         id -> Integer,
@@ -5,6 +18,8 @@ table! {
         published -> Bool,
         mime_type -> Text,
         md5 -> Binary,
+        owner_user_id -> Nullable<BigInt>,
+        sha256 -> Nullable<Binary>,
     }
 }
 
@@ -20,13 +35,15 @@ table! {
 table! {
     jam_entries (id) {
         id -> Integer,
+        jam_id -> Integer,
         submitter_user_id -> BigInt,
         approval_state -> Integer,
         title -> Text,
         slug -> Text,
         summary -> Text,
-        summary_attachment_id -> Integer,
+        summary_attachment_id -> Nullable<Integer>,
         rich_text_id -> Integer,
+        rejection_reason -> Nullable<Text>,
     }
 }
 
@@ -40,6 +57,20 @@ table! {
         rich_text_id -> Nullable<Integer>,
         external_content_url -> Nullable<Text>,
         approval_state -> Integer,
+        created_at -> Timestamp,
+        rejection_reason -> Nullable<Text>,
+    }
+}
+
+table! {
+    jam_entry_votes (id) {
+        id -> Integer,
+        jam_entry_id -> Integer,
+        voter_user_id -> BigInt,
+        vote_category_id -> Integer,
+        rating -> Integer,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -54,6 +85,9 @@ table! {
         start_date -> Timestamp,
         end_date -> Timestamp,
         approval_state -> Integer,
+        rejection_reason -> Nullable<Text>,
+        voting_end_date -> Nullable<Timestamp>,
+        timezone -> Text,
     }
 }
 
@@ -62,6 +96,8 @@ table! {
         id -> Integer,
         gh_user_id -> BigInt,
         name -> Text,
+        reason -> Nullable<Text>,
+        created_at -> Timestamp,
     }
 }
 
@@ -73,6 +109,16 @@ table! {
     }
 }
 
+table! {
+    rich_text_revisions (id) {
+        id -> Integer,
+        rich_text_id -> Integer,
+        content -> Text,
+        author_user_id -> Nullable<BigInt>,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     rich_texts (id) {
         id -> Integer,
@@ -80,13 +126,37 @@ table! {
     }
 }
 
+table! {
+    sessions (id) {
+        id -> Integer,
+        gh_user_id -> BigInt,
+        token_sha256 -> Binary,
+        created_at -> Timestamp,
+        last_seen_at -> Timestamp,
+    }
+}
+
+table! {
+    vote_categories (id) {
+        id -> Integer,
+        name -> Text,
+        description -> Text,
+        position -> Integer,
+    }
+}
+
 allow_tables_to_appear_in_same_query!(
+    approval_transitions,
     attachments,
     gh_user_records,
     jam_entries,
     jam_entry_updates,
+    jam_entry_votes,
     jams,
     permissions,
     rich_text_attachments,
+    rich_text_revisions,
     rich_texts,
+    sessions,
+    vote_categories,
 );
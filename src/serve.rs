@@ -1,6 +1,8 @@
 use crate::{
-    attachments::AttachmentStorage, controllers::gh_oauth::GhCredentials,
+    attachments::AttachmentStorage,
+    controllers::gh_oauth::{GhCredentials, OAuthStateKey},
     db::DbPool,
+    template_helpers::CsrfFairing,
 };
 use rocket::{
     catchers, config::Config as RocketConfig, figment::Figment, routes,
@@ -20,19 +22,23 @@ pub async fn serve(
     db_pool: DbPool,
     gh_credentials: GhCredentials,
     attachment_storage: AttachmentStorage,
+    fake_github: bool,
 ) {
+    let oauth_state_key = OAuthStateKey::new(&secret);
     let config = Figment::from(RocketConfig::default())
         .merge(("address", address))
         .merge(("port", port))
         .merge(("workers", workers))
         .merge(("secret_key", secret));
 
-    let _ = rocket::custom(config)
+    let mut rocket = rocket::custom(config)
         .manage(gh_credentials)
+        .manage(oauth_state_key)
         .manage(crate::controllers::gh_oauth::gh_client())
         .manage(db_pool)
         .manage(attachment_storage)
         .attach(Template::fairing())
+        .attach(CsrfFairing)
         //        .attach(Compression::fairing())
         .attach(SpaceHelmet::default())
         .mount(
@@ -40,21 +46,60 @@ pub async fn serve(
             routes![
                 crate::controllers::homepage::homepage,
                 crate::controllers::attachments::get_attachment,
+                crate::controllers::attachments::create_attachment,
+                crate::controllers::attachments::create_jam_attachment,
                 crate::controllers::gh_oauth::login_with_github,
                 crate::controllers::gh_oauth::gh_callback,
                 crate::controllers::gh_oauth::logout,
+                crate::controllers::gh_oauth::logout_everywhere,
+                crate::controllers::bans::show_ban,
                 crate::controllers::jams::create_jam,
                 crate::controllers::jams::edit_jam,
                 crate::controllers::jams::update_jam,
+                crate::controllers::jams::show_jam,
+                crate::controllers::jams::list_jams,
+                crate::controllers::jam_entries::create_jam_entry,
+                crate::controllers::jam_entries::edit_jam_entry,
+                crate::controllers::jam_entries::update_jam_entry,
+                crate::controllers::jam_entries::delete_jam_entry,
+                crate::controllers::jam_entries::list_jam_entries,
+                crate::controllers::jam_entries::show_jam_entry,
+                crate::controllers::jam_entry_updates::create_jam_entry_update,
+                crate::controllers::jam_entry_updates::edit_jam_entry_update,
+                crate::controllers::jam_entry_updates::update_jam_entry_update,
+                crate::controllers::jam_entry_updates::delete_jam_entry_update,
+                crate::controllers::jam_entry_updates::show_jam_entry_update,
+                crate::controllers::jam_entry_votes::cast_jam_entry_vote,
+                crate::controllers::moderation::moderation_queue,
+                crate::controllers::moderation::moderate_jam,
+                crate::controllers::moderation::moderate_jam_entry,
+                crate::controllers::moderation::moderate_jam_entry_update,
+                crate::controllers::rich_texts::list_rich_text_revisions,
+                crate::controllers::rich_texts::show_rich_text_revision,
+                crate::controllers::rich_texts::restore_rich_text_revision,
+                crate::controllers::timezones::choose_timezone,
             ],
         )
         .mount("/static", StaticFiles::from(crate_relative!("/static")))
         .register(catchers![
+            crate::error_handlers::bad_request,
             crate::error_handlers::not_found,
             crate::error_handlers::not_authorized,
             crate::error_handlers::forbidden,
             crate::error_handlers::server_error,
-        ])
-        .launch()
-        .await;
+        ]);
+
+    if fake_github {
+        rocket = rocket.mount(
+            "/fake_github",
+            routes![
+                crate::controllers::fake_github::authorize,
+                crate::controllers::fake_github::authorize_as,
+                crate::controllers::fake_github::access_token,
+                crate::controllers::fake_github::user,
+            ],
+        );
+    }
+
+    let _ = rocket.launch().await;
 }
@@ -0,0 +1,50 @@
+use crate::db::DbConn;
+use crate::models::{ApprovalSubject, ApprovalTransition, ModelError};
+use serde::Serialize;
+
+/// Describes what a
+/// [`crate::models::approval_transitions::ApprovalTransition`] is to a Tera
+/// Template context.
+#[derive(Debug, Serialize)]
+pub struct ApprovalTransitionContext {
+    from_state: String,
+    to_state: String,
+    actor_login: Option<String>,
+    actor_html_url: Option<String>,
+    reason: Option<String>,
+    created_at: String,
+}
+
+impl ApprovalTransitionContext {
+    /// Populates a [`ApprovalTransitionContext`] from a database model,
+    /// pulling whoever made the move from the database at `conn`.
+    pub fn from_model(
+        conn: &DbConn,
+        transition: &ApprovalTransition,
+    ) -> Result<Self, ModelError> {
+        let actor = transition.load_actor(conn)?;
+
+        Ok(ApprovalTransitionContext {
+            from_state: transition.from_state.to_human_str(),
+            to_state: transition.to_state.to_human_str(),
+            actor_login: actor.as_ref().map(|a| a.login.clone()),
+            actor_html_url: actor.as_ref().map(|a| a.html_url.clone()),
+            reason: transition.reason.clone(),
+            created_at: transition
+                .created_at
+                .format("%Y-%m-%d %H:%M UTC")
+                .to_string(),
+        })
+    }
+
+    /// Populates contexts for every move some content has made, oldest first.
+    pub fn for_subject(
+        conn: &DbConn,
+        subject: ApprovalSubject,
+    ) -> Result<Vec<Self>, ModelError> {
+        ApprovalTransition::find_by_subject(conn, subject)?
+            .iter()
+            .map(|t| ApprovalTransitionContext::from_model(conn, t))
+            .collect()
+    }
+}
@@ -11,7 +11,9 @@ pub struct AttachmentContext {
     published: bool,
     mime_type: String,
     md5: String,
+    sha256: Option<String>,
     url: String,
+    reference: String,
 }
 
 impl AttachmentContext {
@@ -23,7 +25,9 @@ impl AttachmentContext {
             published: attachment.published.clone(),
             mime_type: attachment.mime_type.clone(),
             md5: hex_encode(&attachment.md5),
+            sha256: attachment.sha256.as_ref().map(hex_encode),
             url: attachment.url(),
+            reference: format!("attachment:{}", attachment.id),
         }
     }
 }
@@ -1,5 +1,6 @@
 ///! The name is a little sloppy but it's just generally stuff that has to do
 ///! with the template system.
+use crate::models::{Jam, JamEntry, JamEntryUpdate};
 use serde::Serialize;
 
 /// Drives the navbar's breadcrumbs to show hierarchy and stuff.
@@ -10,6 +11,68 @@ pub struct Breadcrumbs(Vec<Breadcrumb>);
 pub enum Breadcrumb {
     /// Will link to the homepage.
     Home,
+
+    /// Will link to the listing of all jams.
+    Jams,
+
+    /// Will link to the detail page of a jam.
+    Jam {
+        /// The id of the jam.
+        id: i32,
+        /// The slug of the jam, as used in its url.
+        slug: String,
+        /// The title of the jam, which is shown in the navbar.
+        title: String,
+    },
+
+    /// Will link to the listing of entries in a jam.
+    JamEntries {
+        /// The id of the jam.
+        jam_id: i32,
+        /// The slug of the jam, as used in its url.
+        jam_slug: String,
+    },
+
+    /// Will link to the detail page of a jam entry.
+    JamEntry {
+        /// The id of the jam the entry is in.
+        jam_id: i32,
+        /// The slug of the jam the entry is in.
+        jam_slug: String,
+        /// The id of the entry.
+        id: i32,
+        /// The slug of the entry, as used in its url.
+        slug: String,
+        /// The title of the entry, which is shown in the navbar.
+        title: String,
+    },
+
+    /// Will link to the page of an update on a jam entry.
+    JamEntryUpdate {
+        /// The id of the jam the update's entry is in.
+        jam_id: i32,
+        /// The slug of the jam the update's entry is in.
+        jam_slug: String,
+        /// The id of the entry the update is on.
+        jam_entry_id: i32,
+        /// The slug of the entry the update is on.
+        jam_entry_slug: String,
+        /// The id of the update.
+        id: i32,
+        /// The slug of the update, as used in its url.
+        slug: String,
+        /// The title of the update, which is shown in the navbar.
+        title: String,
+    },
+
+    /// Will link to the moderation queue.
+    Moderation,
+
+    /// Will link to the revision history of a rich text.
+    RichTextRevisions {
+        /// The id of the rich text.
+        rich_text_id: i32,
+    },
 }
 
 /// Unwraps the concept of a breadcrumb from a higher-level abstraction into a
@@ -50,9 +113,98 @@ impl Breadcrumbs {
 }
 
 impl Breadcrumb {
+    /// Creates a breadcrumb linking to the given jam.
+    pub fn from_jam(jam: &Jam) -> Breadcrumb {
+        Breadcrumb::Jam {
+            id: jam.id,
+            slug: jam.slug.clone(),
+            title: jam.title.clone(),
+        }
+    }
+
+    /// Creates a breadcrumb linking to the entries of the given jam.
+    pub fn entries_of_jam(jam: &Jam) -> Breadcrumb {
+        Breadcrumb::JamEntries {
+            jam_id: jam.id,
+            jam_slug: jam.slug.clone(),
+        }
+    }
+
+    /// Creates a breadcrumb linking to the given jam entry.
+    pub fn from_jam_entry(jam: &Jam, jam_entry: &JamEntry) -> Breadcrumb {
+        Breadcrumb::JamEntry {
+            jam_id: jam.id,
+            jam_slug: jam.slug.clone(),
+            id: jam_entry.id,
+            slug: jam_entry.slug.clone(),
+            title: jam_entry.title.clone(),
+        }
+    }
+
+    /// Creates a breadcrumb linking to the given update of a jam entry.
+    pub fn from_jam_entry_update(
+        jam: &Jam,
+        jam_entry: &JamEntry,
+        jam_entry_update: &JamEntryUpdate,
+    ) -> Breadcrumb {
+        Breadcrumb::JamEntryUpdate {
+            jam_id: jam.id,
+            jam_slug: jam.slug.clone(),
+            jam_entry_id: jam_entry.id,
+            jam_entry_slug: jam_entry.slug.clone(),
+            id: jam_entry_update.id,
+            slug: jam_entry_update.slug.clone(),
+            title: jam_entry_update.title.clone(),
+        }
+    }
+
     fn to_breadcrumb_context(&self) -> BreadcrumbContext {
         match self {
             Breadcrumb::Home => BreadcrumbContext::new("Home", "/"),
+            Breadcrumb::Jams => BreadcrumbContext::new("Jams", "/jams"),
+            Breadcrumb::Jam { id, slug, title } => {
+                BreadcrumbContext::new(title, &format!("/jams/{}/{}", id, slug))
+            }
+            Breadcrumb::JamEntries { jam_id, jam_slug } => {
+                BreadcrumbContext::new(
+                    "Entries",
+                    &format!("/jams/{}/{}/entries", jam_id, jam_slug),
+                )
+            }
+            Breadcrumb::JamEntry {
+                jam_id,
+                jam_slug,
+                id,
+                slug,
+                title,
+            } => BreadcrumbContext::new(
+                title,
+                &format!("/jams/{}/{}/{}/{}", jam_id, jam_slug, id, slug),
+            ),
+            Breadcrumb::JamEntryUpdate {
+                jam_id,
+                jam_slug,
+                jam_entry_id,
+                jam_entry_slug,
+                id,
+                slug,
+                title,
+            } => BreadcrumbContext::new(
+                title,
+                &format!(
+                    "/jams/{}/{}/{}/{}/updates/{}/{}",
+                    jam_id, jam_slug, jam_entry_id, jam_entry_slug, id, slug
+                ),
+            ),
+            Breadcrumb::Moderation => {
+                BreadcrumbContext::new("Moderation", "/moderation")
+            }
+            Breadcrumb::RichTextRevisions { rich_text_id } => {
+                BreadcrumbContext::new(
+                    "History",
+                    &format!("/rich_texts/{}/revisions", rich_text_id),
+                )
+            }
         }
     }
 }
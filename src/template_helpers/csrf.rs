@@ -0,0 +1,174 @@
+//! Cross-site request forgery protection. Every session gets a random token,
+//! which pages put in a `<meta>` tag and in a hidden `csrf_token` field of
+//! their forms. Anything which changes state then has to send the token back,
+//! either in that form field or in the `X-CSRF-Token` header, which
+//! jquery-ujs does for its ajax requests and `data-method` links on its own.
+use rand::{rngs::OsRng, RngCore};
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::{Cookie, CookieJar, Method, Status},
+    request::{FromRequest, Outcome, Request},
+    Data,
+};
+use thiserror::Error;
+
+/// The private cookie holding the session's token.
+const CSRF_COOKIE: &str = "csrf_token";
+
+/// The form field that forms send the token back in.
+const CSRF_FIELD: &str = "csrf_token";
+
+/// The header that ajax requests send the token back in.
+const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// How far into a form body to look for the token. Forms must put their token
+/// field first to be sure it is found.
+const CSRF_PEEK_BYTES: usize = 512;
+
+/// Gets the token for the session making this request, starting a new one if
+/// there isn't one yet.
+pub fn csrf_token_from_request(req: &Request<'_>) -> String {
+    let cookies = req.cookies();
+
+    if let Some(cookie) = cookies.get_private(CSRF_COOKIE) {
+        return cookie.value().to_string();
+    }
+
+    let mut token = [0u8; 32];
+    OsRng.fill_bytes(&mut token);
+    let token = hex::encode(token);
+    cookies.add_private(Cookie::new(CSRF_COOKIE, token.clone()));
+
+    token
+}
+
+/// Throws away the session's token, so that a new one is made the next time a
+/// page is rendered. This should happen whenever someone logs in or out.
+pub fn reset_csrf_token(cookies: &CookieJar<'_>) {
+    cookies.remove_private(Cookie::named(CSRF_COOKIE));
+}
+
+/// The token found in the body of a form, stashed away by [`CsrfFairing`] for
+/// [`CsrfVerified`] to check, since request guards can't see the body.
+struct SubmittedCsrfToken(Option<String>);
+
+/// Fairing which looks for the token in the body of submitted forms before
+/// the body is handed off to the route.
+pub struct CsrfFairing;
+
+#[rocket::async_trait]
+impl Fairing for CsrfFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "CSRF token",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, data: &mut Data) {
+        let is_form = req.content_type().map_or(false, |ct| ct.is_form());
+
+        if req.method() == Method::Get || !is_form {
+            return;
+        }
+
+        let body = data.peek(CSRF_PEEK_BYTES).await;
+        let token = find_form_token(&String::from_utf8_lossy(body));
+        req.local_cache(|| SubmittedCsrfToken(token));
+    }
+}
+
+/// Request guard which only succeeds when the request carries the session's
+/// token. Every route that changes anything must have one of these.
+pub struct CsrfVerified;
+
+#[derive(Debug, Error)]
+pub enum CsrfError {
+    #[error("The session has no CSRF token")]
+    NoSessionToken,
+
+    #[error("The request has no CSRF token")]
+    Missing,
+
+    #[error("The request's CSRF token does not match the session's")]
+    Mismatch,
+}
+
+#[rocket::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for CsrfVerified {
+    type Error = CsrfError;
+
+    async fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let expected = match req.cookies().get_private(CSRF_COOKIE) {
+            Some(cookie) => cookie.value().to_string(),
+            None => {
+                return Outcome::Failure((
+                    Status::Forbidden,
+                    CsrfError::NoSessionToken,
+                ))
+            }
+        };
+
+        let submitted = match req.headers().get_one(CSRF_HEADER) {
+            Some(token) => Some(token),
+            None => req.local_cache(|| SubmittedCsrfToken(None)).0.as_deref(),
+        };
+
+        match submitted {
+            Some(token) if tokens_match(&expected, token) => {
+                Outcome::Success(CsrfVerified)
+            }
+            Some(_) => {
+                Outcome::Failure((Status::Forbidden, CsrfError::Mismatch))
+            }
+            None => Outcome::Failure((Status::Forbidden, CsrfError::Missing)),
+        }
+    }
+}
+
+/// Finds the token in a urlencoded form body. The body may have been cut off
+/// partway through, which at worst leaves the token cut short too.
+fn find_form_token(body: &str) -> Option<String> {
+    body.split('&').find_map(|pair| {
+        let mut parts = pair.splitn(2, '=');
+
+        match (parts.next(), parts.next()) {
+            (Some(CSRF_FIELD), Some(value)) => Some(value.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// Compares tokens without bailing out at the first difference, so that how
+/// long a comparison takes gives nothing away.
+fn tokens_match(expected: &str, submitted: &str) -> bool {
+    expected.len() == submitted.len()
+        && expected
+            .bytes()
+            .zip(submitted.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_form_token() {
+        assert_eq!(
+            Some("abc123".to_string()),
+            find_form_token("_method=delete&csrf_token=abc123&title=hi")
+        );
+        assert_eq!(None, find_form_token("title=hi&not_csrf_token=abc123"));
+        assert_eq!(None, find_form_token(""));
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("abc123", "abc123"));
+        assert!(!tokens_match("abc123", "abc124"));
+        assert!(!tokens_match("abc123", "abc12"));
+        assert!(!tokens_match("abc123", ""));
+    }
+}
@@ -1,8 +1,16 @@
 use crate::db::DbConn;
-use crate::models::{Jam, ModelError};
+use crate::models::{ApprovalState, Jam, JamPhase, ModelError};
 use crate::template_helpers::attachment_context::AttachmentContext;
+use chrono::{NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
 use serde::Serialize;
 
+/// How jam times are shown to people.
+const DISPLAY_FORMAT: &str = "%Y-%m-%d %H:%M %Z";
+
+/// How jam times go in and out of a `datetime-local` input.
+pub const JAM_TIME_INPUT_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
 /// Describes what a [`crate::models::jams::Jam`] is to a Tera Template context.
 #[derive(Debug, Serialize)]
 pub struct JamContext {
@@ -11,26 +19,71 @@ pub struct JamContext {
     slug: String,
     summary: String,
     summary_attachment: Option<AttachmentContext>,
+    rich_text_id: i32,
     rich_text_content: String,
     rendered_rich_text_content: Option<String>,
+    /// The dates in the viewer's timezone, or the organizer's until the
+    /// viewer picks one.
     start_date: String,
     end_date: String,
+    voting_end_date: Option<String>,
+    /// The dates in the organizer's timezone, for the edit form.
+    start_date_input: String,
+    end_date_input: String,
+    voting_end_date_input: Option<String>,
+    /// The IANA name of the organizer's timezone.
+    timezone: String,
+    /// The IANA name of the timezone the dates are shown in.
+    display_timezone: String,
+    /// Time left until the jam's next milestone, if it has one coming up.
+    countdown: Option<CountdownContext>,
+    /// Where the jam is in its life, as its dates have it right now.
+    phase: String,
+    /// Whether entries may be made or changed right now, for anyone who isn't
+    /// an admin.
+    accepts_entries: bool,
+    /// Whether entries may have updates posted to them right now.
+    accepts_updates: bool,
+    /// Whether entries may be voted on right now.
+    accepts_votes: bool,
+    /// Whether voting is over, and how entries were rated can be shown.
+    shows_vote_results: bool,
     approval_state: String,
+    rejection_reason: Option<String>,
+}
+
+/// Counts down to the next thing to happen in a jam, such as its end.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct CountdownContext {
+    /// What happens when the countdown runs out, such as "Ends".
+    label: &'static str,
+    /// When it runs out as an RFC 3339 instant, for scripts to keep counting.
+    until: String,
+    /// Whole seconds left as of when the page was made.
+    seconds: i64,
+    /// The time left for people, such as "3d 4h 12m".
+    remaining: String,
 }
 
 impl JamContext {
     /// Populates a [`JamContext`] from a database model, pulling other related
     /// values from the database at `conn`. Optionally renders Markdown, so it
-    /// can be omitted if not used in the page.
+    /// can be omitted if not used in the page. Dates are shown in
+    /// `viewer_timezone` when there is one, and in the organizer's otherwise.
     pub fn from_model(
         conn: &DbConn,
         jam: &Jam,
         render_markdown: bool,
+        viewer_timezone: Option<Tz>,
     ) -> Result<Self, ModelError> {
         let attachment = jam.load_attachment(conn)?;
+        let now = chrono::offset::Utc::now().naive_utc();
+        let phase = jam.phase_at(now);
+        let organizer_timezone = jam.organizer_timezone();
+        let display_timezone = viewer_timezone.unwrap_or(organizer_timezone);
         let rich_text = jam.load_rich_text(conn)?;
         let rendered_rich_text_content = if render_markdown {
-            Some(rich_text.render())
+            Some(rich_text.render(conn)?)
         } else {
             None
         };
@@ -42,11 +95,170 @@ impl JamContext {
             summary: jam.summary.clone(),
             summary_attachment: attachment
                 .map(|a| AttachmentContext::from_model(&a)),
+            rich_text_id: rich_text.id,
             rich_text_content: rich_text.content.clone(),
             rendered_rich_text_content: rendered_rich_text_content,
-            start_date: jam.start_date.format("%Y-%m-%d").to_string(),
-            end_date: jam.end_date.format("%Y-%m-%d").to_string(),
+            start_date: format_in(
+                display_timezone,
+                jam.start_date,
+                DISPLAY_FORMAT,
+            ),
+            end_date: format_in(display_timezone, jam.end_date, DISPLAY_FORMAT),
+            voting_end_date: jam
+                .voting_end_date
+                .map(|d| format_in(display_timezone, d, DISPLAY_FORMAT)),
+            start_date_input: format_in(
+                organizer_timezone,
+                jam.start_date,
+                JAM_TIME_INPUT_FORMAT,
+            ),
+            end_date_input: format_in(
+                organizer_timezone,
+                jam.end_date,
+                JAM_TIME_INPUT_FORMAT,
+            ),
+            voting_end_date_input: jam.voting_end_date.map(|d| {
+                format_in(organizer_timezone, d, JAM_TIME_INPUT_FORMAT)
+            }),
+            timezone: organizer_timezone.name().to_string(),
+            display_timezone: display_timezone.name().to_string(),
+            countdown: countdown(jam, phase, now),
+            phase: phase.to_human_str(),
+            accepts_entries: phase.accepts_entries(),
+            accepts_updates: phase.accepts_updates(),
+            accepts_votes: phase.accepts_votes(),
+            shows_vote_results: phase.shows_vote_results(),
             approval_state: jam.approval_state.to_human_str(),
+            rejection_reason: rejection_reason(
+                jam.approval_state,
+                &jam.rejection_reason,
+            ),
         })
     }
 }
+
+/// Formats a UTC instant as it would be said in `timezone`.
+fn format_in(timezone: Tz, instant: NaiveDateTime, format: &str) -> String {
+    timezone
+        .from_utc_datetime(&instant)
+        .format(format)
+        .to_string()
+}
+
+/// Counts down to whatever the jam has coming next: its start, its end, or the
+/// end of voting. Jams which are all done have nothing to count down to.
+fn countdown(
+    jam: &Jam,
+    phase: JamPhase,
+    now: NaiveDateTime,
+) -> Option<CountdownContext> {
+    let (label, until) = match phase {
+        JamPhase::Upcoming => ("Starts", jam.start_date),
+        JamPhase::Running => ("Ends", jam.end_date),
+        JamPhase::Voting => ("Voting ends", jam.voting_end_date?),
+        JamPhase::SubmissionsClosed | JamPhase::Results => return None,
+    };
+    let seconds = (until - now).num_seconds();
+
+    Some(CountdownContext {
+        label,
+        until: chrono::Utc.from_utc_datetime(&until).to_rfc3339(),
+        seconds,
+        remaining: format_remaining(seconds),
+    })
+}
+
+/// Says how long is left in days, hours and minutes, leaving off the days and
+/// hours when there are none.
+fn format_remaining(seconds: i64) -> String {
+    let minutes = seconds.max(0) / 60;
+    let (days, hours, minutes) =
+        (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// The reason a moderator gave for rejecting something, so long as it is still
+/// rejected. A reason left over from an earlier rejection means nothing once
+/// the content has been resubmitted.
+pub(super) fn rejection_reason(
+    approval_state: ApprovalState,
+    reason: &Option<String>,
+) -> Option<String> {
+    match approval_state {
+        ApprovalState::Rejected => reason.clone(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn jam() -> Jam {
+        Jam {
+            id: 1,
+            title: "My Jam".to_string(),
+            slug: "my-jam".to_string(),
+            summary: "".to_string(),
+            summary_attachment_id: None,
+            rich_text_id: 1,
+            start_date: NaiveDate::from_ymd(2021, 1, 10).and_hms(8, 0, 0),
+            end_date: NaiveDate::from_ymd(2021, 1, 12).and_hms(7, 59, 0),
+            approval_state: ApprovalState::Approved,
+            rejection_reason: None,
+            voting_end_date: None,
+            timezone: "America/Los_Angeles".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_format_in() {
+        let j = jam();
+
+        assert_eq!(
+            "2021-01-11 23:59 PST",
+            format_in(j.organizer_timezone(), j.end_date, DISPLAY_FORMAT)
+        );
+        assert_eq!(
+            "2021-01-12T07:59",
+            format_in(Tz::UTC, j.end_date, JAM_TIME_INPUT_FORMAT)
+        );
+    }
+
+    #[test]
+    fn test_countdown() {
+        let j = jam();
+        let now = NaiveDate::from_ymd(2021, 1, 10).and_hms(12, 30, 0);
+
+        assert_eq!(
+            Some(CountdownContext {
+                label: "Ends",
+                until: "2021-01-12T07:59:00+00:00".to_string(),
+                seconds: 156_540,
+                remaining: "1d 19h 29m".to_string(),
+            }),
+            countdown(&j, JamPhase::Running, now)
+        );
+        assert_eq!(None, countdown(&j, JamPhase::SubmissionsClosed, now));
+        assert_eq!(None, countdown(&j, JamPhase::Voting, now));
+    }
+
+    #[test]
+    fn test_format_remaining() {
+        assert_eq!("0m", format_remaining(-5));
+        assert_eq!("59m", format_remaining(59 * 60 + 59));
+        assert_eq!("2h 0m", format_remaining(2 * 60 * 60));
+        assert_eq!(
+            "3d 4h 12m",
+            format_remaining(((3 * 24 + 4) * 60 + 12) * 60)
+        );
+    }
+}
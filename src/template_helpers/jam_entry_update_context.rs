@@ -0,0 +1,59 @@
+use crate::db::DbConn;
+use crate::models::{JamEntryUpdate, ModelError};
+use crate::template_helpers::jam_context::rejection_reason;
+use serde::Serialize;
+
+/// Describes what a [`crate::models::jam_entry_updates::JamEntryUpdate`] is to
+/// a Tera Template context. Exactly one of `rich_text_content` and
+/// `external_content_url` is present.
+#[derive(Debug, Serialize)]
+pub struct JamEntryUpdateContext {
+    id: i32,
+    jam_entry_id: i32,
+    title: String,
+    slug: String,
+    summary: String,
+    rich_text_content: Option<String>,
+    rendered_rich_text_content: Option<String>,
+    external_content_url: Option<String>,
+    approval_state: String,
+    rejection_reason: Option<String>,
+    created_at: String,
+}
+
+impl JamEntryUpdateContext {
+    /// Populates a [`JamEntryUpdateContext`] from a database model, pulling
+    /// other related values from the database at `conn`. Optionally renders
+    /// Markdown, so it can be omitted if not used in the page.
+    pub fn from_model(
+        conn: &DbConn,
+        jam_entry_update: &JamEntryUpdate,
+        render_markdown: bool,
+    ) -> Result<Self, ModelError> {
+        let rich_text = jam_entry_update.load_rich_text(conn)?;
+        let rendered_rich_text_content = match &rich_text {
+            Some(rich_text) if render_markdown => Some(rich_text.render(conn)?),
+            _ => None,
+        };
+
+        Ok(JamEntryUpdateContext {
+            id: jam_entry_update.id,
+            jam_entry_id: jam_entry_update.jam_entry_id,
+            title: jam_entry_update.title.clone(),
+            slug: jam_entry_update.slug.clone(),
+            summary: jam_entry_update.summary.clone(),
+            rich_text_content: rich_text.map(|r| r.content),
+            rendered_rich_text_content,
+            external_content_url: jam_entry_update.external_content_url.clone(),
+            approval_state: jam_entry_update.approval_state.to_human_str(),
+            rejection_reason: rejection_reason(
+                jam_entry_update.approval_state,
+                &jam_entry_update.rejection_reason,
+            ),
+            created_at: jam_entry_update
+                .created_at
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+        })
+    }
+}
@@ -1,21 +1,38 @@
-mod admin_only;
+mod approval_transition_context;
 mod attachment_context;
 mod breadcrumbs;
+mod csrf;
 mod jam_context;
+mod jam_entry_context;
+mod jam_entry_update_context;
+mod pagination;
+mod require_permission;
+mod rich_text_revision_context;
 mod user_optional;
+mod user_required;
+mod viewer_timezone;
+mod vote_category_context;
 
-use std::num::ParseIntError;
-
-use rocket::Request;
+use rocket::{
+    http::{Cookie, Status},
+    request::Outcome,
+    Request,
+};
 use serde::Serialize;
 
 pub use crate::template_helpers::{
-    admin_only::*, attachment_context::*, breadcrumbs::*, jam_context::*,
-    user_optional::*,
+    approval_transition_context::*, attachment_context::*, breadcrumbs::*,
+    csrf::*, jam_context::*, jam_entry_context::*, jam_entry_update_context::*,
+    pagination::*, require_permission::*, rich_text_revision_context::*,
+    user_optional::*, user_required::*, viewer_timezone::*,
+    vote_category_context::*,
 };
 use crate::{
-    db::DbPool,
-    models::{GhUserRecord, ModelError, Permission},
+    db::{DbConn, DbPool},
+    models::{
+        ApprovalState, GhUserRecord, Jam, JamEntry, JamEntryUpdate, ModelError,
+        Permission, PermissionKind, Session,
+    },
 };
 use thiserror::Error;
 
@@ -37,51 +54,208 @@ struct TemplateContextUser {
     permissions: Vec<String>,
 }
 
+/// Why a request guard turned a request away. The guards share this, and
+/// differ only in which of these they fail with.
 #[derive(Debug, Error)]
 pub enum AuthFromRequestError {
+    #[error("No user is logged in")]
+    NotLoggedIn,
+
+    #[error("The user is banned")]
+    Banned,
+
+    #[error("The user does not have the {0} permission")]
+    MissingPermission(PermissionKind),
+
     #[error("Could not get a connection from the pool with error {0}")]
     DbPoolError(#[from] diesel::r2d2::PoolError),
 
-    #[error("Could not parse uid from cookie with error {0}")]
-    UserIdDecodeError(#[from] ParseIntError),
-
     #[error("Could not query the database with error {0}")]
     DbQueryError(#[from] ModelError),
 }
 
+/// This is the context that goes to the template itself for pages which always
+/// have a logged in user. This should always be in the `auth` field of a
+/// template context.
+#[derive(Debug, Serialize)]
+pub struct AuthContext {
+    /// The user.
+    user: TemplateContextUser,
+
+    /// The session's token, for the hidden field of forms.
+    csrf_token: String,
+
+    /// How much is waiting for a moderator, shown in the navbar to those who
+    /// can moderate.
+    moderation_queue_length: Option<i64>,
+}
+
+/// The private cookie holding the token of the browser's
+/// [`crate::models::Session`].
+pub const SESSION_COOKIE: &str = "session_token";
+
+/// Who is making a request, as worked out from their session. The request
+/// guards for logged in users each wrap one of these.
+struct Auth {
+    user: GhUserRecord,
+    permissions: Vec<PermissionKind>,
+    /// The session's token for protecting forms from forgery.
+    csrf_token: String,
+    /// How much submitted content is waiting for a moderator, for users who
+    /// can moderate it.
+    moderation_queue_length: Option<i64>,
+}
+
+impl Auth {
+    fn is_banned(&self) -> bool {
+        PermissionKind::Banned.is_held_by(&self.permissions)
+    }
+
+    /// Whether the user is an admin, which a banned admin is not.
+    fn is_admin(&self) -> bool {
+        !self.is_banned() && PermissionKind::Admin.is_held_by(&self.permissions)
+    }
+
+    /// Whether the user may approve and reject what others have submitted.
+    fn can_moderate(&self) -> bool {
+        can_moderate(&self.permissions)
+    }
+
+    fn user_context(&self) -> TemplateContextUser {
+        TemplateContextUser {
+            id: self.user.id,
+            login: self.user.login.clone(),
+            html_url: self.user.html_url.clone(),
+            avatar_url: self.user.avatar_url.clone(),
+            permissions: permission_names(&self.permissions),
+        }
+    }
+
+    fn to_context(&self) -> AuthContext {
+        AuthContext {
+            user: self.user_context(),
+            csrf_token: self.csrf_token.clone(),
+            moderation_queue_length: self.moderation_queue_length,
+        }
+    }
+}
+
 fn auth_from_request<'a, 'r>(
     req: &'a Request<'r>,
-) -> Result<Option<(GhUserRecord, Vec<String>)>, AuthFromRequestError> {
+) -> Result<Option<Auth>, AuthFromRequestError> {
     // unwrap is okay here, if there's no pool then the entire application
     // bootstrap was wrong
     let pool = req.managed_state::<DbPool>().unwrap();
     let conn = pool.get()?;
 
-    // pull the user out of the cookie, if it's there
+    // pull the session out of the cookie, if it's there
     let cookies = req.cookies();
-    let user_id = cookies.get_private("gh_user_id");
-
-    match user_id {
-        Some(cookie) => {
-            let value = cookie.value();
-            let uid = str::parse::<i64>(value)?;
-            let user = match GhUserRecord::find_by_id(&conn, uid)? {
-                Some(user) => user,
-                None => {
-                    // remove the nonexistent user from the cookie, effectively
-                    // logging out the user
-                    cookies.remove_private(cookie);
-                    return Ok(None);
-                }
-            };
-
-            let permissions = Permission::find_by_gh_user_id(&conn, uid)?
-                .iter()
-                .map(|p| p.name.clone())
-                .collect();
-
-            return Ok(Some((user, permissions)));
-        }
+    let token = match cookies.get_private(SESSION_COOKIE) {
+        Some(cookie) => cookie.value().to_string(),
         None => return Ok(None),
     };
+
+    let session = match Session::find_by_token(&conn, &token)? {
+        Some(session) => session,
+        None => {
+            // the session was revoked or has expired, so forget about it,
+            // effectively logging out the user
+            cookies.remove_private(Cookie::named(SESSION_COOKIE));
+            return Ok(None);
+        }
+    };
+    session.touch(&conn)?;
+
+    let user = match GhUserRecord::find_by_id(&conn, session.gh_user_id)? {
+        Some(user) => user,
+        None => {
+            cookies.remove_private(Cookie::named(SESSION_COOKIE));
+            return Ok(None);
+        }
+    };
+
+    // names which aren't known permissions don't grant anything
+    let permissions: Vec<PermissionKind> =
+        Permission::find_by_gh_user_id(&conn, user.id)?
+            .iter()
+            .filter_map(Permission::kind)
+            .collect();
+
+    let moderation_queue_length = if can_moderate(&permissions) {
+        Some(moderation_queue_length(&conn)?)
+    } else {
+        None
+    };
+
+    Ok(Some(Auth {
+        user,
+        permissions,
+        csrf_token: csrf_token_from_request(req),
+        moderation_queue_length,
+    }))
+}
+
+/// Works out who is making a request for a guard which needs them logged in.
+/// Banned users are turned away, and a request with nobody logged in fails
+/// with `not_logged_in`.
+fn require_auth(
+    req: &Request<'_>,
+    not_logged_in: Status,
+) -> Outcome<Auth, AuthFromRequestError> {
+    match auth_from_request(req) {
+        Ok(Some(auth)) if auth.is_banned() => {
+            turn_away_banned(req);
+            Outcome::Failure((Status::Forbidden, AuthFromRequestError::Banned))
+        }
+        Ok(Some(auth)) => Outcome::Success(auth),
+        Ok(None) => {
+            Outcome::Failure((not_logged_in, AuthFromRequestError::NotLoggedIn))
+        }
+        Err(e) => Outcome::Failure(lookup_failure(e)),
+    }
+}
+
+/// How a guard fails when the user couldn't be looked up at all.
+fn lookup_failure(e: AuthFromRequestError) -> (Status, AuthFromRequestError) {
+    match e {
+        AuthFromRequestError::DbPoolError(_) => {
+            (Status::InternalServerError, e)
+        }
+        e => (Status::BadRequest, e),
+    }
+}
+
+/// Whether a user with these permissions may approve and reject what others
+/// have submitted.
+fn can_moderate(permissions: &[PermissionKind]) -> bool {
+    !PermissionKind::Banned.is_held_by(permissions)
+        && PermissionKind::Moderator.is_held_by(permissions)
+}
+
+/// How many jams, entries and updates are waiting for a moderator.
+pub fn moderation_queue_length(conn: &DbConn) -> Result<i64, ModelError> {
+    let submitted = ApprovalState::Submitted;
+
+    Ok(Jam::count_by_approval_state(conn, submitted)?
+        + JamEntry::count_by_approval_state(conn, submitted)?
+        + JamEntryUpdate::count_by_approval_state(conn, submitted)?)
+}
+
+/// The names of permissions, for templates to check with `in`.
+fn permission_names(permissions: &[PermissionKind]) -> Vec<String> {
+    permissions.iter().map(|p| p.name().to_string()).collect()
+}
+
+/// Set on a request whose user was turned away for being banned, so that the
+/// 403 catcher can tell them so rather than leave them guessing.
+struct TurnedAwayBanned(bool);
+
+fn turn_away_banned(req: &Request<'_>) {
+    req.local_cache(|| TurnedAwayBanned(true));
+}
+
+/// Whether a request guard turned this request away because its user is
+/// banned.
+pub fn was_turned_away_banned(req: &Request<'_>) -> bool {
+    req.local_cache(|| TurnedAwayBanned(false)).0
 }
@@ -0,0 +1,89 @@
+use serde::Serialize;
+
+/// Describes where in a paged listing the user is, so that a template can
+/// render previous/next controls and a count of what there is to see. Pages
+/// are numbered from zero, same as the models count them.
+#[derive(Debug, Serialize)]
+pub struct PaginationContext {
+    /// The current page.
+    page: i64,
+
+    /// How many items are on each page.
+    page_size: i64,
+
+    /// How many items there are in total, across all pages.
+    total_items: i64,
+
+    /// How many pages there are in total. Always at least one, so that an
+    /// empty listing still reads as "page 1 of 1."
+    page_count: i64,
+
+    /// The previous page, if there is one.
+    previous_page: Option<i64>,
+
+    /// The next page, if there is one.
+    next_page: Option<i64>,
+}
+
+impl PaginationContext {
+    /// Works out the surrounding pages for `page` in a listing of
+    /// `total_items` split into pages of `page_size`. A page past either end
+    /// of the listing is taken to mean the nearest page there is.
+    pub fn new(page: i64, page_size: i64, total_items: i64) -> Self {
+        let page_count = ((total_items + page_size - 1) / page_size).max(1);
+        let page = page.max(0).min(page_count - 1);
+
+        PaginationContext {
+            page,
+            page_size,
+            total_items,
+            page_count,
+            previous_page: if page > 0 { Some(page - 1) } else { None },
+            next_page: if page + 1 < page_count {
+                Some(page + 1)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// The current page, which is always one that exists.
+    pub fn page(&self) -> i64 {
+        self.page
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::template_helpers::pagination::*;
+
+    #[test]
+    fn test_pagination_bounds() {
+        let empty = PaginationContext::new(0, 10, 0);
+        assert_eq!(1, empty.page_count);
+        assert_eq!(None, empty.previous_page);
+        assert_eq!(None, empty.next_page);
+
+        let middle = PaginationContext::new(1, 10, 25);
+        assert_eq!(3, middle.page_count);
+        assert_eq!(Some(0), middle.previous_page);
+        assert_eq!(Some(2), middle.next_page);
+
+        let last = PaginationContext::new(2, 10, 30);
+        assert_eq!(3, last.page_count);
+        assert_eq!(Some(1), last.previous_page);
+        assert_eq!(None, last.next_page);
+    }
+
+    #[test]
+    fn test_pagination_clamping() {
+        let past_end = PaginationContext::new(i64::MAX, 10, 25);
+        assert_eq!(2, past_end.page());
+        assert_eq!(Some(1), past_end.previous_page);
+        assert_eq!(None, past_end.next_page);
+
+        let before_start = PaginationContext::new(-3, 10, 25);
+        assert_eq!(0, before_start.page());
+        assert_eq!(None, before_start.previous_page);
+    }
+}
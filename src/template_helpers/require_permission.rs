@@ -0,0 +1,82 @@
+use crate::models::PermissionKind;
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome, Request},
+};
+use std::marker::PhantomData;
+
+use super::{require_auth, Auth, AuthContext, AuthFromRequestError};
+
+/// A permission which a [`RequirePermission`] guard can insist on. Each one is
+/// a type of its own so that routes can say which they need in their
+/// signature, as in `RequirePermission<Admin>`.
+pub trait RequiredPermission: Send + Sync + 'static {
+    /// The permission the user must hold.
+    const KIND: PermissionKind;
+}
+
+/// For pages which cannot be accessed except by an admin.
+pub struct Admin;
+
+impl RequiredPermission for Admin {
+    const KIND: PermissionKind = PermissionKind::Admin;
+}
+
+/// For pages where moderators look after what other users have posted.
+pub struct Moderator;
+
+impl RequiredPermission for Moderator {
+    const KIND: PermissionKind = PermissionKind::Moderator;
+}
+
+/// For pages where jams are run.
+pub struct JamHost;
+
+impl RequiredPermission for JamHost {
+    const KIND: PermissionKind = PermissionKind::JamHost;
+}
+
+/// Request guard for which there must be a logged in user holding the
+/// permission `P`, which admins are taken to hold whatever it is. Banned users
+/// are turned away no matter what else they hold.
+pub struct RequirePermission<P: RequiredPermission> {
+    /// The permitted user.
+    auth: Auth,
+    permission: PhantomData<P>,
+}
+
+impl<P: RequiredPermission> RequirePermission<P> {
+    /// The id of the permitted user.
+    pub fn id(&self) -> i64 {
+        self.auth.user.id
+    }
+
+    /// Produces a serializable context that can be passed to a template.
+    pub fn to_context(&self) -> AuthContext {
+        self.auth.to_context()
+    }
+}
+
+#[rocket::async_trait]
+impl<'a, 'r, P: RequiredPermission> FromRequest<'a, 'r>
+    for RequirePermission<P>
+{
+    type Error = AuthFromRequestError;
+
+    async fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        match require_auth(req, Status::Forbidden) {
+            Outcome::Success(auth)
+                if !P::KIND.is_held_by(&auth.permissions) =>
+            {
+                Outcome::Failure((
+                    Status::Forbidden,
+                    AuthFromRequestError::MissingPermission(P::KIND),
+                ))
+            }
+            outcome => outcome.map(|auth| RequirePermission {
+                auth,
+                permission: PhantomData,
+            }),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::models::{GhUserRecord, RichTextRevision};
+use serde::Serialize;
+
+/// Describes what a [`crate::models::rich_text_revisions::RichTextRevision`]
+/// is to a Tera Template context, including how it differs from the revision
+/// before it. The revisions are loaded by whoever builds these, so that a list
+/// of them takes one query rather than one per revision.
+#[derive(Debug, Serialize)]
+pub struct RichTextRevisionContext {
+    id: i32,
+    rich_text_id: i32,
+    content: String,
+    author_login: Option<String>,
+    author_html_url: Option<String>,
+    created_at: String,
+    lines_added: usize,
+    lines_removed: usize,
+    diff: Option<Vec<DiffLineContext>>,
+}
+
+/// One line of a diff between two revisions.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct DiffLineContext {
+    /// One of `added`, `removed`, or `unchanged`.
+    kind: &'static str,
+    line: String,
+}
+
+impl RichTextRevisionContext {
+    /// Populates a [`RichTextRevisionContext`] from a database model, counting
+    /// the lines changed since `previous`, the revision before it. The first
+    /// revision is counted against nothing at all. Only the counts are kept,
+    /// which is all a list of revisions shows.
+    pub fn from_model(
+        revision: &RichTextRevision,
+        previous: Option<&RichTextRevision>,
+        author: Option<&GhUserRecord>,
+    ) -> Self {
+        RichTextRevisionContext {
+            diff: None,
+            ..Self::with_diff(revision, previous, author)
+        }
+    }
+
+    /// Like [`RichTextRevisionContext::from_model`], but keeping every line of
+    /// the diff as well, for showing the one revision.
+    pub fn with_diff(
+        revision: &RichTextRevision,
+        previous: Option<&RichTextRevision>,
+        author: Option<&GhUserRecord>,
+    ) -> Self {
+        let previous_content =
+            previous.map(|p| p.content.as_str()).unwrap_or_default();
+        let diff = diff_lines(previous_content, &revision.content);
+
+        RichTextRevisionContext {
+            id: revision.id,
+            rich_text_id: revision.rich_text_id,
+            content: revision.content.clone(),
+            author_login: author.map(|a| a.login.clone()),
+            author_html_url: author.map(|a| a.html_url.clone()),
+            created_at: revision
+                .created_at
+                .format("%Y-%m-%d %H:%M UTC")
+                .to_string(),
+            lines_added: diff.iter().filter(|l| l.kind == "added").count(),
+            lines_removed: diff.iter().filter(|l| l.kind == "removed").count(),
+            diff: Some(diff),
+        }
+    }
+}
+
+/// Works out which lines were added and removed to get from one text to
+/// another.
+fn diff_lines(old: &str, new: &str) -> Vec<DiffLineContext> {
+    diff::lines(old, new)
+        .into_iter()
+        .map(|result| match result {
+            diff::Result::Left(line) => DiffLineContext {
+                kind: "removed",
+                line: line.to_string(),
+            },
+            diff::Result::Both(line, _) => DiffLineContext {
+                kind: "unchanged",
+                line: line.to_string(),
+            },
+            diff::Result::Right(line) => DiffLineContext {
+                kind: "added",
+                line: line.to_string(),
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        let kinds: Vec<(&str, String)> =
+            diff_lines("rule one\nrule two", "rule one\nrule 2\nrule three")
+                .into_iter()
+                .map(|l| (l.kind, l.line))
+                .collect();
+
+        assert_eq!(
+            vec![
+                ("unchanged", "rule one".to_string()),
+                ("removed", "rule two".to_string()),
+                ("added", "rule 2".to_string()),
+                ("added", "rule three".to_string()),
+            ],
+            kinds
+        );
+    }
+
+    #[test]
+    fn test_line_counts_against_previous() {
+        let revision = |id, content: &str| RichTextRevision {
+            id,
+            rich_text_id: 1,
+            content: content.to_string(),
+            author_user_id: None,
+            created_at: chrono::NaiveDate::from_ymd(2020, 12, 1)
+                .and_hms(0, 0, 0),
+        };
+        let first = revision(1, "rule one");
+        let second = revision(2, "rule one\nrule two\nrule three");
+
+        let listed =
+            RichTextRevisionContext::from_model(&second, Some(&first), None);
+        assert_eq!((2, 0), (listed.lines_added, listed.lines_removed));
+        assert_eq!(None, listed.diff);
+
+        let shown = RichTextRevisionContext::with_diff(&first, None, None);
+        assert_eq!((1, 0), (shown.lines_added, shown.lines_removed));
+        assert_eq!(Some(1), shown.diff.map(|d| d.len()));
+    }
+}
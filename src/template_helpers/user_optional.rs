@@ -1,23 +1,24 @@
 //! This request guard is also a template helper because it provides the user
 //! and permissions to a template context.
-use crate::{models::GhUserRecord, template_helpers::TemplateContextUser};
-use rocket::{
-    http::Status,
-    request::{FromRequest, Outcome, Request},
-};
+use crate::template_helpers::TemplateContextUser;
+use rocket::request::{FromRequest, Outcome, Request};
 use serde::Serialize;
 
-use super::{auth_from_request, AuthFromRequestError};
+use super::{
+    auth_from_request, csrf_token_from_request, lookup_failure, Auth,
+    AuthFromRequestError,
+};
 
 /// Request guard for which there may or may not be a logged in user. This is
 /// for pages which can be viewed by anyone but which may change their controls
 /// when viewed by someone who is logged in.
 pub struct UserOptional {
     /// The current user, or is it?
-    user: Option<GhUserRecord>,
+    auth: Option<Auth>,
 
-    /// The permissions the current user has, if any.
-    permissions: Vec<String>,
+    /// The session's token for protecting forms from forgery, which visitors
+    /// who aren't logged in have too.
+    csrf_token: String,
 }
 
 /// This is the context that goes to the template itself. To check for the
@@ -27,31 +28,46 @@ pub struct UserOptional {
 pub struct UserOptionalContext {
     /// The user, or is it?
     user: Option<TemplateContextUser>,
+
+    /// The session's token, for the hidden field of forms.
+    csrf_token: String,
+
+    /// How much is waiting for a moderator, shown in the navbar to those who
+    /// can moderate.
+    moderation_queue_length: Option<i64>,
 }
 
 impl UserOptional {
+    /// The id of the logged in user, if there is one.
+    pub fn id(&self) -> Option<i64> {
+        self.auth.as_ref().map(|a| a.user.id)
+    }
+
     pub fn is_banned(&self) -> bool {
-        self.permissions.contains(&"banned".to_string())
+        self.auth.as_ref().map_or(false, Auth::is_banned)
     }
 
+    /// Whether the user is an admin, which a banned admin is not.
     pub fn is_admin(&self) -> bool {
-        self.permissions.contains(&"admin".to_string())
+        self.auth.as_ref().map_or(false, Auth::is_admin)
+    }
+
+    /// Whether the user may approve and reject what others have submitted,
+    /// and so needs to see it before it's approved.
+    pub fn can_moderate(&self) -> bool {
+        self.auth.as_ref().map_or(false, Auth::can_moderate)
     }
 
     /// Produces a serializable context that can be passed to a template.
     pub fn to_context(&self) -> UserOptionalContext {
-        return UserOptionalContext {
-            user: match &self.user {
-                Some(u) => Some(TemplateContextUser {
-                    id: u.id,
-                    login: u.login.clone(),
-                    html_url: u.html_url.clone(),
-                    avatar_url: u.avatar_url.clone(),
-                    permissions: self.permissions.clone(),
-                }),
-                None => None,
-            },
-        };
+        UserOptionalContext {
+            user: self.auth.as_ref().map(Auth::user_context),
+            csrf_token: self.csrf_token.clone(),
+            moderation_queue_length: self
+                .auth
+                .as_ref()
+                .and_then(|a| a.moderation_queue_length),
+        }
     }
 }
 
@@ -61,25 +77,11 @@ impl<'a, 'r> FromRequest<'a, 'r> for UserOptional {
 
     async fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
         match auth_from_request(req) {
-            Ok(Some((user, permissions))) => Outcome::Success(UserOptional {
-                user: Some(user),
-                permissions,
-            }),
-            Ok(None) => Outcome::Success(UserOptional {
-                user: None,
-                permissions: vec![],
+            Ok(auth) => Outcome::Success(UserOptional {
+                auth,
+                csrf_token: csrf_token_from_request(req),
             }),
-            Err(e) => match e {
-                AuthFromRequestError::DbPoolError(_) => {
-                    Outcome::Failure((Status::InternalServerError, e))
-                }
-                AuthFromRequestError::UserIdDecodeError(_) => {
-                    Outcome::Failure((Status::BadRequest, e))
-                }
-                AuthFromRequestError::DbQueryError(_) => {
-                    Outcome::Failure((Status::BadRequest, e))
-                }
-            },
+            Err(e) => Outcome::Failure(lookup_failure(e)),
         }
     }
 }
@@ -94,7 +96,11 @@ mod tests {
     /// updated.
     #[test]
     fn test_user_optional_template_context() {
-        let none_context = UserOptionalContext { user: None };
+        let none_context = UserOptionalContext {
+            user: None,
+            csrf_token: "".to_string(),
+            moderation_queue_length: None,
+        };
         let some_context = UserOptionalContext {
             user: Some(TemplateContextUser {
                 id: 1,
@@ -103,6 +109,8 @@ mod tests {
                 avatar_url: "".to_string(),
                 permissions: vec!["admin".to_string()],
             }),
+            csrf_token: "".to_string(),
+            moderation_queue_length: Some(0),
         };
         let mut tera = Tera::default();
         tera.add_raw_template(
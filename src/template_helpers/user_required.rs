@@ -0,0 +1,45 @@
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome, Request},
+};
+
+use super::{require_auth, Auth, AuthContext, AuthFromRequestError};
+
+/// Request guard for which there must be a logged in user, but they needn't
+/// have any particular permission. This is for pages where users contribute
+/// something of their own to the site, so banned users are turned away.
+pub struct UserRequired {
+    /// The logged in user.
+    auth: Auth,
+}
+
+impl UserRequired {
+    /// The id of the logged in user.
+    pub fn id(&self) -> i64 {
+        self.auth.user.id
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.auth.is_admin()
+    }
+
+    /// Whether the user may approve and reject what others have submitted.
+    pub fn can_moderate(&self) -> bool {
+        self.auth.can_moderate()
+    }
+
+    /// Produces a serializable context that can be passed to a template.
+    pub fn to_context(&self) -> AuthContext {
+        self.auth.to_context()
+    }
+}
+
+#[rocket::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for UserRequired {
+    type Error = AuthFromRequestError;
+
+    async fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        require_auth(req, Status::Unauthorized)
+            .map(|auth| UserRequired { auth })
+    }
+}
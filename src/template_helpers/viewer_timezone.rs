@@ -0,0 +1,48 @@
+//! Lets visitors see jam times in their own timezone. Whoever is looking picks
+//! a timezone once, which is kept in a cookie, and pages which show times then
+//! convert them. Until they pick, times are shown the way the jam's organizer
+//! would say them.
+use chrono_tz::Tz;
+use rocket::{
+    http::{Cookie, CookieJar},
+    request::{FromRequest, Outcome, Request},
+};
+
+/// The cookie holding the IANA name of the viewer's chosen timezone. It's a
+/// preference rather than a secret, so it isn't private.
+const TIMEZONE_COOKIE: &str = "timezone";
+
+/// Request guard for the timezone the viewer chose, if they chose one. Never
+/// fails, as a missing or garbled cookie just means they haven't chosen.
+pub struct ViewerTimezone(Option<Tz>);
+
+impl ViewerTimezone {
+    /// The viewer's chosen timezone.
+    pub fn get(&self) -> Option<Tz> {
+        self.0
+    }
+
+    /// Remembers the viewer's choice, or forgets it given `None`.
+    pub fn choose(cookies: &CookieJar<'_>, timezone: Option<Tz>) {
+        match timezone {
+            Some(tz) => {
+                cookies.add(Cookie::new(TIMEZONE_COOKIE, tz.name().to_string()))
+            }
+            None => cookies.remove(Cookie::named(TIMEZONE_COOKIE)),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for ViewerTimezone {
+    type Error = ();
+
+    async fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let timezone = req
+            .cookies()
+            .get(TIMEZONE_COOKIE)
+            .and_then(|cookie| cookie.value().parse().ok());
+
+        Outcome::Success(ViewerTimezone(timezone))
+    }
+}
@@ -0,0 +1,74 @@
+use crate::db::DbConn;
+use crate::models::{
+    JamEntryVote, ModelError, VoteCategory, MAX_RATING, MIN_RATING,
+};
+use serde::Serialize;
+
+/// Describes what a [`crate::models::vote_categories::VoteCategory`] is to a
+/// Tera Template context, along with how one entry fared in it.
+#[derive(Debug, Serialize)]
+pub struct VoteCategoryContext {
+    id: i32,
+    name: String,
+    description: String,
+    /// The ratings a voter may pick from, lowest first.
+    ratings: Vec<i32>,
+    /// What the viewer rated the entry in this category, if they did.
+    my_rating: Option<i32>,
+    /// The entry's average rating, to one decimal place, once results are
+    /// shown and someone has voted.
+    average: Option<String>,
+    /// How many people rated the entry, once results are shown.
+    vote_count: Option<usize>,
+}
+
+impl VoteCategoryContext {
+    /// Populates contexts for every category as they apply to an entry. The
+    /// viewer's own ratings are filled in given `voter_user_id`, and everyone's
+    /// are tallied given `with_results`.
+    pub fn for_jam_entry(
+        conn: &DbConn,
+        jam_entry_id: i32,
+        voter_user_id: Option<i64>,
+        with_results: bool,
+    ) -> Result<Vec<Self>, ModelError> {
+        let my_votes = match voter_user_id {
+            Some(user_id) => JamEntryVote::find_by_jam_entry_and_voter(
+                conn,
+                jam_entry_id,
+                user_id,
+            )?,
+            None => Vec::new(),
+        };
+        let tallies = if with_results {
+            JamEntryVote::tally_by_jam_entry_id(conn, jam_entry_id)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(VoteCategory::find_all(conn)?
+            .into_iter()
+            .map(|category| {
+                let tally =
+                    tallies.iter().find(|t| t.vote_category_id == category.id);
+
+                VoteCategoryContext {
+                    id: category.id,
+                    ratings: (MIN_RATING..=MAX_RATING).collect(),
+                    my_rating: my_votes
+                        .iter()
+                        .find(|v| v.vote_category_id == category.id)
+                        .map(|v| v.rating),
+                    average: tally.map(|t| format!("{:.1}", t.average)),
+                    vote_count: if with_results {
+                        Some(tally.map(|t| t.count).unwrap_or(0))
+                    } else {
+                        None
+                    },
+                    name: category.name,
+                    description: category.description,
+                }
+            })
+            .collect())
+    }
+}